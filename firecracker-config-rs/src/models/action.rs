@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(tag = "action_type", rename_all = "PascalCase")]
+/// Variant wrapper containing the real action
+pub enum Action {
+    /// Flushes the metrics to the configured metrics file
+    FlushMetrics,
+    /// Starts the microVM
+    InstanceStart,
+    /// Sends CTRL+ALT+DEL to the guest, only supported on x86_64
+    SendCtrlAltDel,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Action;
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn actions_round_trip() {
+        for (example, action) in [
+            (r#"{"action_type": "FlushMetrics"}"#, Action::FlushMetrics),
+            (r#"{"action_type": "InstanceStart"}"#, Action::InstanceStart),
+            (
+                r#"{"action_type": "SendCtrlAltDel"}"#,
+                Action::SendCtrlAltDel,
+            ),
+        ] {
+            assert_eq!(assert_round_trip::<Action>(example), action);
+        }
+    }
+}
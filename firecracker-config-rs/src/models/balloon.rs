@@ -0,0 +1,135 @@
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Balloon device descriptor
+pub struct Balloon {
+    /// Target balloon size in MiB
+    pub amount_mib: u32,
+    /// Whether the balloon should deflate when the guest has memory pressure
+    pub deflate_on_oom: bool,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Interval in seconds between refreshing statistics. A non-zero value will enable the
+    /// statistics. Defaults to 0.
+    pub stats_polling_interval_s: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Balloon device descriptor, for updating the target size of a running microVM
+pub struct BalloonUpdate {
+    /// Target balloon size in MiB
+    pub amount_mib: u32,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Update the statistics polling interval, with the first statistics update scheduled immediately.
+/// Statistics cannot be turned on/off after boot.
+pub struct BalloonStatsUpdate {
+    /// Interval in seconds between refreshing statistics
+    pub stats_polling_interval_s: u32,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Describes the balloon device statistics
+pub struct BalloonStats {
+    /// Target number of pages the device aims to hold
+    pub target_pages: u32,
+    /// Actual number of pages the device is holding
+    pub actual_pages: u32,
+    /// Target amount of memory (in MiB) the device aims to hold
+    pub target_mib: u32,
+    /// Actual amount of memory (in MiB) the device is holding
+    pub actual_mib: u32,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The amount of memory that has been swapped in (in bytes)
+    pub swap_in: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The amount of memory that has been swapped out to disk (in bytes)
+    pub swap_out: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The number of major page faults that have occurred
+    pub major_faults: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The number of minor page faults that have occurred
+    pub minor_faults: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The amount of memory not being used for any purpose (in bytes)
+    pub free_memory: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The total amount of memory available (in bytes)
+    pub total_memory: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// An estimate of how much memory is available (in bytes) for starting new applications,
+    /// without pushing the system to swap
+    pub available_memory: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The amount of memory, in bytes, that can be quickly reclaimed without additional I/O.
+    /// Typically these pages are used for caching files from disk.
+    pub disk_caches: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The number of successful hugetlb page allocations in the guest
+    pub hugetlb_allocations: Option<u64>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The number of failed hugetlb page allocations in the guest
+    pub hugetlb_failures: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Balloon, BalloonStats, BalloonStatsUpdate, BalloonUpdate};
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn balloon_round_trips() {
+        let balloon = assert_round_trip::<Balloon>(
+            r#"{"amount_mib": 256, "deflate_on_oom": true, "stats_polling_interval_s": 1}"#,
+        );
+        assert_eq!(balloon.stats_polling_interval_s, Some(1));
+
+        assert_round_trip::<BalloonUpdate>(r#"{"amount_mib": 128}"#);
+        assert_round_trip::<BalloonStatsUpdate>(r#"{"stats_polling_interval_s": 5}"#);
+    }
+
+    #[test]
+    fn balloon_stats_round_trip() {
+        let stats = assert_round_trip::<BalloonStats>(
+            r#"{
+                "target_pages": 65536,
+                "actual_pages": 65536,
+                "target_mib": 256,
+                "actual_mib": 256,
+                "swap_in": 0,
+                "swap_out": 0,
+                "major_faults": 112,
+                "minor_faults": 5102,
+                "free_memory": 412246016,
+                "total_memory": 1004269568,
+                "available_memory": 774520832,
+                "disk_caches": 287965184,
+                "hugetlb_allocations": 0,
+                "hugetlb_failures": 0
+            }"#,
+        );
+        assert_eq!(stats.actual_mib, 256);
+
+        // Only the balloon's own sizes are always reported
+        assert_round_trip::<BalloonStats>(
+            r#"{"target_pages": 0, "actual_pages": 0, "target_mib": 0, "actual_mib": 0}"#,
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuidRegister {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug)]
+#[builder(setter(into))]
+/// Modifies the bits of a single CPUID register
+pub struct CpuidRegisterModifier {
+    pub register: CpuidRegister,
+    /// Bits to set, clear or leave alone, like `0bxxxx0001`
+    pub bitmap: String,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug)]
+#[builder(setter(into))]
+/// Modifies the registers of a CPUID leaf & subleaf, x86_64 only
+pub struct CpuidLeafModifier {
+    /// CPUID leaf, like `0x1`
+    pub leaf: String,
+    /// CPUID subleaf, like `0x0`
+    pub subleaf: String,
+    /// KVM CPUID flags
+    pub flags: u32,
+    pub modifiers: Vec<CpuidRegisterModifier>,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug)]
+#[builder(setter(into))]
+/// Modifies the bits of a model specific register on x86_64 or a register on aarch64
+pub struct RegisterModifier {
+    /// Address of the register, like `0x10a`
+    pub addr: String,
+    /// Bits to set, clear or leave alone, like `0bxxxx0001`
+    pub bitmap: String,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug)]
+#[builder(setter(into))]
+/// Modifies the vCPU features KVM initializes vCPUs with, aarch64 only
+pub struct VcpuFeatureModifier {
+    /// Index of the features word
+    pub index: u32,
+    /// Bits to set, clear or leave alone, like `0b1100000`
+    pub bitmap: String,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into), default)]
+/// A custom CPU template, which defines the CPU features exposed to the guest as modifiers of
+/// the host's
+pub struct CpuConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// KVM capabilities to check for, capabilities prefixed with `!` must not be available
+    pub kvm_capabilities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpuid_modifiers: Vec<CpuidLeafModifier>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub msr_modifiers: Vec<RegisterModifier>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reg_modifiers: Vec<RegisterModifier>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vcpu_features: Vec<VcpuFeatureModifier>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CpuConfig, CpuidRegister};
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn x86_64_templates_round_trip() {
+        let config = assert_round_trip::<CpuConfig>(
+            r#"{
+                "kvm_capabilities": ["!56"],
+                "cpuid_modifiers": [
+                    {
+                        "leaf": "0x1",
+                        "subleaf": "0x0",
+                        "flags": 0,
+                        "modifiers": [
+                            {
+                                "register": "eax",
+                                "bitmap": "0bxxxx000000000011xx00011011110010"
+                            }
+                        ]
+                    }
+                ],
+                "msr_modifiers": [
+                    {
+                        "addr": "0x10a",
+                        "bitmap": "0b0000000000000000000000000000000000000000000000000000000000000000"
+                    }
+                ]
+            }"#,
+        );
+        assert_eq!(
+            config.cpuid_modifiers[0].modifiers[0].register,
+            CpuidRegister::Eax
+        );
+    }
+
+    #[test]
+    fn aarch64_templates_round_trip() {
+        assert_round_trip::<CpuConfig>(
+            r#"{
+                "reg_modifiers": [
+                    {
+                        "addr": "0x603000000013c020",
+                        "bitmap": "0bxxxxxxxxxxxx0000xxxxxxxxxxxx0000xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"
+                    }
+                ],
+                "vcpu_features": [{"index": 0, "bitmap": "0b1100000"}]
+            }"#,
+        );
+    }
+}
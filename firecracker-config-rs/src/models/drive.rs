@@ -20,7 +20,7 @@ pub enum IoEngine {
 }
 
 #[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
-#[builder(setter(into))]
+#[builder(setter(into), derive(Debug, Deserialize))]
 /// Represent a block drive
 pub struct Drive {
     /// Identifier of the block device
@@ -50,3 +50,41 @@ pub struct Drive {
     /// Rate limiter for operations on the block drive
     pub rate_limiter: Option<RateLimiter>,
 }
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Updates an existing block drive, only the fields that are set are changed
+pub struct PartialDrive {
+    /// Identifier of the block device
+    pub drive_id: String,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Host level path for the block drive
+    pub path_on_host: Option<PathBuf>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Rate limiter for operations on the block drive
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheType, DriveBuilder};
+
+    #[test]
+    fn builders_deserialize_partial_drives() {
+        let mut builder: DriveBuilder =
+            serde_json::from_str(r#"{"is_read_only": true, "cache_type": "Writeback"}"#).unwrap();
+
+        let drive = builder
+            .drive_id("data")
+            .path_on_host("/drives/data.ext4")
+            .is_root_device(false)
+            .build()
+            .unwrap();
+
+        assert_eq!(drive.is_read_only, Some(true));
+        assert_eq!(drive.cache_type, CacheType::Writeback);
+        assert!(DriveBuilder::default().build().is_err());
+    }
+}
@@ -0,0 +1,30 @@
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use crate::models::rate_limiter::RateLimiter;
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into), default)]
+/// Defines an entropy device
+pub struct EntropyDevice {
+    #[builder(setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Rate limiter for the entropy the guest reads
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EntropyDevice;
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn entropy_device_round_trips() {
+        let device = assert_round_trip::<EntropyDevice>(
+            r#"{"rate_limiter": {"bandwidth": {"size": 1000, "refill_time": 100}}}"#,
+        );
+        assert!(device.rate_limiter.is_some());
+
+        assert_round_trip::<EntropyDevice>("{}");
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+/// The current state of the microVM
+pub enum InstanceState {
+    #[default]
+    #[serde(rename = "Not started")]
+    NotStarted,
+    Running,
+    Paused,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+/// Describes the microVM & the VMM running it
+pub struct InstanceInfo {
+    /// Application name
+    pub app_name: String,
+    /// MicroVM / instance ID
+    pub id: String,
+    /// The current state of the microVM
+    pub state: InstanceState,
+    /// MicroVM hypervisor build version
+    pub vmm_version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+/// Version of the firecracker VMM
+pub struct FirecrackerVersion {
+    /// Firecracker build version
+    pub firecracker_version: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FirecrackerVersion, InstanceInfo, InstanceState};
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn instance_info_round_trips() {
+        let info = assert_round_trip::<InstanceInfo>(
+            r#"{"app_name": "Firecracker", "id": "anonymous-instance", "state": "Not started", "vmm_version": "1.7.0"}"#,
+        );
+        assert_eq!(info.state, InstanceState::NotStarted);
+
+        assert_round_trip::<FirecrackerVersion>(r#"{"firecracker_version": "1.7.0"}"#);
+    }
+}
@@ -15,7 +15,7 @@ pub enum LogLevel {
 }
 
 #[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
-#[builder(setter(into))]
+#[builder(setter(into), derive(Debug, Deserialize))]
 /// Describes the configuration options for the logging capability
 pub struct Logger {
     /// Path to the named pipe or file for human readable log output
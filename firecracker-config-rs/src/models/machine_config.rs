@@ -0,0 +1,75 @@
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+/// Static CPU templates shipped with firecracker
+pub enum CpuTemplate {
+    C3,
+    T2,
+    T2S,
+    T2CL,
+    T2A,
+    V1N1,
+    #[default]
+    None,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+/// Backing pages of the guest memory
+pub enum HugePages {
+    #[default]
+    None,
+    #[serde(rename = "2M")]
+    TwoMiB,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Describes the number of vCPUs, memory size, SMT capabilities and dirty page tracking of the
+/// microVM
+pub struct MachineConfiguration {
+    /// Number of vCPUs, either 1 or an even number when SMT is enabled
+    pub vcpu_count: u8,
+    /// Memory size of the VM in MiB
+    pub mem_size_mib: u32,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Flag for enabling/disabling simultaneous multithreading. Can be enabled only on x86.
+    pub smt: Option<bool>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Enables or disables dirty page tracking. Enabling allows incremental snapshots.
+    pub track_dirty_pages: Option<bool>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Static CPU template, superseded by custom templates set through `/cpu-config`
+    pub cpu_template: Option<CpuTemplate>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Which huge pages configuration (if any) should be used to back guest memory
+    pub huge_pages: Option<HugePages>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CpuTemplate, HugePages, MachineConfiguration};
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn machine_config_round_trips() {
+        let config = assert_round_trip::<MachineConfiguration>(
+            r#"{
+                "vcpu_count": 2,
+                "mem_size_mib": 1024,
+                "smt": false,
+                "track_dirty_pages": false,
+                "cpu_template": "T2CL",
+                "huge_pages": "2M"
+            }"#,
+        );
+        assert_eq!(config.cpu_template, Some(CpuTemplate::T2CL));
+        assert_eq!(config.huge_pages, Some(HugePages::TwoMiB));
+
+        assert_round_trip::<MachineConfiguration>(r#"{"vcpu_count": 1, "mem_size_mib": 128}"#);
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Describes the configuration option for the metrics capability
+pub struct Metrics {
+    /// Path to the named pipe or file where the JSON-formatted metrics are flushed
+    pub metrics_path: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn metrics_round_trip() {
+        assert_round_trip::<Metrics>(r#"{"metrics_path": "/tmp/metrics.fifo"}"#);
+    }
+}
@@ -0,0 +1,44 @@
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum MmdsVersion {
+    #[default]
+    V1,
+    /// Session oriented, guests need a token to read the data store
+    V2,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Defines the MMDS configuration
+pub struct MmdsConfig {
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Enumeration indicating the MMDS version to be configured
+    pub version: Option<MmdsVersion>,
+    /// List of the network interface IDs capable of forwarding packets to the MMDS. Network
+    /// interface IDs mentioned must be valid at the time of this request. The net device model
+    /// will reply to HTTP GET requests sent to the MMDS address via the interfaces mentioned.
+    pub network_interfaces: Vec<String>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// A valid IPv4 link-local address, defaults to 169.254.169.254
+    pub ipv4_address: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MmdsConfig, MmdsVersion};
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn mmds_config_round_trips() {
+        let config = assert_round_trip::<MmdsConfig>(
+            r#"{"version": "V2", "network_interfaces": ["eth0"], "ipv4_address": "169.254.169.254"}"#,
+        );
+        assert_eq!(config.version, Some(MmdsVersion::V2));
+
+        assert_round_trip::<MmdsConfig>(r#"{"network_interfaces": ["eth0"]}"#);
+    }
+}
@@ -1,6 +1,32 @@
+pub mod action;
+pub mod balloon;
 pub mod bootsource;
+pub mod cpu_config;
 pub mod drive;
+pub mod entropy;
+pub mod instance_info;
 pub mod logger;
+pub mod machine_config;
+pub mod metrics;
+pub mod mmds;
 pub mod network_interface;
 pub mod rate_limiter;
+pub mod snapshot;
 pub mod virtual_machine;
+pub mod vm;
+pub mod vsock;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::Value;
+
+    /// Parses an example from the firecracker API spec & checks it serializes back to the same
+    /// JSON, so no field is dropped or renamed on the way
+    pub(crate) fn assert_round_trip<T: Serialize + DeserializeOwned>(example: &str) -> T {
+        let expected: Value = serde_json::from_str(example).expect("the example should be JSON");
+        let model: T = serde_json::from_value(expected.clone()).expect("the example should parse");
+        assert_eq!(serde_json::to_value(&model).unwrap(), expected);
+        model
+    }
+}
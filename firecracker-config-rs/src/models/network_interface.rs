@@ -24,3 +24,19 @@ pub struct NetworkInterface {
     /// Rate limiter for sending packets
     pub tx_rate_limiter: Option<RateLimiter>,
 }
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Updates the rate limiters of an existing network interface
+pub struct PartialNetworkInterface {
+    /// Identifier of the network interface
+    pub iface_id: String,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Rate limiter for receiving packets
+    pub rx_rate_limiter: Option<RateLimiter>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Rate limiter for sending packets
+    pub tx_rate_limiter: Option<RateLimiter>,
+}
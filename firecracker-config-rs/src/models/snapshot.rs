@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SnapshotType {
+    #[default]
+    Full,
+    /// Only the memory pages written since the last snapshot, needs dirty page tracking
+    Diff,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+pub struct SnapshotCreateParams {
+    /// Path to the file that will contain the guest memory
+    pub mem_file_path: PathBuf,
+    /// Path to the file that will contain the microVM state
+    pub snapshot_path: PathBuf,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Type of snapshot to create. It is optional and by default, a full snapshot is created.
+    pub snapshot_type: Option<SnapshotType>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum MemoryBackendType {
+    /// The guest memory is mapped from a file
+    #[default]
+    File,
+    /// Page faults of the guest memory are handled by a userfaultfd handler
+    Uffd,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+pub struct MemoryBackend {
+    pub backend_type: MemoryBackendType,
+    /// Based on 'backend_type' it is either 1) Path to the file that contains the guest memory to
+    /// be loaded 2) Path to the UDS where a process is listening for a UFFD initialization
+    /// control payload and open file descriptor that it can use to serve this process's guest
+    /// memory page faults
+    pub backend_path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Defines the configuration used for handling snapshot resume. Exactly one of the two
+/// `mem_*` fields must be present in the body of the request.
+pub struct SnapshotLoadParams {
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Enable support for incremental (diff) snapshots by tracking dirty guest pages
+    pub enable_diff_snapshots: Option<bool>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Path to the file that contains the guest memory to be loaded. It is only allowed if
+    /// `mem_backend` is not present. This parameter has been deprecated and it will be removed in
+    /// future Firecracker release.
+    pub mem_file_path: Option<PathBuf>,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Configuration for the backend that handles memory load. If this field is specified,
+    /// `mem_file_path` is forbidden. Either `mem_backend` or `mem_file_path` must be present at a
+    /// time.
+    pub mem_backend: Option<MemoryBackend>,
+    /// Path to the file that contains the microVM state to be loaded
+    pub snapshot_path: PathBuf,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// When set to true, the vm is also resumed if the snapshot load is successful
+    pub resume_vm: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryBackendType, SnapshotCreateParams, SnapshotLoadParams, SnapshotType};
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn snapshot_create_params_round_trip() {
+        let params = assert_round_trip::<SnapshotCreateParams>(
+            r#"{"snapshot_type": "Diff", "snapshot_path": "./snapshot_file", "mem_file_path": "./mem_file"}"#,
+        );
+        assert_eq!(params.snapshot_type, Some(SnapshotType::Diff));
+    }
+
+    #[test]
+    fn snapshot_load_params_round_trip() {
+        let params = assert_round_trip::<SnapshotLoadParams>(
+            r#"{
+                "snapshot_path": "./snapshot_file",
+                "mem_backend": {"backend_path": "./mem_file", "backend_type": "Uffd"},
+                "enable_diff_snapshots": true,
+                "resume_vm": false
+            }"#,
+        );
+        assert_eq!(
+            params.mem_backend.unwrap().backend_type,
+            MemoryBackendType::Uffd
+        );
+
+        assert_round_trip::<SnapshotLoadParams>(
+            r#"{"snapshot_path": "./snapshot_file", "mem_file_path": "./mem_file"}"#,
+        );
+    }
+}
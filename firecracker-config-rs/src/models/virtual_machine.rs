@@ -1,13 +1,76 @@
 use super::{
-    bootsource::BootSource, drive::Drive, logger::Logger, network_interface::NetworkInterface,
+    bootsource::BootSource, drive::Drive, logger::Logger, machine_config::MachineConfiguration,
+    network_interface::NetworkInterface, vsock::Vsock,
 };
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
 
-#[derive(Builder, Clone, PartialEq, Debug, Default)]
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
 #[builder(setter(into, strip_option), default)]
+#[serde(rename_all = "kebab-case")]
+/// Configuration of a whole microVM, serializes to the file firecracker boots from when it is
+/// started with `--config-file`
 pub struct VirtualMachine {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub logger: Option<Logger>,
     pub boot_source: BootSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub machine_config: Option<MachineConfiguration>,
     pub drives: Vec<Drive>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub network_interfaces: Vec<NetworkInterface>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vsock: Option<Vsock>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VirtualMachine;
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn config_files_round_trip() {
+        let config = assert_round_trip::<VirtualMachine>(
+            r#"{
+                "boot-source": {
+                    "kernel_image_path": "vmlinux.bin",
+                    "boot_args": "console=ttyS0 reboot=k panic=1 pci=off"
+                },
+                "drives": [
+                    {
+                        "drive_id": "rootfs",
+                        "path_on_host": "bionic.rootfs.ext4",
+                        "is_root_device": true,
+                        "is_read_only": false,
+                        "cache_type": "Unsafe",
+                        "io_engine": "Sync"
+                    }
+                ],
+                "machine-config": {
+                    "vcpu_count": 2,
+                    "mem_size_mib": 1024,
+                    "smt": false
+                },
+                "network-interfaces": [
+                    {
+                        "iface_id": "eth0",
+                        "guest_mac": "06:00:AC:10:00:02",
+                        "host_dev_name": "tap0"
+                    }
+                ],
+                "logger": {
+                    "log_path": "/log/firecracker.log",
+                    "level": "Info",
+                    "show_level": true,
+                    "show_log_origin": true
+                },
+                "vsock": {"guest_cid": 3, "uds_path": "/run/vsock.socket"}
+            }"#,
+        );
+        assert_eq!(config.drives[0].drive_id, "rootfs");
+
+        assert_round_trip::<VirtualMachine>(
+            r#"{"boot-source": {"kernel_image_path": "vmlinux.bin"}, "drives": []}"#,
+        );
+    }
 }
@@ -0,0 +1,34 @@
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum VmState {
+    #[default]
+    Paused,
+    Resumed,
+}
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Defines the microVM running state. It is especially useful in the snapshotting context.
+pub struct Vm {
+    pub state: VmState,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Vm, VmState};
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn vm_state_round_trips() {
+        assert_eq!(
+            assert_round_trip::<Vm>(r#"{"state": "Paused"}"#).state,
+            VmState::Paused
+        );
+        assert_eq!(
+            assert_round_trip::<Vm>(r#"{"state": "Resumed"}"#).state,
+            VmState::Resumed
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Builder, Clone, PartialEq, Debug, Default)]
+#[builder(setter(into))]
+/// Defines a vsock device, backed by a set of Unix Domain Sockets on the host side
+pub struct Vsock {
+    /// Guest vsock CID
+    pub guest_cid: u32,
+    /// Path to the UNIX domain socket used for proxying vsock connections
+    pub uds_path: PathBuf,
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Deprecated identifier of the vsock device
+    pub vsock_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vsock;
+    use crate::models::tests::assert_round_trip;
+
+    #[test]
+    fn vsock_round_trips() {
+        let vsock = assert_round_trip::<Vsock>(r#"{"guest_cid": 3, "uds_path": "./v.sock"}"#);
+        assert_eq!(vsock.guest_cid, 3);
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Component, Path},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    bootsource::BootSource,
+    drive::Drive,
+    logger::Logger,
+    machine_config::MachineConfiguration,
+    network_interface::NetworkInterface,
+    rate_limiter::{RateLimiter, TokenBucket},
+    virtual_machine::VirtualMachine,
+    vsock::Vsock,
+};
+
+/// Most vCPUs firecracker supports
+const MAX_VCPU_COUNT: u8 = 32;
+/// Context ids below this one are reserved for the hypervisor & host
+const MIN_GUEST_CID: u32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+/// A problem with a single field of a configuration
+pub struct ValidationError {
+    /// Path of the field, like `drives[1].drive_id`
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+/// Every problem found in a configuration
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(ValidationError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Fails with the errors, if there are any
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors = self
+            .errors
+            .iter()
+            .map(|error| format!("{}: {}", error.path, error.message))
+            .collect::<Vec<_>>();
+        write!(f, "Invalid configuration: {}", errors.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Checks a configuration before it reaches firecracker. Paths are the ones
+/// firecracker opens in its jail, so they have to be absolute & can't leave
+/// it.
+pub trait Validate {
+    /// Adds the problems of the configuration found at `path` to `errors`
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors);
+
+    /// Every problem of the configuration at once
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        self.validate_at("", &mut errors);
+        errors.into_result()
+    }
+}
+
+/// Path of `name`, a field of the configuration at `path`
+fn field(path: &str, name: &str) -> String {
+    match path {
+        "" => name.to_string(),
+        path => format!("{path}.{name}"),
+    }
+}
+
+fn validate_jailed_path(path: &Path, at: String, errors: &mut ValidationErrors) {
+    if !path.is_absolute() {
+        errors.add(at, format!("{} has to be absolute", path.display()));
+    } else if path.components().any(|c| c == Component::ParentDir) {
+        errors.add(at, format!("{} leaves the chroot", path.display()));
+    }
+}
+
+fn validate_unique<'a>(
+    values: impl IntoIterator<Item = (String, &'a str)>,
+    name: &str,
+    errors: &mut ValidationErrors,
+) {
+    let mut seen = HashSet::new();
+    for (at, value) in values {
+        if !seen.insert(value) {
+            errors.add(at, format!("{name} {value} is used more than once"));
+        }
+    }
+}
+
+fn is_mac_address(mac: &str) -> bool {
+    let octets = mac.split(':').collect::<Vec<_>>();
+    octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl Validate for VirtualMachine {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        if let Some(logger) = &self.logger {
+            logger.validate_at(&field(path, "logger"), errors);
+        }
+        self.boot_source
+            .validate_at(&field(path, "boot_source"), errors);
+        if let Some(machine_config) = &self.machine_config {
+            machine_config.validate_at(&field(path, "machine_config"), errors);
+        }
+
+        let drives = field(path, "drives");
+        for (index, drive) in self.drives.iter().enumerate() {
+            drive.validate_at(&format!("{drives}[{index}]"), errors);
+        }
+        validate_unique(
+            self.drives.iter().enumerate().map(|(index, drive)| {
+                (
+                    format!("{drives}[{index}].drive_id"),
+                    drive.drive_id.as_str(),
+                )
+            }),
+            "Drive id",
+            errors,
+        );
+        let root_devices = self.drives.iter().filter(|d| d.is_root_device).count();
+        if root_devices != 1 {
+            errors.add(
+                drives,
+                format!("Exactly one drive has to be the root device, found {root_devices}"),
+            );
+        }
+
+        let interfaces = field(path, "network_interfaces");
+        for (index, interface) in self.network_interfaces.iter().enumerate() {
+            interface.validate_at(&format!("{interfaces}[{index}]"), errors);
+        }
+        let indexed = || self.network_interfaces.iter().enumerate();
+        validate_unique(
+            indexed().map(|(index, interface)| {
+                (
+                    format!("{interfaces}[{index}].iface_id"),
+                    interface.iface_id.as_str(),
+                )
+            }),
+            "Interface id",
+            errors,
+        );
+        validate_unique(
+            indexed().map(|(index, interface)| {
+                (
+                    format!("{interfaces}[{index}].host_dev_name"),
+                    interface.host_dev_name.as_str(),
+                )
+            }),
+            "Host device",
+            errors,
+        );
+        validate_unique(
+            indexed().filter_map(|(index, interface)| {
+                let mac = interface.guest_mac.as_deref()?;
+                Some((format!("{interfaces}[{index}].guest_mac"), mac))
+            }),
+            "MAC address",
+            errors,
+        );
+
+        if let Some(vsock) = &self.vsock {
+            vsock.validate_at(&field(path, "vsock"), errors);
+        }
+    }
+}
+
+impl Validate for Logger {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        validate_jailed_path(&self.log_path, field(path, "log_path"), errors);
+    }
+}
+
+impl Validate for BootSource {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        validate_jailed_path(
+            &self.kernel_image_path,
+            field(path, "kernel_image_path"),
+            errors,
+        );
+        if let Some(initrd_path) = &self.initrd_path {
+            validate_jailed_path(initrd_path, field(path, "initrd_path"), errors);
+        }
+    }
+}
+
+impl Validate for MachineConfiguration {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        let vcpu_count = self.vcpu_count;
+        if vcpu_count == 0 || vcpu_count > MAX_VCPU_COUNT {
+            errors.add(
+                field(path, "vcpu_count"),
+                format!("Has to be between 1 and {MAX_VCPU_COUNT}"),
+            );
+        } else if self.smt == Some(true) && vcpu_count > 1 && !vcpu_count.is_multiple_of(2) {
+            errors.add(
+                field(path, "vcpu_count"),
+                "Has to be 1 or an even number with SMT enabled",
+            );
+        }
+        if self.mem_size_mib == 0 {
+            errors.add(field(path, "mem_size_mib"), "Has to be positive");
+        }
+    }
+}
+
+impl Validate for Drive {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        if self.drive_id.is_empty() {
+            errors.add(field(path, "drive_id"), "Can't be empty");
+        }
+        validate_jailed_path(&self.path_on_host, field(path, "path_on_host"), errors);
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.validate_at(&field(path, "rate_limiter"), errors);
+        }
+    }
+}
+
+impl Validate for NetworkInterface {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        if self.iface_id.is_empty() {
+            errors.add(field(path, "iface_id"), "Can't be empty");
+        }
+        if self.host_dev_name.is_empty() {
+            errors.add(field(path, "host_dev_name"), "Can't be empty");
+        }
+        if let Some(mac) = &self.guest_mac {
+            if !is_mac_address(mac) {
+                errors.add(
+                    field(path, "guest_mac"),
+                    format!("{mac} is not a MAC address like 06:00:AC:10:00:02"),
+                );
+            }
+        }
+        if let Some(rate_limiter) = &self.rx_rate_limiter {
+            rate_limiter.validate_at(&field(path, "rx_rate_limiter"), errors);
+        }
+        if let Some(rate_limiter) = &self.tx_rate_limiter {
+            rate_limiter.validate_at(&field(path, "tx_rate_limiter"), errors);
+        }
+    }
+}
+
+impl Validate for RateLimiter {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.validate_at(&field(path, "bandwidth"), errors);
+        }
+        if let Some(ops) = &self.ops {
+            ops.validate_at(&field(path, "ops"), errors);
+        }
+    }
+}
+
+impl Validate for TokenBucket {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        if self.refill_time == 0 {
+            errors.add(field(path, "refill_time"), "Has to be positive");
+        }
+    }
+}
+
+impl Validate for Vsock {
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        if self.guest_cid < MIN_GUEST_CID {
+            errors.add(
+                field(path, "guest_cid"),
+                format!("Has to be at least {MIN_GUEST_CID}"),
+            );
+        }
+        validate_jailed_path(&self.uds_path, field(path, "uds_path"), errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Validate, ValidationError};
+    use crate::models::{
+        bootsource::BootSourceBuilder,
+        drive::DriveBuilder,
+        machine_config::MachineConfigurationBuilder,
+        network_interface::NetworkInterfaceBuilder,
+        rate_limiter::{RateLimiter, TokenBucket},
+        virtual_machine::{VirtualMachine, VirtualMachineBuilder},
+    };
+
+    fn virtual_machine() -> VirtualMachine {
+        VirtualMachineBuilder::default()
+            .boot_source(
+                BootSourceBuilder::default()
+                    .kernel_image_path("/kernel.bin")
+                    .build()
+                    .unwrap(),
+            )
+            .machine_config(
+                MachineConfigurationBuilder::default()
+                    .vcpu_count(2)
+                    .mem_size_mib(512u32)
+                    .build()
+                    .unwrap(),
+            )
+            .drives(vec![
+                DriveBuilder::default()
+                    .drive_id("rootfs")
+                    .path_on_host("/drives/rootfs.ext4")
+                    .is_root_device(true)
+                    .build()
+                    .unwrap(),
+                DriveBuilder::default()
+                    .drive_id("vdb")
+                    .path_on_host("/drives/code-drive.ext4")
+                    .is_root_device(false)
+                    .build()
+                    .unwrap(),
+            ])
+            .network_interfaces(vec![NetworkInterfaceBuilder::default()
+                .host_dev_name("tap0")
+                .iface_id("eth0")
+                .guest_mac("06:00:AC:10:00:02")
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn valid_configs_pass() {
+        assert_eq!(virtual_machine().validate(), Ok(()));
+    }
+
+    #[test]
+    fn odd_vcpu_counts_need_smt_disabled() {
+        let mut config = virtual_machine();
+        config.machine_config.as_mut().unwrap().vcpu_count = 3;
+        assert_eq!(config.validate(), Ok(()));
+
+        config.machine_config.as_mut().unwrap().smt = Some(false);
+        assert_eq!(config.validate(), Ok(()));
+
+        config.machine_config.as_mut().unwrap().smt = Some(true);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn every_problem_is_reported_with_its_path() {
+        let mut config = virtual_machine();
+        config.boot_source.kernel_image_path = "../kernel.bin".into();
+        let machine_config = config.machine_config.as_mut().unwrap();
+        machine_config.vcpu_count = 3;
+        machine_config.smt = Some(true);
+        config.drives[1].drive_id = "rootfs".to_string();
+        config.drives[1].is_root_device = true;
+        config.drives[1].path_on_host = "/drives/../../etc/shadow".into();
+        config.network_interfaces[0].guest_mac = Some("06:00:AC:10:00".to_string());
+        config.network_interfaces[0].tx_rate_limiter = Some(RateLimiter {
+            bandwidth: Some(TokenBucket {
+                size: 1024,
+                refill_time: 0,
+                one_time_burst: None,
+            }),
+            ops: None,
+        });
+
+        let errors = config.validate().unwrap_err();
+
+        let paths = errors
+            .errors
+            .iter()
+            .map(|ValidationError { path, .. }| path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "boot_source.kernel_image_path",
+                "machine_config.vcpu_count",
+                "drives[1].path_on_host",
+                "drives[1].drive_id",
+                "drives",
+                "network_interfaces[0].guest_mac",
+                "network_interfaces[0].tx_rate_limiter.bandwidth.refill_time",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_root_device_is_required() {
+        let mut config = virtual_machine();
+        config.drives.remove(0);
+
+        let errors = config.validate().unwrap_err();
+
+        assert_eq!(
+            errors.to_string(),
+            "Invalid configuration: drives: Exactly one drive has to be the root device, found 0"
+        );
+    }
+}
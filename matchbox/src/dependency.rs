@@ -1,31 +1,50 @@
 use std::{path::PathBuf, sync::Arc};
 
 use crate::{
-    jailer::factory::{JailedFirecrackerFactory, ProvideFirecracker},
+    jailer::{
+        direct::DirectFirecrackerFactory,
+        factory::{JailedFirecrackerFactory, ProvideFirecracker},
+    },
     sandbox::{
         id::{ProvideIdentifier, VmIdentifierFactory},
+        network::factory::{DisabledNetworkFactory, NetnsNetworkFactory, ProvideNetwork},
         spark::factory::{ProvideSparkClient, SparkClientFactory},
+        usage::{RecordUsage, StdoutUsageRecorder},
         InitializeSandbox, ProvideSandbox, SandboxFactory, SandboxInitializer,
     },
+    server::config::{FirecrackerMode, ServerConfig},
 };
 
+const ROOTFS_PATH: &str = "/tmp/rootfs.ext4";
+const KERNEL_IMAGE_PATH: &str = "/tmp/kernel.bin";
+
 pub struct DependencyFactory {
     firecracker_provider: Arc<Box<dyn ProvideFirecracker>>,
+    network_provider: Arc<Box<dyn ProvideNetwork>>,
     sandbox_initialixer: Arc<Box<dyn InitializeSandbox>>,
     identifier_provider: Arc<Box<dyn ProvideIdentifier>>,
     spark_client_provider: Arc<Box<dyn ProvideSparkClient>>,
     dummy_drive_path: PathBuf,
+    kernel_image_path: PathBuf,
+    usage_recorder: Arc<Box<dyn RecordUsage>>,
 }
 
 impl DependencyFactory {
     pub fn sandbox_provider(&self) -> Box<dyn ProvideSandbox + Send + Sync> {
+        assert!(
+            self.dummy_drive_path.exists(),
+            "The dummy drive path should exist"
+        );
         let sandbox_provider = SandboxFactory::new(
             self.identifier_provider.clone(),
             self.spark_client_provider.clone(),
             self.firecracker_provider.clone(),
+            self.network_provider.clone(),
             self.sandbox_initialixer.clone(),
             self.dummy_drive_path.clone(),
-        );
+            self.kernel_image_path.clone(),
+        )
+        .with_usage_recorder(self.usage_recorder.clone());
         Box::new(sandbox_provider)
     }
 
@@ -39,6 +58,43 @@ impl DependencyFactory {
         }
     }
 
+    pub fn with_network_provider(self, network_provider: Arc<Box<dyn ProvideNetwork>>) -> Self {
+        Self {
+            network_provider,
+            ..self
+        }
+    }
+
+    /// Runs & boots firecracker the way the server is configured to. Direct
+    /// mode runs without root, so its sandboxes can't have a network.
+    pub fn with_server_config(self, config: &ServerConfig) -> Self {
+        let (firecracker_provider, network_provider): (
+            Box<dyn ProvideFirecracker>,
+            Box<dyn ProvideNetwork>,
+        ) = match config.firecracker {
+            FirecrackerMode::Jailed => {
+                let mut factory =
+                    JailedFirecrackerFactory::default().with_hardening(config.jailer.clone());
+                if let Some(parent_cgroup) = &config.parent_cgroup {
+                    factory = factory.with_parent_cgroup(parent_cgroup);
+                }
+                (Box::new(factory), Box::<NetnsNetworkFactory>::default())
+            }
+            FirecrackerMode::Direct => (
+                Box::new(DirectFirecrackerFactory::from(&config.direct)),
+                Box::<DisabledNetworkFactory>::default(),
+            ),
+        };
+
+        let sandbox_initializer: Box<dyn InitializeSandbox> = Box::new(
+            SandboxInitializer::new(ROOTFS_PATH, &self.kernel_image_path).with_boot(config.boot),
+        );
+
+        self.with_firecracker_provider(Arc::from(firecracker_provider))
+            .with_network_provider(Arc::from(network_provider))
+            .with_sandbox_initializer(Arc::from(sandbox_initializer))
+    }
+
     pub fn with_spark_client_provider(
         self,
         spark_client_provider: Arc<Box<dyn ProvideSparkClient>>,
@@ -59,6 +115,29 @@ impl DependencyFactory {
         }
     }
 
+    /// Drive attached in place of a code drive when a sandbox has none
+    pub fn with_dummy_drive_path(self, dummy_drive_path: impl Into<PathBuf>) -> Self {
+        Self {
+            dummy_drive_path: dummy_drive_path.into(),
+            ..self
+        }
+    }
+
+    /// Kernel sandboxes boot from unless their template has an image
+    pub fn with_kernel_image_path(self, kernel_image_path: impl Into<PathBuf>) -> Self {
+        Self {
+            kernel_image_path: kernel_image_path.into(),
+            ..self
+        }
+    }
+
+    pub fn with_usage_recorder(self, usage_recorder: Arc<Box<dyn RecordUsage>>) -> Self {
+        Self {
+            usage_recorder,
+            ..self
+        }
+    }
+
     pub fn with_sandbox_initializer(
         self,
         sandbox_initialixer: Arc<Box<dyn InitializeSandbox>>,
@@ -73,29 +152,24 @@ impl DependencyFactory {
 impl Default for DependencyFactory {
     fn default() -> Self {
         let firecracker_provider: Box<dyn ProvideFirecracker> =
-            Box::new(JailedFirecrackerFactory::new(
-                "/usr/local/bin/jailer",
-                "/usr/local/bin/firecracker",
-                "/tmp/vms",
-            ));
-        let sandbox_initializer: Box<dyn InitializeSandbox> = Box::new(SandboxInitializer::new(
-            "/tmp/rootfs.ext4",
-            "/tmp/kernel.bin",
-        ));
+            Box::<JailedFirecrackerFactory>::default();
+        let network_provider: Box<dyn ProvideNetwork> = Box::<NetnsNetworkFactory>::default();
+        let sandbox_initializer: Box<dyn InitializeSandbox> =
+            Box::new(SandboxInitializer::new(ROOTFS_PATH, KERNEL_IMAGE_PATH));
         let identifier_provider: Box<dyn ProvideIdentifier> = Box::<VmIdentifierFactory>::default();
         let spark_client_provider: Box<dyn ProvideSparkClient> =
             Box::<SparkClientFactory>::default();
         let dummy_drive_path = PathBuf::from("/tmp/dummy.ext4");
-        assert!(
-            dummy_drive_path.exists(),
-            "The dummy drive path should exist"
-        );
+        let usage_recorder: Box<dyn RecordUsage> = Box::<StdoutUsageRecorder>::default();
         Self {
             firecracker_provider: Arc::from(firecracker_provider),
+            network_provider: Arc::from(network_provider),
             sandbox_initialixer: Arc::from(sandbox_initializer),
             identifier_provider: Arc::from(identifier_provider),
             spark_client_provider: Arc::from(spark_client_provider),
             dummy_drive_path,
+            kernel_image_path: PathBuf::from(KERNEL_IMAGE_PATH),
+            usage_recorder: Arc::from(usage_recorder),
         }
     }
 }
@@ -0,0 +1,172 @@
+use std::{fmt::Display, os::unix::fs::MetadataExt, path::Path};
+
+use anyhow::Context;
+
+use crate::sandbox::resources::SandboxResources;
+
+/// Scheduling period of cpu.max in microseconds
+const CPU_PERIOD_US: u64 = 100_000;
+/// Memory the VMM process needs on top of the guest's memory
+const VMM_MEMORY_OVERHEAD_MIB: u64 = 32;
+
+/// A cgroup file & the value the jailer writes to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cgroup {
+    pub file: String,
+    pub value: String,
+}
+
+impl Cgroup {
+    fn new(file: &str, value: impl Into<String>) -> Cgroup {
+        Cgroup {
+            file: file.to_string(),
+            value: value.into(),
+        }
+    }
+}
+
+impl Display for Cgroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.file, self.value)
+    }
+}
+
+/// Block devices by their major & minor number
+const SYSFS_BLOCK_DEVICES: &str = "/sys/dev/block";
+
+/// Major & minor number of the disk a path lives on, file systems which
+/// aren't backed by a block device like tmpfs have none. io.max only accepts
+/// whole disks, so a partition resolves to its disk.
+pub fn block_device(path: &Path) -> anyhow::Result<Option<(u32, u32)>> {
+    let device = std::fs::metadata(path)?.dev();
+    match libc::major(device) {
+        0 => Ok(None),
+        major => whole_disk(Path::new(SYSFS_BLOCK_DEVICES), (major, libc::minor(device))).map(Some),
+    }
+}
+
+/// The disk of a partition, sysfs places partitions in their disk's directory
+fn whole_disk(
+    sysfs_block_devices: &Path,
+    (major, minor): (u32, u32),
+) -> anyhow::Result<(u32, u32)> {
+    let device = sysfs_block_devices.join(format!("{major}:{minor}"));
+    if !device.join("partition").exists() {
+        return Ok((major, minor));
+    }
+
+    let disk = std::fs::read_to_string(device.join("../dev"))
+        .with_context(|| format!("Failed to find the disk of block device {major}:{minor}"))?;
+    let parsed = disk
+        .trim()
+        .split_once(':')
+        .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+    parsed.with_context(|| format!("Block device number {disk} is invalid"))
+}
+
+/// The cgroup v2 limits of the VMM process of a sandbox. IO can only be
+/// limited when the block device backing the sandbox's files is known.
+pub fn cgroups(resources: &SandboxResources, io_device: Option<(u32, u32)>) -> Vec<Cgroup> {
+    let cpu_percent = resources
+        .cpu_percent
+        .map(u64::from)
+        .unwrap_or(u64::from(resources.vcpus) * 100);
+    let quota = cpu_percent * CPU_PERIOD_US / 100;
+    let memory = (u64::from(resources.memory_mib) + VMM_MEMORY_OVERHEAD_MIB) * 1024 * 1024;
+
+    let mut cgroups = vec![
+        Cgroup::new("cpu.max", format!("{quota} {CPU_PERIOD_US}")),
+        Cgroup::new("memory.max", memory.to_string()),
+    ];
+
+    if let Some(pids) = resources.pids {
+        cgroups.push(Cgroup::new("pids.max", pids.to_string()));
+    }
+
+    let io = &resources.io;
+    if let (false, Some((major, minor))) = (io.is_unlimited(), io_device) {
+        let limits = [
+            ("rbps", io.read_bytes_per_sec),
+            ("wbps", io.write_bytes_per_sec),
+            ("riops", io.read_iops),
+            ("wiops", io.write_iops),
+        ]
+        .into_iter()
+        .filter_map(|(key, limit)| Some(format!("{key}={}", limit?)))
+        .collect::<Vec<_>>();
+        cgroups.push(Cgroup::new(
+            "io.max",
+            format!("{major}:{minor} {}", limits.join(" ")),
+        ));
+    }
+
+    cgroups
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sandbox::resources::{IoLimits, SandboxResources};
+
+    use super::{cgroups, whole_disk, Cgroup};
+
+    #[test]
+    fn default_resources_limit_cpu_and_memory() {
+        let cgroups = cgroups(&SandboxResources::default(), Some((259, 1)));
+
+        assert_eq!(
+            cgroups,
+            vec![
+                Cgroup::new("cpu.max", "100000 100000"),
+                Cgroup::new("memory.max", "167772160"),
+            ]
+        );
+        assert_eq!(cgroups[0].to_string(), "cpu.max=100000 100000");
+    }
+
+    #[test]
+    fn all_limits() {
+        let resources = SandboxResources {
+            vcpus: 2,
+            memory_mib: 512,
+            cpu_percent: Some(150),
+            pids: Some(64),
+            io: IoLimits {
+                read_bytes_per_sec: Some(1048576),
+                write_iops: Some(100),
+                ..Default::default()
+            },
+        };
+
+        assert_eq!(
+            cgroups(&resources, Some((8, 0))),
+            vec![
+                Cgroup::new("cpu.max", "150000 100000"),
+                Cgroup::new("memory.max", "570425344"),
+                Cgroup::new("pids.max", "64"),
+                Cgroup::new("io.max", "8:0 rbps=1048576 wiops=100"),
+            ]
+        );
+        assert_eq!(
+            cgroups(&resources, None).len(),
+            3,
+            "io can't be limited without a device"
+        );
+    }
+
+    #[test]
+    fn partitions_resolve_to_their_disk() {
+        let sysfs = tempfile::tempdir().unwrap();
+        let disk = sysfs.path().join("devices/nvme0n1");
+        std::fs::create_dir_all(disk.join("nvme0n1p1")).unwrap();
+        std::fs::write(disk.join("dev"), "259:0\n").unwrap();
+        std::fs::write(disk.join("nvme0n1p1/dev"), "259:1\n").unwrap();
+        std::fs::write(disk.join("nvme0n1p1/partition"), "1\n").unwrap();
+        let block_devices = sysfs.path().join("block");
+        std::fs::create_dir(&block_devices).unwrap();
+        std::os::unix::fs::symlink(disk.join("nvme0n1p1"), block_devices.join("259:1")).unwrap();
+        std::os::unix::fs::symlink(&disk, block_devices.join("259:0")).unwrap();
+
+        assert_eq!(whole_disk(&block_devices, (259, 1)).unwrap(), (259, 0));
+        assert_eq!(whole_disk(&block_devices, (259, 0)).unwrap(), (259, 0));
+    }
+}
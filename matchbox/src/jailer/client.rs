@@ -1,9 +1,18 @@
-use std::path::PathBuf;
+use std::{fmt::Display, path::PathBuf};
 
-use anyhow::Context;
-use hyper::{Body, Client, Method, Request, Response};
+use firecracker_config_rs::models::{
+    action::Action,
+    bootsource::BootSource,
+    drive::{Drive, PartialDrive},
+    instance_info::{FirecrackerVersion, InstanceInfo},
+    logger::Logger,
+    machine_config::MachineConfiguration,
+    network_interface::{NetworkInterface, PartialNetworkInterface},
+    vsock::Vsock,
+};
+use hyper::{Body, Client, Method, Request, StatusCode};
 use hyperlocal::{UnixClientExt, UnixConnector, Uri};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[derive(Debug)]
 pub struct FirecrackerClient {
@@ -11,12 +20,67 @@ pub struct FirecrackerClient {
     socket_path: PathBuf,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "action_type", rename_all = "PascalCase")]
-pub enum Action {
-    InstanceStart,
+/// Body firecracker answers rejected requests with
+#[derive(Debug, Deserialize)]
+struct Fault {
+    fault_message: String,
 }
 
+#[derive(Debug)]
+pub enum FirecrackerError {
+    /// Firecracker rejected the request, `message` is its fault message
+    Fault {
+        method: Method,
+        path: String,
+        status: StatusCode,
+        message: String,
+    },
+    /// Firecracker couldn't be reached on its API socket
+    Connection {
+        method: Method,
+        path: String,
+        source: hyper::Error,
+    },
+    /// Firecracker answered with something other than what the endpoint
+    /// returns
+    InvalidResponse {
+        method: Method,
+        path: String,
+        source: serde_json::Error,
+    },
+}
+
+impl Display for FirecrackerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FirecrackerError::Fault {
+                method,
+                path,
+                status,
+                message,
+            } => write!(f, "{method} {path} failed with {status}: {message}"),
+            FirecrackerError::Connection { method, path, .. } => {
+                write!(f, "{method} {path} failed to reach firecracker")
+            }
+            FirecrackerError::InvalidResponse { method, path, .. } => {
+                write!(f, "{method} {path} returned an invalid response")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FirecrackerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FirecrackerError::Fault { .. } => None,
+            FirecrackerError::Connection { source, .. } => Some(source),
+            FirecrackerError::InvalidResponse { source, .. } => Some(source),
+        }
+    }
+}
+
+pub type FirecrackerResult<T> = Result<T, FirecrackerError>;
+
 impl FirecrackerClient {
     pub fn new(socket_path: PathBuf) -> FirecrackerClient {
         let client = Client::unix();
@@ -26,42 +90,142 @@ impl FirecrackerClient {
         }
     }
 
-    pub async fn get(&self, path: impl AsRef<str>) -> anyhow::Result<Response<Body>> {
-        self.execute(path, Method::GET, Body::empty()).await
+    pub async fn version(&self) -> FirecrackerResult<FirecrackerVersion> {
+        self.get("/version").await
+    }
+
+    pub async fn instance_info(&self) -> FirecrackerResult<InstanceInfo> {
+        self.get("/").await
+    }
+
+    pub async fn machine_config(&self) -> FirecrackerResult<MachineConfiguration> {
+        self.get("/machine-config").await
     }
 
-    pub async fn put(
+    pub async fn put_logger(&self, logger: &Logger) -> FirecrackerResult<()> {
+        self.put("/logger", logger).await
+    }
+
+    pub async fn put_boot_source(&self, boot_source: &BootSource) -> FirecrackerResult<()> {
+        self.put("/boot-source", boot_source).await
+    }
+
+    pub async fn put_machine_config(
         &self,
-        path: impl AsRef<str>,
-        body: impl Serialize,
-    ) -> anyhow::Result<Response<Body>> {
-        let body = Body::from(serde_json::to_string(&body)?);
-        self.execute(path, Method::PUT, body).await
+        machine_config: &MachineConfiguration,
+    ) -> FirecrackerResult<()> {
+        self.put("/machine-config", machine_config).await
     }
 
-    pub async fn action(&self, action: Action) -> anyhow::Result<Response<Body>> {
+    pub async fn put_drive(&self, drive: &Drive) -> FirecrackerResult<()> {
+        self.put(&format!("/drives/{}", drive.drive_id), drive)
+            .await
+    }
+
+    pub async fn patch_drive(&self, drive: &PartialDrive) -> FirecrackerResult<()> {
+        self.patch(&format!("/drives/{}", drive.drive_id), drive)
+            .await
+    }
+
+    pub async fn put_network_interface(
+        &self,
+        interface: &NetworkInterface,
+    ) -> FirecrackerResult<()> {
+        self.put(
+            &format!("/network-interfaces/{}", interface.iface_id),
+            interface,
+        )
+        .await
+    }
+
+    pub async fn patch_network_interface(
+        &self,
+        interface: &PartialNetworkInterface,
+    ) -> FirecrackerResult<()> {
+        self.patch(
+            &format!("/network-interfaces/{}", interface.iface_id),
+            interface,
+        )
+        .await
+    }
+
+    pub async fn put_vsock(&self, vsock: &Vsock) -> FirecrackerResult<()> {
+        self.put("/vsock", vsock).await
+    }
+
+    pub async fn action(&self, action: Action) -> FirecrackerResult<()> {
         self.put("/actions", &action).await
     }
 
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> FirecrackerResult<T> {
+        let body = self.execute(Method::GET, path, Body::empty()).await?;
+        serde_json::from_slice(&body).map_err(|source| FirecrackerError::InvalidResponse {
+            method: Method::GET,
+            path: path.to_string(),
+            source,
+        })
+    }
+
+    async fn put(&self, path: &str, body: &impl Serialize) -> FirecrackerResult<()> {
+        self.execute(Method::PUT, path, json_body(body)).await?;
+        Ok(())
+    }
+
+    async fn patch(&self, path: &str, body: &impl Serialize) -> FirecrackerResult<()> {
+        self.execute(Method::PATCH, path, json_body(body)).await?;
+        Ok(())
+    }
+
+    /// Sends a request & returns the body of a successful response
     async fn execute(
         &self,
-        path: impl AsRef<str>,
         method: Method,
+        path: &str,
         body: Body,
-    ) -> anyhow::Result<Response<Body>> {
-        let uri: hyper::Uri = Uri::new(&self.socket_path, path.as_ref()).into();
+    ) -> FirecrackerResult<hyper::body::Bytes> {
+        let uri: hyper::Uri = Uri::new(&self.socket_path, path).into();
 
         let request = Request::builder()
-            .method(method)
+            .method(method.clone())
             .uri(uri)
-            .header("Acccept", "application/json")
+            .header("Accept", "application/json")
             .header("Content-Type", "application/json")
             .body(body)
             .unwrap();
 
-        self.client
+        let connection_error = |source| FirecrackerError::Connection {
+            method: method.clone(),
+            path: path.to_string(),
+            source,
+        };
+        let response = self
+            .client
             .request(request)
             .await
-            .context("Failed to execute request")
+            .map_err(connection_error)?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(connection_error)?;
+
+        if !status.is_success() {
+            let message = match serde_json::from_slice::<Fault>(&body) {
+                Ok(fault) => fault.fault_message,
+                Err(_) => String::from_utf8_lossy(&body).to_string(),
+            };
+            return Err(FirecrackerError::Fault {
+                method,
+                path: path.to_string(),
+                status,
+                message,
+            });
+        }
+
+        Ok(body)
     }
 }
+
+/// Every model serializes to JSON
+fn json_body(body: &impl Serialize) -> Body {
+    Body::from(serde_json::to_vec(body).unwrap())
+}
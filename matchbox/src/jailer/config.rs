@@ -1,6 +1,9 @@
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use super::cgroup::Cgroup;
+
 #[derive(Copy, Clone, Debug)]
 pub struct JailerGid(u32);
 
@@ -10,6 +13,12 @@ impl Default for JailerGid {
     }
 }
 
+impl From<u32> for JailerGid {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
 impl From<&JailerGid> for u32 {
     fn from(value: &JailerGid) -> Self {
         value.0
@@ -24,14 +33,94 @@ impl Default for JailerUid {
     }
 }
 
+impl From<u32> for JailerUid {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
 impl From<&JailerUid> for u32 {
     fn from(value: &JailerUid) -> Self {
         value.0
     }
 }
 
+/// Arguments of firecracker which are managed by matchbox
+const RESERVED_FIRECRACKER_ARGS: &[&str] = &[
+    "--api-sock",
+    "--id",
+    "--seccomp-filter",
+    "--no-seccomp",
+    "--config-file",
+];
+
+/// Resource limits the jailer sets on the exec_file process, unset limits are
+/// inherited
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Maximum size in bytes of files the process creates
+    pub fsize: Option<u64>,
+    /// Maximum number of file descriptors the process opens
+    pub no_file: Option<u64>,
+}
+
+/// Seccomp filters firecracker installs on its threads
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Seccomp {
+    /// Firecracker's built in filters
+    #[default]
+    Default,
+    /// A compiled filter file on the host, copied into the jail
+    Filter { path: PathBuf },
+    /// No filters at all, only meant for debugging
+    Disabled,
+}
+
+/// Hardening of jailed firecracker processes, the same for every sandbox of
+/// a deployment
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct JailerHardening {
+    /// Run firecracker in a new pid namespace, its pid is written to a pid
+    /// file in the jail
+    pub new_pid_ns: bool,
+    /// Detach the jailer from the terminal, firecracker's pid is written to
+    /// a pid file in the jail
+    pub daemonize: bool,
+    pub resource_limits: ResourceLimits,
+    pub seccomp: Seccomp,
+    /// Extra arguments passed to firecracker
+    pub firecracker_args: Vec<String>,
+}
+
+impl JailerHardening {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let ResourceLimits { fsize, no_file } = self.resource_limits;
+        if fsize == Some(0) || no_file == Some(0) {
+            anyhow::bail!("Resource limits have to be greater than 0");
+        }
+
+        if let Seccomp::Filter { path } = &self.seccomp {
+            if !path.is_file() {
+                anyhow::bail!("Seccomp filter {} does not exist", path.display());
+            }
+        }
+
+        for arg in &self.firecracker_args {
+            let flag = arg.split('=').next().unwrap_or_default();
+            if RESERVED_FIRECRACKER_ARGS.contains(&flag) {
+                anyhow::bail!("Firecracker argument {flag} is managed by matchbox");
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Builder, Clone, Debug)]
-#[builder(setter(into))]
+#[builder(setter(into), build_fn(validate = "Self::validate"))]
 pub struct JailerConfig {
     /// Path to the  jailer binary
     pub jailer_path: PathBuf,
@@ -45,8 +134,9 @@ pub struct JailerConfig {
     /// Base directory of the chrooted process
     pub chroot_base_dir: PathBuf,
 
-    /// Path of the network namespace
-    pub netns: PathBuf,
+    /// Path of the network namespace, firecracker runs without one when unset
+    #[builder(setter(strip_option), default)]
+    pub netns: Option<PathBuf>,
 
     /// gid jailer will switch to when executed the exec_file process
     #[builder(default)]
@@ -55,4 +145,204 @@ pub struct JailerConfig {
     /// uid jailer will switch to when executed the exec_file process
     #[builder(default)]
     pub uid: JailerUid,
+
+    /// cgroup v2 limits of the exec_file process
+    #[builder(default)]
+    pub cgroups: Vec<Cgroup>,
+
+    /// cgroup the exec_file process' cgroup is created under, jailer uses the
+    /// name of the exec_file when unset
+    #[builder(setter(strip_option), default)]
+    pub parent_cgroup: Option<String>,
+
+    /// Hardening of the jailer & firecracker
+    #[builder(default)]
+    pub hardening: JailerHardening,
+
+    /// Path of the seccomp filter in the jail, set when the hardening has a
+    /// filter
+    #[builder(setter(strip_option), default)]
+    pub seccomp_filter: Option<PathBuf>,
+
+    /// Path of the config file in the jail firecracker boots the microvm
+    /// from, it only serves its API when unset
+    #[builder(setter(strip_option), default)]
+    pub config_file: Option<PathBuf>,
+}
+
+impl JailerConfigBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(id) = &self.id {
+            if id.is_empty()
+                || id.len() > 64
+                || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(format!(
+                    "Id {id} has to be 1 to 64 alphanumeric characters or hyphens"
+                ));
+            }
+        }
+
+        if let Some(hardening) = &self.hardening {
+            hardening.validate().map_err(|e| e.to_string())?;
+            let has_filter = matches!(hardening.seccomp, Seccomp::Filter { .. });
+            if has_filter && self.seccomp_filter.as_ref().is_none_or(Option::is_none) {
+                return Err("The seccomp filter has no path in the jail".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl JailerConfig {
+    /// Arguments of the jailer, followed by the ones of firecracker
+    pub fn arguments(&self) -> Vec<String> {
+        let mut args = vec![
+            "--id".to_string(),
+            self.id.clone(),
+            "--exec-file".to_string(),
+            self.exec_file.to_string_lossy().to_string(),
+            "--gid".to_string(),
+            u32::from(&self.gid).to_string(),
+            "--uid".to_string(),
+            u32::from(&self.uid).to_string(),
+            "--chroot-base-dir".to_string(),
+            self.chroot_base_dir.to_string_lossy().to_string(),
+        ];
+        if let Some(netns) = &self.netns {
+            args.extend(["--netns".to_string(), netns.to_string_lossy().to_string()]);
+        }
+        if let Some(parent_cgroup) = &self.parent_cgroup {
+            args.extend([
+                "--cgroup-version".to_string(),
+                "2".to_string(),
+                "--parent-cgroup".to_string(),
+                parent_cgroup.clone(),
+            ]);
+            for cgroup in &self.cgroups {
+                args.extend(["--cgroup".to_string(), cgroup.to_string()]);
+            }
+        }
+
+        let hardening = &self.hardening;
+        if hardening.new_pid_ns {
+            args.push("--new-pid-ns".to_string());
+        }
+        if hardening.daemonize {
+            args.push("--daemonize".to_string());
+        }
+        if let Some(fsize) = hardening.resource_limits.fsize {
+            args.extend(["--resource-limit".to_string(), format!("fsize={fsize}")]);
+        }
+        if let Some(no_file) = hardening.resource_limits.no_file {
+            args.extend(["--resource-limit".to_string(), format!("no-file={no_file}")]);
+        }
+
+        let mut firecracker_args = Vec::new();
+        match (&hardening.seccomp, &self.seccomp_filter) {
+            (Seccomp::Filter { .. }, Some(path)) => firecracker_args.extend([
+                "--seccomp-filter".to_string(),
+                path.to_string_lossy().to_string(),
+            ]),
+            (Seccomp::Disabled, _) => firecracker_args.push("--no-seccomp".to_string()),
+            _ => {}
+        }
+        if let Some(config_file) = &self.config_file {
+            firecracker_args.extend([
+                "--config-file".to_string(),
+                config_file.to_string_lossy().to_string(),
+            ]);
+        }
+        firecracker_args.extend(hardening.firecracker_args.iter().cloned());
+        if !firecracker_args.is_empty() {
+            args.push("--".to_string());
+            args.extend(firecracker_args);
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JailerConfigBuilder, JailerHardening, ResourceLimits, Seccomp};
+
+    fn builder() -> JailerConfigBuilder {
+        let mut builder = JailerConfigBuilder::default();
+        builder
+            .jailer_path("/usr/local/bin/jailer")
+            .exec_file("/usr/local/bin/firecracker")
+            .chroot_base_dir("/tmp/vms")
+            .uid(1000)
+            .gid(1000)
+            .id("sandbox-1");
+        builder
+    }
+
+    #[test]
+    fn hardening_is_passed_to_jailer_and_firecracker() {
+        let config = builder()
+            .hardening(JailerHardening {
+                new_pid_ns: true,
+                daemonize: true,
+                resource_limits: ResourceLimits {
+                    fsize: Some(1024),
+                    no_file: Some(256),
+                },
+                seccomp: Seccomp::Disabled,
+                firecracker_args: vec!["--level".to_string(), "Debug".to_string()],
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            config.arguments().join(" "),
+            "--id sandbox-1 --exec-file /usr/local/bin/firecracker --gid 1000 --uid 1000 \
+             --chroot-base-dir /tmp/vms --new-pid-ns --daemonize --resource-limit fsize=1024 \
+             --resource-limit no-file=256 -- --no-seccomp --level Debug"
+        );
+    }
+
+    #[test]
+    fn config_files_are_passed_to_firecracker() {
+        let config = builder().config_file("/vm-config.json").build().unwrap();
+
+        assert!(config
+            .arguments()
+            .join(" ")
+            .ends_with("-- --config-file /vm-config.json"));
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        assert!(builder().id("not_valid").build().is_err());
+
+        let zero_limit = JailerHardening {
+            resource_limits: ResourceLimits {
+                fsize: Some(0),
+                no_file: None,
+            },
+            ..Default::default()
+        };
+        assert!(builder().hardening(zero_limit).build().is_err());
+
+        let reserved_arg = JailerHardening {
+            firecracker_args: vec!["--api-sock=/tmp/other.socket".to_string()],
+            ..Default::default()
+        };
+        assert!(builder().hardening(reserved_arg).build().is_err());
+
+        let missing_filter = JailerHardening {
+            seccomp: Seccomp::Filter {
+                path: "/does/not/exist.bpf".into(),
+            },
+            ..Default::default()
+        };
+        assert!(builder()
+            .hardening(missing_filter)
+            .seccomp_filter("/seccomp.bpf")
+            .build()
+            .is_err());
+    }
 }
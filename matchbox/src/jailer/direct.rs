@@ -0,0 +1,139 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client::FirecrackerClient,
+    factory::{ProvideFirecracker, ProvideFirecrackerOptions},
+    FirecrackerProcess, LaunchFirecracker, PathResolver,
+};
+
+/// Where firecracker runs from without the jailer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct DirectConfig {
+    pub firecracker_path: PathBuf,
+    /// Every sandbox gets a working directory laid out like a jail below it
+    pub base_dir: PathBuf,
+}
+
+impl Default for DirectConfig {
+    fn default() -> Self {
+        DirectConfig {
+            firecracker_path: "/usr/local/bin/firecracker".into(),
+            base_dir: "/tmp/matchbox-dev".into(),
+        }
+    }
+}
+
+/// Runs firecracker without the jailer, as the user running matchbox, for
+/// development hosts without root or a jailer binary. Sandboxes aren't
+/// isolated from the host beyond KVM itself, so only trusted workloads may run
+/// on it.
+#[derive(Clone, Debug)]
+pub struct DirectFirecrackerFactory {
+    firecracker_path: PathBuf,
+    /// Every sandbox gets a working directory laid out like a jail below it
+    base_dir: PathBuf,
+}
+
+impl Default for DirectFirecrackerFactory {
+    fn default() -> Self {
+        Self::from(&DirectConfig::default())
+    }
+}
+
+impl From<&DirectConfig> for DirectFirecrackerFactory {
+    fn from(config: &DirectConfig) -> Self {
+        Self::new(&config.firecracker_path, &config.base_dir)
+    }
+}
+
+impl DirectFirecrackerFactory {
+    pub fn new(
+        firecracker_path: impl Into<PathBuf>,
+        base_dir: impl Into<PathBuf>,
+    ) -> DirectFirecrackerFactory {
+        DirectFirecrackerFactory {
+            firecracker_path: firecracker_path.into(),
+            base_dir: base_dir.into(),
+        }
+    }
+
+    /// Working directory of a sandbox, the parent of its root like a jail's
+    fn working_directory(&self, id: &str) -> PathBuf {
+        self.base_dir.join(id)
+    }
+
+    pub fn spawn_firecracker(
+        &self,
+        options: &ProvideFirecrackerOptions,
+    ) -> anyhow::Result<FirecrackerProcess> {
+        let vm_id = options.id.as_str();
+        let resolver = PathResolver {
+            root_directory: self.working_directory(vm_id).join("root"),
+            jailed: false,
+        };
+        for directory in ["/drives/", "/log/", "/run/"] {
+            std::fs::create_dir_all(resolver.resolve(directory))?;
+        }
+
+        let firecracker_socket = resolver.resolve("/run/firecracker.socket");
+        let launcher = DirectLauncher {
+            id: vm_id.to_string(),
+            firecracker_path: self.firecracker_path.clone(),
+            api_socket: firecracker_socket.clone(),
+            netns: options.netns.clone(),
+        };
+
+        Ok(FirecrackerProcess {
+            path_resolver: resolver,
+            client: FirecrackerClient::new(firecracker_socket),
+            owner: None,
+            pid_file: None,
+            launcher: Box::new(launcher),
+        })
+    }
+}
+
+impl ProvideFirecracker for DirectFirecrackerFactory {
+    fn provide_firecracker(
+        &self,
+        options: &ProvideFirecrackerOptions,
+    ) -> anyhow::Result<FirecrackerProcess> {
+        self.spawn_firecracker(options)
+    }
+}
+
+/// Runs firecracker in a tmux session named after the VM
+#[derive(Debug)]
+struct DirectLauncher {
+    id: String,
+    firecracker_path: PathBuf,
+    api_socket: PathBuf,
+    netns: Option<PathBuf>,
+}
+
+impl LaunchFirecracker for DirectLauncher {
+    fn launch(&self, config_file: Option<&Path>) -> anyhow::Result<()> {
+        let mut cmd = Command::new("tmux");
+        cmd.args(["new-session", "-d", "-s", &self.id]);
+        if let Some(netns) = &self.netns {
+            // Entering a network namespace is the one step that needs root
+            cmd.arg("nsenter").arg(format!("--net={}", netns.display()));
+        }
+        cmd.arg(&self.firecracker_path)
+            .arg("--api-sock")
+            .arg(&self.api_socket)
+            .args(["--id", &self.id]);
+        if let Some(config_file) = config_file {
+            cmd.arg("--config-file").arg(config_file);
+        }
+
+        let _ = cmd.output()?;
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 use super::{
-    client::FirecrackerClient, config::JailerConfigBuilder, FirecrackerProcess, PathResolver,
+    cgroup::{block_device, cgroups},
+    client::FirecrackerClient,
+    config::{JailerConfig, JailerConfigBuilder, JailerHardening, Seccomp},
+    ids::IdPool,
+    FirecrackerProcess, LaunchFirecracker, PathResolver,
 };
 
 use std::{
@@ -8,13 +12,39 @@ use std::{
     process::Command,
 };
 
+use derive_builder::Builder;
+use firecracker_config_rs::validation::ValidationErrors;
+
+use crate::sandbox::resources::SandboxResources;
+
+/// Where a custom seccomp filter is copied to in the jail
+const SECCOMP_FILTER_PATH: &str = "/seccomp.bpf";
+
+#[derive(Builder, Clone, Debug)]
+#[builder(setter(into))]
+pub struct ProvideFirecrackerOptions {
+    pub id: String,
+    /// Network namespace firecracker runs in, none when unset
+    #[builder(setter(strip_option), default)]
+    pub netns: Option<PathBuf>,
+    #[builder(default)]
+    pub resources: SandboxResources,
+}
+
+/// Lays out the jail of a firecracker, which runs once it is launched
 pub trait ProvideFirecracker: Debug + Send + Sync {
-    fn provide_firecracker(&self, id: &str, netns: &Path) -> FirecrackerProcess;
+    fn provide_firecracker(
+        &self,
+        options: &ProvideFirecrackerOptions,
+    ) -> anyhow::Result<FirecrackerProcess>;
 }
 
 impl ProvideFirecracker for JailedFirecrackerFactory {
-    fn provide_firecracker(&self, id: &str, netns: &Path) -> FirecrackerProcess {
-        self.spawn_jailed_firecracker(id, netns)
+    fn provide_firecracker(
+        &self,
+        options: &ProvideFirecrackerOptions,
+    ) -> anyhow::Result<FirecrackerProcess> {
+        self.spawn_jailed_firecracker(options)
     }
 }
 
@@ -23,6 +53,21 @@ pub struct JailedFirecrackerFactory {
     jailer_path: PathBuf,
     firecracker_path: PathBuf,
     chroot_base_dir: PathBuf,
+    /// Limit firecracker through cgroups created under this cgroup
+    parent_cgroup: Option<String>,
+    /// Every firecracker runs as its own unprivileged user & group
+    ids: IdPool,
+    hardening: JailerHardening,
+}
+
+impl Default for JailedFirecrackerFactory {
+    fn default() -> Self {
+        Self::new(
+            "/usr/local/bin/jailer",
+            "/usr/local/bin/firecracker",
+            "/tmp/vms",
+        )
+    }
 }
 
 impl JailedFirecrackerFactory {
@@ -39,58 +84,195 @@ impl JailedFirecrackerFactory {
             jailer_path,
             firecracker_path,
             chroot_base_dir,
+            parent_cgroup: None,
+            ids: IdPool::default(),
+            hardening: JailerHardening::default(),
         }
     }
 
-    pub fn spawn_jailed_firecracker(&self, vm_id: &str, netns: &Path) -> FirecrackerProcess {
-        let jailer_config = JailerConfigBuilder::default()
-            .jailer_path(&self.jailer_path)
-            .exec_file(&self.firecracker_path)
-            .chroot_base_dir(&self.chroot_base_dir)
-            .id(vm_id)
-            .netns(netns)
-            .build()
-            .unwrap();
+    /// Enforces the sandbox's resources on firecracker with cgroup v2. The
+    /// cpu, memory, io & pids controllers have to be enabled for the parent
+    /// cgroup's children.
+    pub fn with_parent_cgroup(self, parent_cgroup: impl Into<String>) -> Self {
+        Self {
+            parent_cgroup: Some(parent_cgroup.into()),
+            ..self
+        }
+    }
 
-        let mut cmd = Command::new("tmux");
-        cmd.args([
-            "new-session",
-            "-d",
-            "-s",
-            &jailer_config.id,
-            &jailer_config.jailer_path.to_string_lossy(),
-            "--id",
-            &jailer_config.id,
-            "--exec-file",
-            &jailer_config.exec_file.to_string_lossy(),
-            "--gid",
-            &u32::from(&jailer_config.gid).to_string(),
-            "--uid",
-            &u32::from(&jailer_config.uid).to_string(),
-            "--chroot-base-dir",
-            &jailer_config.chroot_base_dir.to_string_lossy(),
-            "--netns",
-            &jailer_config.netns.to_string_lossy(),
-        ]);
-
-        let _ = cmd.spawn().unwrap();
+    /// Hands out the uids & gids of firecracker processes from `ids`
+    pub fn with_id_pool(self, ids: IdPool) -> Self {
+        Self { ids, ..self }
+    }
+
+    /// Hardens every jailed firecracker, the hardening has to be valid
+    pub fn with_hardening(self, hardening: JailerHardening) -> Self {
+        Self { hardening, ..self }
+    }
+
+    pub fn spawn_jailed_firecracker(
+        &self,
+        options: &ProvideFirecrackerOptions,
+    ) -> anyhow::Result<FirecrackerProcess> {
+        let vm_id = options.id.as_str();
+        let io_device = match &self.parent_cgroup {
+            Some(_) => {
+                std::fs::create_dir_all(&self.chroot_base_dir)?;
+                block_device(&self.chroot_base_dir)?
+            }
+            None => None,
+        };
+        if !options.resources.io.is_unlimited() && io_device.is_none() {
+            let mut errors = ValidationErrors::default();
+            errors.add(
+                "resources.io",
+                "IO can't be limited on this server, its sandboxes aren't on a disk with cgroups",
+            );
+            return Err(errors.into());
+        }
+
+        let owner = self.ids.allocate()?;
         let root_directory = self
             .chroot_base_dir
             .join(self.firecracker_path.file_stem().unwrap())
             .join(vm_id)
             .join("root");
-        let resolver = PathResolver { root_directory };
+        let resolver = PathResolver {
+            root_directory,
+            jailed: true,
+        };
 
-        std::fs::create_dir_all(resolver.resolve("/drives/")).unwrap();
-        std::fs::create_dir_all(resolver.resolve("/log/")).unwrap();
-        std::fs::create_dir_all(resolver.resolve("/run/")).unwrap();
+        let mut builder = JailerConfigBuilder::default();
+        builder
+            .jailer_path(&self.jailer_path)
+            .exec_file(&self.firecracker_path)
+            .chroot_base_dir(&self.chroot_base_dir)
+            .uid(owner.uid())
+            .gid(owner.gid())
+            .id(vm_id)
+            .hardening(self.hardening.clone());
+        if let Some(netns) = &options.netns {
+            builder.netns(netns);
+        }
+        if let Some(parent_cgroup) = &self.parent_cgroup {
+            builder
+                .parent_cgroup(parent_cgroup)
+                .cgroups(cgroups(&options.resources, io_device));
+        }
+        if let Seccomp::Filter { .. } = &self.hardening.seccomp {
+            builder.seccomp_filter(SECCOMP_FILTER_PATH);
+        }
+        let jailer_config = builder.build()?;
 
         let firecracker_socket = resolver.resolve("/run/firecracker.socket");
         let client = FirecrackerClient::new(firecracker_socket);
-
-        FirecrackerProcess {
+        let pid_file = self.pid_file(&resolver);
+        let process = FirecrackerProcess {
             path_resolver: resolver,
             client,
+            owner: Some(owner),
+            pid_file,
+            launcher: Box::new(JailerLauncher(jailer_config)),
+        };
+
+        for directory in ["/drives/", "/log/", "/run/"] {
+            let directory = process.path_resolver.resolve(directory);
+            std::fs::create_dir_all(&directory)?;
+            process.chown(&directory)?;
+        }
+        if let Seccomp::Filter { path } = &self.hardening.seccomp {
+            // Firecracker reads the filter after it is jailed, as its own user
+            let filter_path_on_host = process.path_resolver.resolve(SECCOMP_FILTER_PATH);
+            std::fs::copy(path, &filter_path_on_host)?;
+            process.chown(&filter_path_on_host)?;
+        }
+
+        Ok(process)
+    }
+
+    /// A daemonized jailer leaves the tmux session & a jailer with a new pid
+    /// namespace forks firecracker before exiting, either way firecracker can
+    /// only be found through its pid file
+    fn pid_file(&self, resolver: &PathResolver) -> Option<PathBuf> {
+        (self.hardening.daemonize || self.hardening.new_pid_ns).then(|| {
+            let exec_file_name = self.firecracker_path.file_name().unwrap();
+            resolver.resolve(format!("/{}.pid", exec_file_name.to_string_lossy()))
+        })
+    }
+}
+
+/// Runs the jailer in a tmux session named after the VM
+#[derive(Debug)]
+struct JailerLauncher(JailerConfig);
+
+impl LaunchFirecracker for JailerLauncher {
+    fn launch(&self, config_file: Option<&Path>) -> anyhow::Result<()> {
+        let mut jailer_config = self.0.clone();
+        jailer_config.config_file = config_file.map(Path::to_path_buf);
+
+        let mut cmd = Command::new("tmux");
+        cmd.args(["new-session", "-d", "-s", &jailer_config.id])
+            .arg(&jailer_config.jailer_path)
+            .args(jailer_config.arguments());
+
+        let _ = cmd.output()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use firecracker_config_rs::validation::ValidationErrors;
+
+    use crate::{
+        jailer::{config::JailerHardening, PathResolver},
+        sandbox::resources::{IoLimits, SandboxResources},
+    };
+
+    use super::{JailedFirecrackerFactory, ProvideFirecrackerOptionsBuilder};
+
+    #[test]
+    fn io_limits_without_cgroups_are_rejected() {
+        let options = ProvideFirecrackerOptionsBuilder::default()
+            .id("sandbox")
+            .resources(SandboxResources {
+                io: IoLimits {
+                    write_iops: Some(100),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let error = JailedFirecrackerFactory::default()
+            .spawn_jailed_firecracker(&options)
+            .unwrap_err();
+        assert!(error.downcast_ref::<ValidationErrors>().is_some());
+    }
+
+    #[test]
+    fn firecracker_in_a_new_pid_namespace_is_found_through_its_pid_file() {
+        let resolver = PathResolver {
+            root_directory: PathBuf::from("/jail/root"),
+            jailed: true,
+        };
+        let factory = |new_pid_ns, daemonize| {
+            JailedFirecrackerFactory::default().with_hardening(JailerHardening {
+                new_pid_ns,
+                daemonize,
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(factory(false, false).pid_file(&resolver), None);
+        for (new_pid_ns, daemonize) in [(true, false), (false, true), (true, true)] {
+            assert_eq!(
+                factory(new_pid_ns, daemonize).pid_file(&resolver),
+                Some(PathBuf::from("/jail/root/firecracker.pid"))
+            );
         }
     }
 }
@@ -0,0 +1,99 @@
+use std::{
+    collections::BTreeSet,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+/// Unprivileged ids handed out to jailed firecracker processes, far away
+/// from regular users & the subordinate ids of containers
+pub const DEFAULT_ID_RANGE: Range<u32> = 900_000..965_536;
+
+/// Hands out a distinct unprivileged id to every firecracker process, which
+/// is used as both its uid & gid
+#[derive(Debug, Clone)]
+pub struct IdPool(Arc<IdPoolInner>);
+
+#[derive(Debug)]
+struct IdPoolInner {
+    range: Range<u32>,
+    allocated: Mutex<BTreeSet<u32>>,
+}
+
+impl IdPool {
+    pub fn new(range: Range<u32>) -> IdPool {
+        IdPool(Arc::new(IdPoolInner {
+            range,
+            allocated: Default::default(),
+        }))
+    }
+
+    pub fn allocate(&self) -> anyhow::Result<IdLease> {
+        let mut allocated = self.0.allocated.lock().unwrap();
+        let Some(id) = self.0.range.clone().find(|id| !allocated.contains(id)) else {
+            anyhow::bail!(
+                "All ids between {} and {} are in use",
+                self.0.range.start,
+                self.0.range.end
+            );
+        };
+        allocated.insert(id);
+
+        Ok(IdLease {
+            pool: self.0.clone(),
+            id,
+        })
+    }
+
+    pub fn allocated(&self) -> usize {
+        self.0.allocated.lock().unwrap().len()
+    }
+}
+
+impl Default for IdPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_ID_RANGE)
+    }
+}
+
+/// An id owned by a single firecracker process, released when dropped
+#[derive(Debug)]
+pub struct IdLease {
+    pool: Arc<IdPoolInner>,
+    id: u32,
+}
+
+impl IdLease {
+    pub fn uid(&self) -> u32 {
+        self.id
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for IdLease {
+    fn drop(&mut self) {
+        self.pool.allocated.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdPool;
+
+    #[test]
+    fn ids_are_unique_and_released_on_drop() {
+        let pool = IdPool::new(10..12);
+
+        let first = pool.allocate().unwrap();
+        let second = pool.allocate().unwrap();
+        assert_eq!((first.uid(), first.gid()), (10, 10));
+        assert_eq!(second.uid(), 11);
+        assert!(pool.allocate().is_err(), "the pool should be exhausted");
+
+        drop(first);
+        assert_eq!(pool.allocated(), 1);
+        assert_eq!(pool.allocate().unwrap().uid(), 10);
+    }
+}
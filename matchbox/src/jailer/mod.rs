@@ -1,26 +1,190 @@
-use std::path::PathBuf;
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
 
-use self::client::FirecrackerClient;
+use anyhow::Context;
 
+use self::{client::FirecrackerClient, ids::IdLease};
+
+pub mod cgroup;
 pub mod client;
 pub mod config;
+pub mod direct;
 pub mod factory;
+pub mod ids;
+
+/// How long a killed firecracker gets to be reaped before its id is given up
+const REAP_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 pub struct FirecrackerProcess {
     pub path_resolver: PathResolver,
     pub client: FirecrackerClient,
+    /// The unprivileged identity firecracker runs as, none when it runs as
+    /// matchbox's user
+    pub owner: Option<IdLease>,
+    /// Pid file of a daemonized firecracker
+    pub pid_file: Option<PathBuf>,
+    pub launcher: Box<dyn LaunchFirecracker>,
+}
+
+/// Starts a provided firecracker, the jail is laid out before it runs
+pub trait LaunchFirecracker: Debug + Send + Sync {
+    /// `config_file` is the path firecracker opens the microvm's config file
+    /// at, firecracker boots the microvm from it right away. Without one it
+    /// only serves its API.
+    fn launch(&self, config_file: Option<&Path>) -> anyhow::Result<()>;
+}
+
+impl FirecrackerProcess {
+    pub fn launch(&self, config_file: Option<&Path>) -> anyhow::Result<()> {
+        self.launcher.launch(config_file)
+    }
+
+    /// Hands a file in the jail over to firecracker's user
+    pub fn chown(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let Some(owner) = &self.owner else {
+            return Ok(());
+        };
+        let path = path.as_ref();
+        std::os::unix::fs::chown(path, Some(owner.uid()), Some(owner.gid()))
+            .with_context(|| format!("Failed to chown {}", path.display()))
+    }
+
+    /// Kills a daemonized firecracker, others are stopped with their tmux
+    /// session
+    pub fn kill_daemon(&self) -> anyhow::Result<()> {
+        let Some(pid_file) = &self.pid_file else {
+            return Ok(());
+        };
+        let pid: i32 = std::fs::read_to_string(pid_file)
+            .with_context(|| format!("Failed to read {}", pid_file.display()))?
+            .trim()
+            .parse()?;
+        // SAFETY: kill only sends a signal, the pid comes from firecracker's
+        // own pid file
+        if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Failed to kill firecracker {pid}"));
+        }
+
+        Ok(())
+    }
+
+    /// Pid of the running firecracker, `session` is the tmux session it was
+    /// launched in
+    pub fn pid(&self, session: &str) -> Option<i32> {
+        match &self.pid_file {
+            Some(pid_file) => std::fs::read_to_string(pid_file).ok()?.trim().parse().ok(),
+            None => {
+                let output = Command::new("tmux")
+                    .args(["list-panes", "-t", session, "-F", "#{pane_pid}"])
+                    .output()
+                    .ok()?;
+                String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+            }
+        }
+    }
+
+    /// Gives firecracker's id back to the pool once the killed process `pid`
+    /// was reaped, an id must never be shared with a process that's still
+    /// around
+    pub fn release_owner(&mut self, pid: Option<i32>) {
+        let Some(owner) = self.owner.take() else {
+            return;
+        };
+        match pid {
+            Some(pid) if !wait_until_reaped(pid, REAP_TIMEOUT) => {
+                println!(
+                    "firecracker {pid} was not reaped, id {} stays in use",
+                    owner.uid()
+                );
+                std::mem::forget(owner);
+            }
+            _ => drop(owner),
+        }
+    }
+}
+
+/// Whether the process `pid` is gone within `timeout`
+fn wait_until_reaped(pid: i32, timeout: Duration) -> bool {
+    let start = Instant::now();
+    loop {
+        // SAFETY: signal 0 sends nothing, it only checks whether the process
+        // exists
+        let exists = unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH);
+        if !exists {
+            return true;
+        }
+        if start.elapsed() > timeout {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
 }
 
 #[derive(Debug)]
 pub struct PathResolver {
     root_directory: PathBuf,
+    /// Whether firecracker is chrooted into the root directory
+    jailed: bool,
 }
 
 impl PathResolver {
+    /// `jailed` is whether firecracker is chrooted into `root_directory`
+    pub fn new(root_directory: impl Into<PathBuf>, jailed: bool) -> PathResolver {
+        PathResolver {
+            root_directory: root_directory.into(),
+            jailed,
+        }
+    }
+
     pub fn resolve(&self, root_directory: impl Into<PathBuf>) -> PathBuf {
         let jailed_path = root_directory.into();
         let jailed_path = jailed_path.strip_prefix("/").unwrap();
         self.root_directory.join(jailed_path)
     }
+
+    /// The path firecracker opens a path of the jail at, a jailed firecracker
+    /// sees the root directory as `/`
+    pub fn vmm_path(&self, jailed_path: impl Into<PathBuf>) -> PathBuf {
+        match self.jailed {
+            true => jailed_path.into(),
+            false => self.resolve(jailed_path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process::Command,
+        time::{Duration, Instant},
+    };
+
+    use super::wait_until_reaped;
+
+    #[test]
+    fn processes_count_as_running_until_they_are_reaped() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = child.id() as i32;
+        child.kill().unwrap();
+        assert!(
+            !wait_until_reaped(pid, Duration::from_millis(50)),
+            "a killed process should be around until it's reaped"
+        );
+
+        let reaper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            child.wait().unwrap();
+        });
+        let start = Instant::now();
+        assert!(wait_until_reaped(pid, Duration::from_secs(5)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        reaper.join().unwrap();
+    }
 }
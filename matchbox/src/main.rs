@@ -1,14 +1,24 @@
+use std::time::Duration;
+
 use matchbox::dependency::DependencyFactory;
+use matchbox::server::config::ServerConfig;
 use matchbox::server::{Application, ApplicationState};
 
+/// How often template files are checked for changes
+const TEMPLATE_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let dependency_factory = DependencyFactory::default();
-    let app = Application::new(
-        "0.0.0.0:3000",
-        ApplicationState::new(dependency_factory.sandbox_provider()),
-    )
-    .await?;
+    let config = match std::env::var("MATCHBOX_CONFIG") {
+        Ok(path) => ServerConfig::from_file(path)?,
+        Err(_) => ServerConfig::default(),
+    };
+
+    let dependency_factory = DependencyFactory::default().with_server_config(&config);
+    let state = ApplicationState::with_config(dependency_factory.sandbox_provider(), config);
+    state.templates().reload()?;
+    state.templates().watch(TEMPLATE_RELOAD_INTERVAL);
+    let app = Application::new("0.0.0.0:3000", state).await?;
     app.run().await?;
 
     Ok(())
@@ -4,6 +4,8 @@ use rand::Rng;
 
 const MAX_NETWORK_START_BLOCK: u64 = 7199;
 const GROUPS_IN_LAST_BLOCK: u64 = 30;
+/// Unique local prefix the per sandbox /64 ipv6 networks are carved out of
+const IPV6_BASE_PREFIX: &str = "fd00:10:200";
 
 pub trait ProvideIdentifier: Debug + Send + Sync {
     fn provide_identifier(&self) -> VmIdentifier;
@@ -59,12 +61,15 @@ impl Default for VmIdentifier {
 pub struct AddressBlock {
     base_address: String,
     starting_ip: u64,
+    ipv6_prefix: String,
 }
 
 /// Computes an address block from a number. We give each network 4 ip
 /// addresses. The first one is the veth device and the second one is the vpeer
 /// device. The third ip is the NAT'ed ip for the microvm. The fourth IP is
 /// empty for now, maybe we'll find a use for it later.
+///
+/// Each block also gets its own ipv6 /64 which is handed out in the same order.
 impl From<u64> for AddressBlock {
     fn from(value: u64) -> Self {
         let block3 = value / GROUPS_IN_LAST_BLOCK;
@@ -73,6 +78,7 @@ impl From<u64> for AddressBlock {
         Self {
             base_address: format!("10.200.{block3}"),
             starting_ip: block4,
+            ipv6_prefix: format!("{IPV6_BASE_PREFIX}:{value:x}"),
         }
     }
 }
@@ -85,6 +91,10 @@ impl AddressBlock {
             self.starting_ip + index.into() + 1
         )
     }
+
+    pub fn get_ipv6(&self, index: impl Into<u64>) -> String {
+        format!("{}::{:x}", self.ipv6_prefix, index.into() + 1)
+    }
 }
 
 #[cfg(test)]
@@ -128,4 +138,19 @@ mod tests {
 
         assert!(block2.starting_ip == 0)
     }
+
+    #[test]
+    fn ipv6_prefix_per_block() {
+        let block1 = AddressBlock::from(0);
+        let block2 = AddressBlock::from(GROUPS_IN_LAST_BLOCK);
+        let last = AddressBlock::from(MAX_NETWORK_START_BLOCK);
+
+        assert_eq!(block1.get_ipv6(0u64), "fd00:10:200:0::1");
+        assert_eq!(block2.get_ipv6(2u64), "fd00:10:200:1e::3");
+        assert_eq!(last.get_ipv6(1u64), "fd00:10:200:1c1f::2");
+        assert_ne!(
+            block1.ipv6_prefix, block2.ipv6_prefix,
+            "every address block should get its own ipv6 prefix"
+        );
+    }
 }
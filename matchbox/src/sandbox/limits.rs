@@ -0,0 +1,493 @@
+use std::collections::HashMap;
+
+use firecracker_config_rs::{
+    models::{
+        rate_limiter::{RateLimiter, TokenBucket},
+        virtual_machine::VirtualMachine,
+    },
+    validation::ValidationErrors,
+};
+use serde::{Deserialize, Serialize};
+
+/// Token buckets refill every second, so their size is the rate per second
+const REFILL_TIME_MS: u64 = 1000;
+
+/// A sustained rate per second with an optional one time burst on top of it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rate {
+    pub per_second: u64,
+    #[serde(default)]
+    pub burst: Option<u64>,
+}
+
+impl Rate {
+    fn token_bucket(&self) -> TokenBucket {
+        TokenBucket {
+            size: self.per_second,
+            refill_time: REFILL_TIME_MS,
+            one_time_burst: self.burst,
+        }
+    }
+
+    /// Firecracker takes empty token buckets as no limit at all, so zero
+    /// rates are rejected rather than lifting the maximum
+    fn check(&self, max: Option<&Rate>, field: &str, errors: &mut ValidationErrors) {
+        if self.per_second == 0 {
+            errors.add(format!("{field}.per_second"), "Must be greater than 0");
+        }
+        if self.burst == Some(0) {
+            errors.add(format!("{field}.burst"), "Must be greater than 0");
+        }
+        let Some(max) = max else {
+            return;
+        };
+        if self.per_second > max.per_second {
+            errors.add(
+                format!("{field}.per_second"),
+                format!("Exceeds the maximum of {}/s", max.per_second),
+            );
+        }
+        if self.burst.unwrap_or_default() > max.burst.unwrap_or_default() {
+            errors.add(
+                format!("{field}.burst"),
+                format!("Exceeds the maximum of {}", max.burst.unwrap_or_default()),
+            );
+        }
+    }
+}
+
+/// Firecracker disables token buckets without a size
+const DISABLED_TOKEN_BUCKET: TokenBucket = TokenBucket {
+    size: 0,
+    refill_time: REFILL_TIME_MS,
+    one_time_burst: None,
+};
+
+/// Limits of a single network interface direction or drive, unset limits are
+/// unlimited
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeviceLimits {
+    /// Bytes per second
+    #[serde(default)]
+    pub bandwidth: Option<Rate>,
+    /// Operations per second, packets for network interfaces
+    #[serde(default)]
+    pub ops: Option<Rate>,
+}
+
+impl DeviceLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.bandwidth.is_none() && self.ops.is_none()
+    }
+
+    pub fn rate_limiter(&self) -> Option<RateLimiter> {
+        if self.is_unlimited() {
+            return None;
+        }
+
+        Some(RateLimiter {
+            bandwidth: self.bandwidth.as_ref().map(Rate::token_bucket),
+            ops: self.ops.as_ref().map(Rate::token_bucket),
+        })
+    }
+
+    /// A rate limiter replacing the device's current one in a PATCH, limits
+    /// that aren't set are removed
+    pub fn replacing_rate_limiter(&self) -> RateLimiter {
+        RateLimiter {
+            bandwidth: Some(
+                self.bandwidth
+                    .as_ref()
+                    .map_or(DISABLED_TOKEN_BUCKET, Rate::token_bucket),
+            ),
+            ops: Some(
+                self.ops
+                    .as_ref()
+                    .map_or(DISABLED_TOKEN_BUCKET, Rate::token_bucket),
+            ),
+        }
+    }
+
+    /// Takes the limits that aren't set from `fallback`
+    fn or(self, fallback: DeviceLimits) -> DeviceLimits {
+        DeviceLimits {
+            bandwidth: self.bandwidth.or(fallback.bandwidth),
+            ops: self.ops.or(fallback.ops),
+        }
+    }
+
+    fn check(&self, max: &DeviceLimits, field: &str, errors: &mut ValidationErrors) {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.check(
+                max.bandwidth.as_ref(),
+                &format!("{field}.bandwidth"),
+                errors,
+            );
+        }
+        if let Some(ops) = &self.ops {
+            ops.check(max.ops.as_ref(), &format!("{field}.ops"), errors);
+        }
+    }
+}
+
+/// Limits of a sandbox's devices
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SandboxLimits {
+    /// Traffic received by the sandbox
+    #[serde(default)]
+    pub network_rx: DeviceLimits,
+    /// Traffic sent by the sandbox
+    #[serde(default)]
+    pub network_tx: DeviceLimits,
+    /// Limits by drive id
+    #[serde(default)]
+    pub drives: HashMap<String, DeviceLimits>,
+}
+
+impl SandboxLimits {
+    /// Takes the limits that aren't set from `fallback`
+    pub fn or(&self, fallback: &SandboxLimits) -> SandboxLimits {
+        let mut drives = fallback.drives.clone();
+        for (drive_id, limits) in &self.drives {
+            let fallback = drives.get(drive_id).copied().unwrap_or_default();
+            drives.insert(drive_id.clone(), limits.or(fallback));
+        }
+
+        SandboxLimits {
+            network_rx: self.network_rx.or(fallback.network_rx),
+            network_tx: self.network_tx.or(fallback.network_tx),
+            drives,
+        }
+    }
+
+    /// Sets the rate limiters of the devices with limits
+    pub fn apply(&self, config: &mut VirtualMachine) {
+        for interface in &mut config.network_interfaces {
+            if let Some(limiter) = self.network_rx.rate_limiter() {
+                interface.rx_rate_limiter = Some(limiter);
+            }
+            if let Some(limiter) = self.network_tx.rate_limiter() {
+                interface.tx_rate_limiter = Some(limiter);
+            }
+        }
+
+        for drive in &mut config.drives {
+            let limiter = self
+                .drives
+                .get(&drive.drive_id)
+                .and_then(DeviceLimits::rate_limiter);
+            if let Some(limiter) = limiter {
+                drive.rate_limiter = Some(limiter);
+            }
+        }
+    }
+}
+
+/// New limits of a running sandbox's devices. The limits of a device are
+/// replaced as a whole, so `{}` removes them, devices which aren't set keep
+/// their limits.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LimitsUpdate {
+    #[serde(default)]
+    pub network_rx: Option<DeviceLimits>,
+    #[serde(default)]
+    pub network_tx: Option<DeviceLimits>,
+    /// Limits by drive id
+    #[serde(default)]
+    pub drives: HashMap<String, DeviceLimits>,
+}
+
+/// The same set of limits for every sandbox
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LimitProfile {
+    #[serde(default)]
+    pub network_rx: DeviceLimits,
+    #[serde(default)]
+    pub network_tx: DeviceLimits,
+    /// Limits of every drive
+    #[serde(default)]
+    pub drive: DeviceLimits,
+}
+
+/// Server side limits, sandboxes get the default limits unless they ask for
+/// something else & can never exceed the maximum
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LimitPolicy {
+    #[serde(default)]
+    pub default: LimitProfile,
+    #[serde(default)]
+    pub max: LimitProfile,
+}
+
+impl LimitPolicy {
+    /// Limits of a new sandbox with the given drives
+    pub fn resolve(
+        &self,
+        requested: &SandboxLimits,
+        drive_ids: &[&str],
+    ) -> anyhow::Result<SandboxLimits> {
+        let defaults = SandboxLimits {
+            network_rx: self.default.network_rx,
+            network_tx: self.default.network_tx,
+            drives: drive_ids
+                .iter()
+                .map(|drive_id| (drive_id.to_string(), self.default.drive))
+                .collect(),
+        };
+
+        let mut limits = SandboxLimits {
+            network_rx: requested.network_rx.or(defaults.network_rx),
+            network_tx: requested.network_tx.or(defaults.network_tx),
+            drives: defaults.drives,
+        };
+        let mut errors = ValidationErrors::default();
+        for (drive_id, drive_limits) in &requested.drives {
+            match limits.drives.get_mut(drive_id) {
+                Some(limits) => *limits = drive_limits.or(*limits),
+                None => unknown_drive(drive_id, &mut errors),
+            }
+        }
+        errors.into_result()?;
+
+        Ok(SandboxLimits {
+            network_rx: self.enforce_max(limits.network_rx, &self.max.network_rx, "network_rx")?,
+            network_tx: self.enforce_max(limits.network_tx, &self.max.network_tx, "network_tx")?,
+            drives: self.enforce_max_of_drives(&limits.drives)?,
+        })
+    }
+
+    /// Limits to change on a running sandbox, devices which weren't asked for
+    /// keep their current ones
+    pub fn resolve_update(
+        &self,
+        requested: &LimitsUpdate,
+        drive_ids: &[&str],
+    ) -> anyhow::Result<LimitsUpdate> {
+        let mut errors = ValidationErrors::default();
+        for drive_id in requested.drives.keys() {
+            if !drive_ids.contains(&drive_id.as_str()) {
+                unknown_drive(drive_id, &mut errors);
+            }
+        }
+        errors.into_result()?;
+
+        let max = &self.max;
+        Ok(LimitsUpdate {
+            network_rx: requested
+                .network_rx
+                .map(|limits| self.enforce_max(limits, &max.network_rx, "network_rx"))
+                .transpose()?,
+            network_tx: requested
+                .network_tx
+                .map(|limits| self.enforce_max(limits, &max.network_tx, "network_tx"))
+                .transpose()?,
+            drives: self.enforce_max_of_drives(&requested.drives)?,
+        })
+    }
+
+    /// Rejects limits above the maximum, limits that aren't set are capped at
+    /// the maximum
+    fn enforce_max(
+        &self,
+        limits: DeviceLimits,
+        max: &DeviceLimits,
+        field: &str,
+    ) -> Result<DeviceLimits, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        limits.check(max, field, &mut errors);
+        errors.into_result()?;
+
+        Ok(limits.or(*max))
+    }
+
+    fn enforce_max_of_drives(
+        &self,
+        drives: &HashMap<String, DeviceLimits>,
+    ) -> Result<HashMap<String, DeviceLimits>, ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        let mut limits = HashMap::new();
+        for (drive_id, drive_limits) in drives {
+            let field = format!("drives.{drive_id}");
+            match self.enforce_max(*drive_limits, &self.max.drive, &field) {
+                Ok(drive_limits) => {
+                    limits.insert(drive_id.clone(), drive_limits);
+                }
+                Err(drive_errors) => errors.errors.extend(drive_errors.errors),
+            }
+        }
+        errors.into_result()?;
+
+        Ok(limits)
+    }
+}
+
+fn unknown_drive(drive_id: &str, errors: &mut ValidationErrors) {
+    errors.add(
+        format!("drives.{drive_id}"),
+        format!("Drive {drive_id} does not exist"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DeviceLimits, LimitPolicy, LimitProfile, LimitsUpdate, Rate, SandboxLimits,
+        ValidationErrors, DISABLED_TOKEN_BUCKET,
+    };
+
+    fn rate(per_second: u64) -> Option<Rate> {
+        Some(Rate {
+            per_second,
+            burst: None,
+        })
+    }
+
+    fn policy() -> LimitPolicy {
+        LimitPolicy {
+            default: LimitProfile {
+                network_tx: DeviceLimits {
+                    bandwidth: rate(1_000),
+                    ops: None,
+                },
+                drive: DeviceLimits {
+                    bandwidth: None,
+                    ops: rate(100),
+                },
+                ..Default::default()
+            },
+            max: LimitProfile {
+                network_tx: DeviceLimits {
+                    bandwidth: rate(10_000),
+                    ops: rate(500),
+                },
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn defaults_fill_in_what_was_not_requested() {
+        let requested = SandboxLimits {
+            network_tx: DeviceLimits {
+                bandwidth: rate(5_000),
+                ops: None,
+            },
+            drives: [(
+                "vdb".to_string(),
+                DeviceLimits {
+                    bandwidth: rate(2_000),
+                    ops: None,
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+
+        let limits = policy().resolve(&requested, &["rootfs", "vdb"]).unwrap();
+
+        assert_eq!(limits.network_rx, DeviceLimits::default());
+        assert_eq!(
+            limits.network_tx,
+            DeviceLimits {
+                bandwidth: rate(5_000),
+                ops: rate(500),
+            },
+            "ops should be capped at the maximum"
+        );
+        assert_eq!(limits.drives["rootfs"].ops, rate(100));
+        assert_eq!(limits.drives["vdb"].bandwidth, rate(2_000));
+        assert_eq!(limits.drives["vdb"].ops, rate(100));
+    }
+
+    #[test]
+    fn limits_above_the_maximum_are_rejected() {
+        let requested = SandboxLimits {
+            network_tx: DeviceLimits {
+                bandwidth: rate(20_000),
+                ops: None,
+            },
+            ..Default::default()
+        };
+
+        let error = policy().resolve(&requested, &["rootfs"]).unwrap_err();
+        assert!(error.is::<ValidationErrors>());
+        let update = LimitsUpdate {
+            network_tx: Some(requested.network_tx),
+            ..Default::default()
+        };
+        let error = policy().resolve_update(&update, &["rootfs"]).unwrap_err();
+        assert!(error.is::<ValidationErrors>());
+    }
+
+    #[test]
+    fn zero_rates_are_rejected() {
+        let requested = SandboxLimits {
+            network_rx: DeviceLimits {
+                bandwidth: rate(0),
+                ops: Some(Rate {
+                    per_second: 10,
+                    burst: Some(0),
+                }),
+            },
+            ..Default::default()
+        };
+
+        let error = policy().resolve(&requested, &["rootfs"]).unwrap_err();
+        let errors = error.downcast::<ValidationErrors>().unwrap();
+        let paths: Vec<_> = errors
+            .errors
+            .iter()
+            .map(|error| error.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            ["network_rx.bandwidth.per_second", "network_rx.ops.burst"]
+        );
+        let update = LimitsUpdate {
+            network_rx: Some(requested.network_rx),
+            ..Default::default()
+        };
+        assert!(policy().resolve_update(&update, &["rootfs"]).is_err());
+    }
+
+    #[test]
+    fn unknown_drives_are_rejected() {
+        let requested = SandboxLimits {
+            drives: [("vdz".to_string(), DeviceLimits::default())].into(),
+            ..Default::default()
+        };
+
+        assert!(policy().resolve(&requested, &["rootfs"]).is_err());
+        let update = LimitsUpdate {
+            drives: requested.drives,
+            ..Default::default()
+        };
+        assert!(policy().resolve_update(&update, &["rootfs"]).is_err());
+    }
+
+    #[test]
+    fn updates_only_cover_the_requested_devices() {
+        let update = LimitsUpdate {
+            network_tx: Some(DeviceLimits::default()),
+            ..Default::default()
+        };
+
+        let limits = policy().resolve_update(&update, &["rootfs"]).unwrap();
+
+        assert_eq!(limits.network_rx, None);
+        assert_eq!(
+            limits.network_tx,
+            Some(policy().max.network_tx),
+            "removed limits should be capped at the maximum"
+        );
+        assert!(limits.drives.is_empty());
+    }
+
+    #[test]
+    fn removed_limits_disable_the_token_buckets() {
+        let limiter = DeviceLimits::default().replacing_rate_limiter();
+
+        assert_eq!(limiter.bandwidth, Some(DISABLED_TOKEN_BUCKET));
+        assert_eq!(limiter.ops, Some(DISABLED_TOKEN_BUCKET));
+    }
+}
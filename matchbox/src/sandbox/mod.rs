@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::OpenOptions;
 
@@ -7,28 +8,69 @@ use std::process::Command;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use anyhow::Context;
 use derive_builder::Builder;
+use firecracker_config_rs::models::action::Action;
 use firecracker_config_rs::models::bootsource::BootSourceBuilder;
-use firecracker_config_rs::models::drive::DriveBuilder;
+use firecracker_config_rs::models::drive::{Drive, DriveBuilder, PartialDriveBuilder};
+use firecracker_config_rs::models::instance_info::InstanceState;
 use firecracker_config_rs::models::logger::{LogLevel, LoggerBuilder};
-use firecracker_config_rs::models::network_interface::NetworkInterfaceBuilder;
+use firecracker_config_rs::models::machine_config::MachineConfigurationBuilder;
+use firecracker_config_rs::models::network_interface::{
+    NetworkInterface, NetworkInterfaceBuilder, PartialNetworkInterfaceBuilder,
+};
 use firecracker_config_rs::models::virtual_machine::{VirtualMachine, VirtualMachineBuilder};
+use firecracker_config_rs::models::vsock::VsockBuilder;
+use firecracker_config_rs::validation::{Validate, ValidationErrors};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{Mutex, MutexGuard};
 
-use crate::jailer::client::Action;
-use crate::jailer::factory::ProvideFirecracker;
+use crate::jailer::client::FirecrackerClient;
+use crate::jailer::factory::{ProvideFirecracker, ProvideFirecrackerOptionsBuilder};
 use crate::jailer::{FirecrackerProcess, PathResolver};
+use crate::server::routes::error::NotFound;
 use crate::util::{self, copy};
 
 use self::id::{ProvideIdentifier, VmIdentifier};
-use self::network::Network;
+use self::limits::{LimitsUpdate, SandboxLimits};
+use self::network::factory::ProvideNetwork;
+use self::network::pcap::{CaptureOptions, CaptureStatus, PacketCapture};
+use self::network::private::{PRIVATE_GUEST_INTERFACE, PRIVATE_TAP_DEVICE};
+use self::network::{
+    NetworkOptions, OfflineNetns, SandboxNetwork, GUEST_GATEWAY, GUEST_GATEWAY_IPV6, GUEST_IP,
+    GUEST_IPV6, PRIMARY_TAP_DEVICE,
+};
+use self::resources::SandboxResources;
 use self::spark::factory::ProvideSparkClient;
-use self::spark::SparkClient;
+use self::spark::{SparkAddress, SparkClient};
+use self::template::{SandboxImage, SandboxTemplate};
+use self::usage::{RecordUsage, SandboxUsage, StdoutUsageRecorder};
 
 pub mod id;
+pub mod limits;
 pub mod network;
+pub mod resources;
 pub mod spark;
+pub mod template;
+pub mod usage;
+
+pub const ROOTFS_DRIVE_ID: &str = "rootfs";
+pub const CODE_DRIVE_ID: &str = "vdb";
+/// Drives every sandbox has
+pub const DRIVE_IDS: [&str; 2] = [ROOTFS_DRIVE_ID, CODE_DRIVE_ID];
+/// Captures of a sandbox which may run at once, each holds a socket & a file
+/// growing on the host
+pub const MAX_RUNNING_CAPTURES: usize = 4;
+
+/// Kernel command line of sandboxes whose template has none
+const DEFAULT_BOOT_ARGS: &str = "console=ttyS0 reboot=k panic=1 pci=off random.trust_cpu=on";
+/// Jailed path of firecracker's log file
+const LOG_PATH: &str = "/log/firecracker.log";
+
+/// Context id of the microvm on its vsock device
+const GUEST_CID: u32 = 3;
+/// Jailed path of the Unix socket backing the microvm's vsock device
+const VSOCK_SOCKET_PATH: &str = "/run/vsock.socket";
 
 #[derive(Debug, Clone, Copy)]
 pub enum SandboxState {
@@ -41,10 +83,18 @@ pub enum SandboxState {
 pub struct Sandbox {
     id: VmIdentifier,
     state: SandboxState,
-    network: Network,
+    network: Option<Box<dyn SandboxNetwork>>,
+    /// Where firecracker runs when the sandbox has no network
+    offline_netns: Option<OfflineNetns>,
     pub jailed_firecracker: FirecrackerProcess,
-    virtual_machine_config: VirtualMachine,
+    /// Locked by changes to the running microvm's devices
+    virtual_machine_config: Mutex<VirtualMachine>,
+    /// The initializer's image when unset
+    image: Option<SandboxImage>,
     client: Mutex<SparkClient>,
+    created_at: Instant,
+    captures: Mutex<HashMap<String, PacketCapture>>,
+    usage_recorder: Arc<Box<dyn RecordUsage>>,
 }
 
 impl Sandbox {
@@ -52,23 +102,236 @@ impl Sandbox {
         self.id.id()
     }
 
-    pub fn network(&self) -> &Network {
-        &self.network
+    /// The sandbox's network, sandboxes created without one have no network
+    /// devices at all
+    pub fn network(&self) -> Option<&dyn SandboxNetwork> {
+        self.network.as_deref()
+    }
+
+    /// Ids of the microvm's drives
+    pub async fn drive_ids(&self) -> Vec<String> {
+        self.virtual_machine_config
+            .lock()
+            .await
+            .drives
+            .iter()
+            .map(|drive| drive.drive_id.clone())
+            .collect()
     }
 
     pub fn path_resolver(&self) -> &PathResolver {
         &self.jailed_firecracker.path_resolver
     }
 
-    pub async fn client(&self) -> MutexGuard<SparkClient> {
+    pub fn usage(&self) -> anyhow::Result<SandboxUsage> {
+        Ok(SandboxUsage {
+            id: self.id().to_string(),
+            uptime_secs: self.created_at.elapsed().as_secs(),
+            network: self
+                .network()
+                .map(|network| network.traffic())
+                .transpose()?,
+        })
+    }
+
+    /// Directory of the VM on the host, the jail's root is one of its children
+    fn vm_directory(&self) -> PathBuf {
+        let root_directory = self.path_resolver().resolve("/");
+        root_directory.parent().unwrap().to_path_buf()
+    }
+
+    /// Starts a packet capture, the capture file is kept outside of the jail
+    /// so neither the guest nor firecracker can touch it
+    pub async fn start_capture(&self, options: CaptureOptions) -> anyhow::Result<String> {
+        options.validate()?;
+        let mut errors = ValidationErrors::default();
+        let Some(network) = &self.network else {
+            errors.add("network", "The sandbox has no network to capture");
+            return Err(errors.into());
+        };
+
+        let mut captures = self.captures.lock().await;
+        let running = captures
+            .values()
+            .filter(|capture| !capture.is_finished())
+            .count();
+        if running >= MAX_RUNNING_CAPTURES {
+            errors.add(
+                "captures",
+                format!("At most {MAX_RUNNING_CAPTURES} captures can run at once"),
+            );
+            return Err(errors.into());
+        }
+
+        let capture_id = uuid::Uuid::new_v4().to_string();
+        let path = self
+            .vm_directory()
+            .join("captures")
+            .join(format!("{capture_id}.pcap"));
+        let capture = network.start_capture(options, path)?;
+
+        captures.insert(capture_id.clone(), capture);
+        Ok(capture_id)
+    }
+
+    pub async fn capture_status(&self, capture_id: &str) -> anyhow::Result<CaptureStatus> {
+        match self.captures.lock().await.get(capture_id) {
+            Some(capture) => Ok(capture.status()),
+            None => Err(unknown_capture(capture_id)),
+        }
+    }
+
+    /// Path of the capture file, once the capture has finished
+    pub async fn capture_file(&self, capture_id: &str) -> anyhow::Result<PathBuf> {
+        let captures = self.captures.lock().await;
+        let Some(capture) = captures.get(capture_id) else {
+            return Err(unknown_capture(capture_id));
+        };
+        if !capture.is_finished() {
+            let mut errors = ValidationErrors::default();
+            errors.add(
+                "capture_id",
+                format!("Capture {capture_id} is still running"),
+            );
+            return Err(errors.into());
+        }
+
+        Ok(capture.path().to_path_buf())
+    }
+
+    /// Replaces the rate limits of the running microvm's devices in `limits`,
+    /// other devices keep their current ones
+    pub async fn update_limits(&self, limits: &LimitsUpdate) -> anyhow::Result<()> {
+        let mut config = self.virtual_machine_config.lock().await;
+
+        if limits.network_rx.is_some() || limits.network_tx.is_some() {
+            for interface in &mut config.network_interfaces {
+                let mut update = PartialNetworkInterfaceBuilder::default();
+                update.iface_id(&interface.iface_id);
+                if let Some(rx) = &limits.network_rx {
+                    update.rx_rate_limiter(rx.replacing_rate_limiter());
+                }
+                if let Some(tx) = &limits.network_tx {
+                    update.tx_rate_limiter(tx.replacing_rate_limiter());
+                }
+                self.vmm().patch_network_interface(&update.build()?).await?;
+
+                if let Some(rx) = &limits.network_rx {
+                    interface.rx_rate_limiter = rx.rate_limiter();
+                }
+                if let Some(tx) = &limits.network_tx {
+                    interface.tx_rate_limiter = tx.rate_limiter();
+                }
+            }
+        }
+
+        for drive in &mut config.drives {
+            let Some(drive_limits) = limits.drives.get(&drive.drive_id) else {
+                continue;
+            };
+            let update = PartialDriveBuilder::default()
+                .drive_id(&drive.drive_id)
+                .rate_limiter(drive_limits.replacing_rate_limiter())
+                .build()?;
+            self.vmm().patch_drive(&update).await?;
+            drive.rate_limiter = drive_limits.rate_limiter();
+        }
+
+        Ok(())
+    }
+
+    /// Swaps the backing file of a drive of the running microvm for a copy of
+    /// `location` & responds with the drive. The guest unmounts the drive
+    /// while firecracker switches files.
+    pub async fn swap_drive(&self, drive_id: &str, location: &Location) -> anyhow::Result<Drive> {
+        let index = self.swappable_drive(drive_id).await?;
+        let source = location.to_local_path()?;
+
+        // Every swap gets a new file so the previous one stays intact until
+        // firecracker switched. It's copied before the drive is locked as
+        // drives can be large.
+        let path_on_host =
+            PathBuf::from(format!("/drives/{drive_id}-{}.ext4", uuid::Uuid::new_v4()));
+        let file = self.path_resolver().resolve(&path_on_host);
+        copy(source, &file)?;
+        if let Err(e) = self.jailed_firecracker.chown(&file) {
+            self.remove_drive_file(&file);
+            return Err(e);
+        }
+
+        let mut config = self.virtual_machine_config.lock().await;
+        let mut client = self.client.lock().await;
+        if let Err(e) = client.unmount_drive(guest_mount_path(drive_id)).await {
+            self.remove_drive_file(&file);
+            return Err(e);
+        }
+        let update = PartialDriveBuilder::default()
+            .drive_id(drive_id)
+            .path_on_host(self.path_resolver().vmm_path(&path_on_host))
+            .build()?;
+        if let Err(e) = self.vmm().patch_drive(&update).await {
+            // The guest gets the previous file back
+            self.remove_drive_file(&file);
+            client
+                .mount_drive(guest_device(index), guest_mount_path(drive_id))
+                .await?;
+            return Err(e.into());
+        }
+
+        let drive = &mut config.drives[index];
+        let previous = std::mem::replace(&mut drive.path_on_host, path_on_host);
+        let drive = drive.clone();
+        client
+            .mount_drive(guest_device(index), guest_mount_path(drive_id))
+            .await?;
+        self.remove_drive_file(&self.path_resolver().resolve(previous));
+
+        Ok(drive)
+    }
+
+    /// Index of the drive that can be swapped
+    async fn swappable_drive(&self, drive_id: &str) -> anyhow::Result<usize> {
+        let config = self.virtual_machine_config.lock().await;
+        let mut errors = ValidationErrors::default();
+        let index = config
+            .drives
+            .iter()
+            .position(|drive| drive.drive_id == drive_id);
+        match index.map(|index| &config.drives[index]) {
+            None => errors.add("drive_id", format!("Drive {drive_id} does not exist")),
+            Some(drive) if drive.is_root_device => {
+                errors.add("drive_id", "The root device can not be swapped")
+            }
+            Some(_) => {}
+        }
+        errors.into_result()?;
+
+        Ok(index.unwrap())
+    }
+
+    /// Removes a file no drive uses anymore, failing to do so only wastes
+    /// space
+    fn remove_drive_file(&self, file: &Path) {
+        if let Err(e) = std::fs::remove_file(file) {
+            println!(
+                "failed to remove drive file {} of sandbox {}: {e:?}",
+                file.display(),
+                self.id()
+            );
+        }
+    }
+
+    /// Firecracker's API
+    fn vmm(&self) -> &FirecrackerClient {
+        &self.jailed_firecracker.client
+    }
+
+    pub async fn client(&self) -> MutexGuard<'_, SparkClient> {
         self.client.lock().await
     }
 
     pub async fn start(&mut self) -> anyhow::Result<()> {
-        self.jailed_firecracker
-            .client
-            .action(Action::InstanceStart)
-            .await?;
+        self.vmm().action(Action::InstanceStart).await?;
         self.state = SandboxState::Running;
         Ok(())
     }
@@ -76,13 +339,26 @@ impl Sandbox {
 
 impl Drop for Sandbox {
     fn drop(&mut self) {
+        // Final usage record, taken first as the traffic counters go away
+        // with the network
+        match self.usage() {
+            Ok(usage) => self.usage_recorder.record_usage(usage),
+            Err(e) => println!("failed to collect usage of sandbox {}: {e:?}", self.id()),
+        }
+
+        // Looked up before the tmux session is gone
+        let pid = self.jailed_firecracker.pid(self.id());
         let mut cmd = Command::new("tmux");
         cmd.args(["kill-session", "-t", self.id()])
             .output()
             .unwrap();
-        let root_directory = self.path_resolver().resolve("/");
-        let vm_directory = root_directory.parent().unwrap();
-        std::fs::remove_dir_all(vm_directory).unwrap();
+        if let Err(e) = self.jailed_firecracker.kill_daemon() {
+            println!("failed to kill firecracker of sandbox {}: {e:?}", self.id());
+        }
+        self.jailed_firecracker.release_owner(pid);
+        // Removed once firecracker no longer runs in it
+        drop(self.offline_netns.take());
+        std::fs::remove_dir_all(self.vm_directory()).unwrap();
     }
 }
 
@@ -95,27 +371,50 @@ pub enum Location {
 
 impl Location {
     pub fn to_local_path(&self) -> anyhow::Result<PathBuf> {
+        let mut errors = ValidationErrors::default();
         match self {
             Location::Local { path } => {
                 let path = PathBuf::from(path);
-                if !path.exists() {
-                    anyhow::bail!("path {} doesn't exist", path.display());
+                if path.exists() {
+                    return Ok(path);
                 }
-
-                Ok(path)
+                errors.add(
+                    "location.path",
+                    format!("Path {} doesn't exist", path.display()),
+                );
             }
-            Location::CloudStorage { path } => {
-                unimplemented!("Google cloud storage not yet implemented")
+            Location::CloudStorage { path: _ } => {
+                errors.add("location.type", "Cloud storage locations aren't supported")
             }
         }
+
+        Err(errors.into())
     }
 }
 
-#[derive(Builder, Debug, Default)]
+#[derive(Builder, Debug)]
 #[builder(setter(into))]
 pub struct ProvideSandboxOptions {
     #[builder(setter(strip_option), default)]
     code_drive_location: Option<Location>,
+    /// Sandboxes without network options get no network devices at all
+    #[builder(default = "Some(NetworkOptions::default())")]
+    network: Option<NetworkOptions>,
+    /// Rate limits of the sandbox's devices
+    #[builder(default)]
+    limits: SandboxLimits,
+    #[builder(default)]
+    resources: SandboxResources,
+    /// Image, kernel command line, logger & extra drives of the sandbox. The
+    /// template's resources, network & limits are resolved by the caller.
+    #[builder(setter(strip_option), default)]
+    template: Option<Arc<SandboxTemplate>>,
+}
+
+impl Default for ProvideSandboxOptions {
+    fn default() -> Self {
+        ProvideSandboxOptionsBuilder::default().build().unwrap()
+    }
 }
 
 #[async_trait::async_trait]
@@ -130,13 +429,41 @@ impl ProvideSandbox for SandboxFactory {
     }
 }
 
+/// Name of the virtio-vsock transport, kernels with it built in carry it
+const VSOCK_DRIVER: &[u8] = b"vmw_vsock_virtio_transport";
+
+/// What the kernel sandboxes boot from supports
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageCapabilities {
+    /// The kernel has virtio-vsock, spark-server has to listen on it
+    pub vsock: bool,
+}
+
+impl ImageCapabilities {
+    /// Looks for drivers in an uncompressed kernel image, the only kind
+    /// firecracker boots
+    pub fn inspect(kernel: &Path) -> anyhow::Result<ImageCapabilities> {
+        let kernel = std::fs::read(kernel)
+            .with_context(|| format!("Failed to read kernel {}", kernel.display()))?;
+        Ok(ImageCapabilities {
+            vsock: kernel
+                .windows(VSOCK_DRIVER.len())
+                .any(|window| window == VSOCK_DRIVER),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct SandboxFactory {
     identifier_factory: Arc<Box<dyn ProvideIdentifier>>,
     spark_factory: Arc<Box<dyn ProvideSparkClient>>,
     firecracker_factory: Arc<Box<dyn ProvideFirecracker>>,
+    network_factory: Arc<Box<dyn ProvideNetwork>>,
     sandbox_initializer: Arc<Box<dyn InitializeSandbox>>,
     dummy_drive_path: PathBuf,
+    /// Kernel of sandboxes whose template has no image
+    kernel_image: PathBuf,
+    usage_recorder: Arc<Box<dyn RecordUsage>>,
 }
 
 impl SandboxFactory {
@@ -144,82 +471,185 @@ impl SandboxFactory {
         identifier_factory: Arc<Box<dyn ProvideIdentifier>>,
         spark_factory: Arc<Box<dyn ProvideSparkClient>>,
         firecracker_factory: Arc<Box<dyn ProvideFirecracker>>,
+        network_factory: Arc<Box<dyn ProvideNetwork>>,
         sandbox_initializer: Arc<Box<dyn InitializeSandbox>>,
         dummy_drive_path: PathBuf,
+        kernel_image: PathBuf,
     ) -> SandboxFactory {
         SandboxFactory {
             identifier_factory,
             spark_factory,
             firecracker_factory,
+            network_factory,
             sandbox_initializer,
             dummy_drive_path,
+            kernel_image,
+            usage_recorder: Arc::new(Box::<StdoutUsageRecorder>::default()),
+        }
+    }
+
+    /// Where the usage of deleted sandboxes goes, stdout by default
+    pub fn with_usage_recorder(self, usage_recorder: Arc<Box<dyn RecordUsage>>) -> Self {
+        Self {
+            usage_recorder,
+            ..self
         }
     }
 
     pub async fn spawn_sandbox(&self, options: ProvideSandboxOptions) -> anyhow::Result<Sandbox> {
         let id = self.identifier_factory.provide_identifier();
-        let virtual_machine_config = VirtualMachineBuilder::default()
-            .logger(
-                LoggerBuilder::default()
-                    .log_path("/log/firecracker.log")
+        let template = options.template.clone().unwrap_or_default();
+        let (network, offline_netns) = match &options.network {
+            Some(network_options) => {
+                let network = self.network_factory.provide_network(
+                    &id,
+                    &[primary_network_interface()?],
+                    network_options,
+                )?;
+                (Some(network), None)
+            }
+            None => {
+                let kernel = template
+                    .image
+                    .as_ref()
+                    .map_or(&self.kernel_image, |image| &image.kernel);
+                if !ImageCapabilities::inspect(kernel)?.vsock {
+                    let mut errors = ValidationErrors::default();
+                    errors.add(
+                        "network",
+                        format!(
+                            "Sandboxes without a network are reached over vsock, which kernel {} doesn't support",
+                            kernel.display()
+                        ),
+                    );
+                    return Err(errors.into());
+                }
+                (None, self.network_factory.provide_offline_netns(&id)?)
+            }
+        };
+
+        let mut logger = match &template.logger {
+            Some(logger) => logger.clone(),
+            None => {
+                let mut logger = LoggerBuilder::default();
+                logger
                     .level(LogLevel::Info)
                     .show_level(true)
-                    .show_log_origin(true)
-                    .build()?
-            )
+                    .show_log_origin(true);
+                logger
+            }
+        };
+        let mut drives = vec![
+            DriveBuilder::default()
+                .drive_id(ROOTFS_DRIVE_ID)
+                .path_on_host("/drives/rootfs.ext4")
+                .is_root_device(true)
+                .is_read_only(false)
+                .build()?,
+            DriveBuilder::default()
+                .drive_id(CODE_DRIVE_ID)
+                .path_on_host("/drives/code-drive.ext4")
+                .is_root_device(false)
+                .is_read_only(false)
+                .build()?,
+        ];
+        for drive in &template.drives {
+            drives.push(
+                drive
+                    .options
+                    .clone()
+                    .drive_id(&drive.drive_id)
+                    .path_on_host(template_drive_path(&drive.drive_id))
+                    .is_root_device(false)
+                    .build()?,
+            );
+        }
+
+        let mut virtual_machine_config = VirtualMachineBuilder::default()
+            .logger(logger.log_path(LOG_PATH).build()?)
             .boot_source(
                 BootSourceBuilder::default()
                     .kernel_image_path("/kernel.bin")
-                    .boot_args("console=ttyS0 reboot=k panic=1 pci=off random.trust_cpu=on IP_ADDRESS::172.16.0.2 IFACE::eth0 GATEWAY::172.16.0.1")
-                    .build()?
+                    .boot_args(boot_args(
+                        template.boot_args.as_deref().unwrap_or(DEFAULT_BOOT_ARGS),
+                        network.as_deref(),
+                    ))
+                    .build()?,
+            )
+            .machine_config(
+                MachineConfigurationBuilder::default()
+                    .vcpu_count(options.resources.vcpus)
+                    .mem_size_mib(options.resources.memory_mib)
+                    .build()?,
             )
-            .drives(vec![
-                DriveBuilder::default()
-                    .drive_id("rootfs")
-                    .path_on_host("/drives/rootfs.ext4")
-                    .is_root_device(true)
-                    .is_read_only(false)
-                    .build()?
-            ])
-            .network_interfaces(vec![
-                NetworkInterfaceBuilder::default()
-                .host_dev_name("tap0")
-                .iface_id("eth0")
-                .guest_mac("06:00:AC:10:00:02")
-                .build()?
-            ])
+            .drives(drives)
+            .network_interfaces(network_interfaces(network.as_deref())?)
             .build()?;
-        let network = Network::new(&id, &virtual_machine_config.network_interfaces)?;
+        if network.is_none() {
+            virtual_machine_config.vsock = Some(
+                VsockBuilder::default()
+                    .guest_cid(GUEST_CID)
+                    .uds_path(VSOCK_SOCKET_PATH)
+                    .build()?,
+            );
+        }
+        options.limits.apply(&mut virtual_machine_config);
+        virtual_machine_config.validate()?;
+
+        let mut firecracker_options = ProvideFirecrackerOptionsBuilder::default();
+        firecracker_options
+            .id(id.id())
+            .resources(options.resources.clone());
+        if let Some(network) = &network {
+            firecracker_options.netns(network.netns_path()?);
+        } else if let Some(netns) = &offline_netns {
+            firecracker_options.netns(netns.path()?);
+        }
         let jailed_firecracker = self
             .firecracker_factory
-            .provide_firecracker(id.id(), &network.netns_path()?);
+            .provide_firecracker(&firecracker_options.build()?)?;
+
+        let spark_address = match &network {
+            Some(network) => SparkAddress::Tcp {
+                ip: network.microvm_ip(),
+            },
+            None => SparkAddress::Vsock {
+                uds_path: jailed_firecracker.path_resolver.resolve(VSOCK_SOCKET_PATH),
+            },
+        };
 
         let mut sandbox = Sandbox {
             id,
             state: SandboxState::Stopped,
             jailed_firecracker,
-            virtual_machine_config,
+            virtual_machine_config: Mutex::new(virtual_machine_config),
+            image: template.image.clone(),
             client: Mutex::new(
                 self.spark_factory
-                    .provide_spark_client(&network.microvm_ip())
+                    .provide_spark_client(&spark_address)
                     .await?,
             ),
             network,
+            offline_netns,
+            created_at: Instant::now(),
+            captures: Default::default(),
+            usage_recorder: self.usage_recorder.clone(),
         };
 
+        let code_drive_path = sandbox.path_resolver().resolve("/drives/code-drive.ext4");
         copy_if_exists(
             &options.code_drive_location,
-            sandbox.path_resolver().resolve("/drives/code-drive.ext4"),
+            code_drive_path.clone(),
             &self.dummy_drive_path,
         )?;
-        sandbox.virtual_machine_config.drives.push(
-            DriveBuilder::default()
-                .drive_id("vdb")
-                .path_on_host("/drives/code-drive.ext4")
-                .is_root_device(false)
-                .is_read_only(false)
-                .build()?,
-        );
+        sandbox.jailed_firecracker.chown(code_drive_path)?;
+        for drive in &template.drives {
+            let drive_path = sandbox
+                .path_resolver()
+                .resolve(template_drive_path(&drive.drive_id));
+            copy(&drive.source, &drive_path)?;
+            sandbox.jailed_firecracker.chown(drive_path)?;
+        }
 
         self.sandbox_initializer
             .initialize_sandbox(&mut sandbox)
@@ -229,6 +659,86 @@ impl SandboxFactory {
     }
 }
 
+fn unknown_capture(capture_id: &str) -> anyhow::Error {
+    NotFound(format!("Capture {capture_id} does not exist")).into()
+}
+
+fn primary_network_interface() -> anyhow::Result<NetworkInterface> {
+    Ok(NetworkInterfaceBuilder::default()
+        .host_dev_name(PRIMARY_TAP_DEVICE)
+        .iface_id("eth0")
+        .guest_mac("06:00:AC:10:00:02")
+        .build()?)
+}
+
+fn network_interfaces(
+    network: Option<&dyn SandboxNetwork>,
+) -> anyhow::Result<Vec<NetworkInterface>> {
+    let Some(network) = network else {
+        return Ok(vec![]);
+    };
+
+    let mut interfaces = vec![primary_network_interface()?];
+    if let Some(membership) = network.private_network() {
+        interfaces.push(
+            NetworkInterfaceBuilder::default()
+                .host_dev_name(PRIVATE_TAP_DEVICE)
+                .iface_id(PRIVATE_GUEST_INTERFACE)
+                .guest_mac(membership.guest_mac())
+                .build()?,
+        );
+    }
+
+    Ok(interfaces)
+}
+
+/// Device of the microvm's `index`th drive in the guest, virtio-blk disks are
+/// named in the order they are attached & the root device comes first
+fn guest_device(index: usize) -> String {
+    format!("/dev/vd{}", (b'a' + index as u8) as char)
+}
+
+/// Directory the guest mounts a drive at
+fn guest_mount_path(drive_id: &str) -> String {
+    format!("/tmp/{drive_id}")
+}
+
+/// Jailed path of a template's drive
+fn template_drive_path(drive_id: &str) -> String {
+    format!("/drives/{drive_id}.ext4")
+}
+
+fn boot_args(base: &str, network: Option<&dyn SandboxNetwork>) -> String {
+    let mut args = vec![base.to_string()];
+    let Some(network) = network else {
+        return args.join(" ");
+    };
+
+    args.push(format!(
+        "IP_ADDRESS::{GUEST_IP} IFACE::eth0 GATEWAY::{GUEST_GATEWAY}"
+    ));
+    if network.options().ipv6 {
+        args.push(format!(
+            "IP6_ADDRESS::{GUEST_IPV6} GATEWAY6::{GUEST_GATEWAY_IPV6}"
+        ));
+    }
+    if let Some(membership) = network.private_network() {
+        args.push(format!(
+            "PRIVATE_IP::{} PRIVATE_IFACE::{PRIVATE_GUEST_INTERFACE}",
+            membership.cidr_block()
+        ));
+    }
+    let nameservers = network
+        .options()
+        .guest_nameservers()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    args.push(format!("DNS::{}", nameservers.join(",")));
+
+    args.join(" ")
+}
+
 fn copy_if_exists(file: &Option<Location>, path: PathBuf, dummy_path: &Path) -> anyhow::Result<()> {
     match file {
         Some(file) => copy(file.to_local_path()?, path),
@@ -248,10 +758,30 @@ impl InitializeSandbox for SandboxInitializer {
     }
 }
 
+/// How long spark-server gets to come up after the microvm started
+const DEFAULT_SPARK_HEALTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Jailed path of the config file firecracker boots the microvm from
+const VMM_CONFIG_PATH: &str = "/vm-config.json";
+
+/// How firecracker gets the microvm's configuration
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VmmBoot {
+    /// Firecracker is launched with a config file & boots the microvm right
+    /// away
+    #[default]
+    ConfigFile,
+    /// Every device is configured through firecracker's API before the
+    /// microvm is started
+    Api,
+}
+
 #[derive(Clone, Debug)]
 pub struct SandboxInitializer {
     rootfs: PathBuf,
     kernel_image: PathBuf,
+    spark_health_timeout: Duration,
+    boot: VmmBoot,
 }
 
 impl SandboxInitializer {
@@ -259,118 +789,153 @@ impl SandboxInitializer {
         Self {
             rootfs: rootfs.into(),
             kernel_image: kernel_image.into(),
+            spark_health_timeout: DEFAULT_SPARK_HEALTH_TIMEOUT,
+            boot: VmmBoot::default(),
         }
     }
 
-    pub async fn initialize(&self, sandbox: &mut Sandbox) -> anyhow::Result<()> {
-        self.wait_for_health_check(sandbox).await?;
-        self.setup_logging(sandbox).await?;
-        self.setup_bootsource(sandbox).await?;
-        self.setup_drives(sandbox).await?;
-        self.setup_network_interfaces(sandbox).await?;
+    pub fn with_spark_health_timeout(self, spark_health_timeout: Duration) -> Self {
+        Self {
+            spark_health_timeout,
+            ..self
+        }
+    }
+
+    pub fn with_boot(self, boot: VmmBoot) -> Self {
+        Self { boot, ..self }
+    }
 
-        sandbox.start().await?;
+    pub async fn initialize(&self, sandbox: &mut Sandbox) -> anyhow::Result<()> {
+        match self.boot {
+            VmmBoot::ConfigFile => self.boot_from_config_file(sandbox).await?,
+            VmmBoot::Api => {
+                self.configure_vmm(sandbox).await?;
+                sandbox.start().await?;
+            }
+        }
 
         self.wait_for_spark_health_check(sandbox).await?;
         self.mount_drives_in_guest(sandbox).await?;
         Ok(())
     }
 
-    async fn wait_for_health_check(&self, sandbox: &Sandbox) -> anyhow::Result<()> {
-        let health_check = || async {
-            let response = sandbox.jailed_firecracker.client.get("/version").await?;
+    /// Launches firecracker with the microvm's config file, the microvm is
+    /// running once firecracker is healthy
+    pub async fn boot_from_config_file(&self, sandbox: &mut Sandbox) -> anyhow::Result<()> {
+        let config = self.prepare_jail(sandbox).await?;
+        let config_file_path_on_host = sandbox.path_resolver().resolve(VMM_CONFIG_PATH);
+        std::fs::write(&config_file_path_on_host, serde_json::to_vec(&config)?)?;
+        sandbox
+            .jailed_firecracker
+            .chown(&config_file_path_on_host)?;
 
-            match response.status().is_success() {
-                true => Ok(()),
-                false => anyhow::bail!("Received error from health check API"),
-            }
-        };
+        let config_file = sandbox.path_resolver().vmm_path(VMM_CONFIG_PATH);
+        sandbox.jailed_firecracker.launch(Some(&config_file))?;
+        self.wait_for_health_check(sandbox).await?;
 
-        let start = Instant::now();
-        while health_check().await.is_err() {
-            if start.elapsed() > Duration::from_secs(20) {
-                panic!("Firecracker did not become healthy in 20 seconds");
-            }
-            tokio::time::sleep(Duration::from_secs(1)).await;
+        let state = sandbox.vmm().instance_info().await?.state;
+        if state != InstanceState::Running {
+            anyhow::bail!(
+                "Sandbox {} did not boot from its config file, it is {state:?}",
+                sandbox.id()
+            );
         }
+        sandbox.state = SandboxState::Running;
 
         Ok(())
     }
 
-    async fn setup_logging(&self, sandbox: &Sandbox) -> anyhow::Result<()> {
-        let logger = match &sandbox.virtual_machine_config.logger {
-            Some(config) => config.clone(),
-            None => return Ok(()),
-        };
-
-        let log_file_path_on_host = sandbox.path_resolver().resolve(&logger.log_path);
-
-        // Touch the logfile
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&log_file_path_on_host)?;
+    /// Launches firecracker & configures the microvm through its API,
+    /// everything up to starting it
+    pub async fn configure_vmm(&self, sandbox: &Sandbox) -> anyhow::Result<()> {
+        let config = self.prepare_jail(sandbox).await?;
+        sandbox.jailed_firecracker.launch(None)?;
+        self.wait_for_health_check(sandbox).await?;
 
-        sandbox
-            .jailed_firecracker
-            .client
-            .put("/logger", &logger)
-            .await?;
+        let vmm = sandbox.vmm();
+        if let Some(logger) = &config.logger {
+            vmm.put_logger(logger).await?;
+        }
+        vmm.put_boot_source(&config.boot_source).await?;
+        if let Some(machine_config) = &config.machine_config {
+            vmm.put_machine_config(machine_config).await?;
+        }
+        for drive in &config.drives {
+            vmm.put_drive(drive).await?;
+        }
+        for interface in &config.network_interfaces {
+            vmm.put_network_interface(interface).await?;
+        }
+        if let Some(vsock) = &config.vsock {
+            vmm.put_vsock(vsock).await?;
+        }
 
         Ok(())
     }
 
-    async fn setup_bootsource(&self, sandbox: &Sandbox) -> anyhow::Result<()> {
-        let bootsource = &sandbox.virtual_machine_config.boot_source;
+    /// Puts the files the microvm's config points to into the jail & returns
+    /// the config with paths firecracker opens them at
+    async fn prepare_jail(&self, sandbox: &Sandbox) -> anyhow::Result<VirtualMachine> {
+        let resolver = sandbox.path_resolver();
+        let mut config = sandbox.virtual_machine_config.lock().await.clone();
+
+        if let Some(logger) = &mut config.logger {
+            let log_file_path_on_host = resolver.resolve(&logger.log_path);
+            // Touch the logfile
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&log_file_path_on_host)?;
+            sandbox.jailed_firecracker.chown(&log_file_path_on_host)?;
+            logger.log_path = resolver.vmm_path(&logger.log_path);
+        }
 
-        let kernel_image_path_on_host = sandbox
-            .path_resolver()
-            .resolve(&bootsource.kernel_image_path);
+        let bootsource = &mut config.boot_source;
+        let kernel_image_path_on_host = resolver.resolve(&bootsource.kernel_image_path);
         std::fs::create_dir_all(kernel_image_path_on_host.parent().unwrap())?;
-
-        // Copy the global bootsource into the VM directory
-        util::copy(&self.kernel_image, kernel_image_path_on_host)?;
-
+        let (rootfs, kernel_image) = match &sandbox.image {
+            Some(image) => (&image.rootfs, &image.kernel),
+            None => (&self.rootfs, &self.kernel_image),
+        };
+        // Copy the bootsource into the VM directory
+        util::copy(kernel_image, &kernel_image_path_on_host)?;
         sandbox
             .jailed_firecracker
-            .client
-            .put("/boot-source", &bootsource)
-            .await?;
-
-        Ok(())
-    }
-
-    async fn setup_drives(&self, sandbox: &Sandbox) -> anyhow::Result<()> {
-        let rootfs_path = sandbox.path_resolver().resolve("/drives/rootfs.ext4");
-        util::copy(&self.rootfs, rootfs_path)?;
-
-        for drive in &sandbox.virtual_machine_config.drives {
-            let path = format!("/drives/{}", drive.drive_id);
+            .chown(kernel_image_path_on_host)?;
+        bootsource.kernel_image_path = resolver.vmm_path(&bootsource.kernel_image_path);
+
+        let rootfs_path = resolver.resolve("/drives/rootfs.ext4");
+        util::copy(rootfs, &rootfs_path)?;
+        sandbox.jailed_firecracker.chown(rootfs_path)?;
+        for drive in &mut config.drives {
+            drive.path_on_host = resolver.vmm_path(&drive.path_on_host);
+        }
 
-            sandbox.jailed_firecracker.client.put(path, &drive).await?;
+        if let Some(vsock) = &mut config.vsock {
+            vsock.uds_path = resolver.vmm_path(&vsock.uds_path);
         }
 
-        Ok(())
+        Ok(config)
     }
 
-    async fn setup_network_interfaces(&self, sandbox: &Sandbox) -> anyhow::Result<()> {
-        for interface in &sandbox.virtual_machine_config.network_interfaces {
-            let path = format!("/network-interfaces/{}", interface.iface_id);
-            sandbox
-                .jailed_firecracker
-                .client
-                .put(path, &interface)
-                .await?;
+    async fn wait_for_health_check(&self, sandbox: &Sandbox) -> anyhow::Result<()> {
+        let start = Instant::now();
+        loop {
+            match sandbox.vmm().version().await {
+                Ok(_) => return Ok(()),
+                Err(e) if start.elapsed() > Duration::from_secs(20) => {
+                    return Err(e).context("Firecracker did not become healthy in 20 seconds")
+                }
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
         }
-
-        Ok(())
     }
 
     async fn wait_for_spark_health_check(&self, sandbox: &mut Sandbox) -> anyhow::Result<()> {
         let mut client = sandbox.client().await;
         let start = Instant::now();
-        while start.elapsed() < Duration::from_secs(10) {
+        while start.elapsed() < self.spark_health_timeout {
             if client.health_check().await.is_ok() {
                 return Ok(());
             }
@@ -381,17 +946,15 @@ impl SandboxInitializer {
     }
 
     async fn mount_drives_in_guest(&self, sandbox: &mut Sandbox) -> anyhow::Result<()> {
+        let drives = sandbox.virtual_machine_config.lock().await.drives.clone();
         let mut client = sandbox.client().await;
-        for drive in &sandbox.virtual_machine_config.drives {
-            if drive.drive_id == "rootfs" {
+        for (index, drive) in drives.iter().enumerate() {
+            if drive.drive_id == ROOTFS_DRIVE_ID {
                 continue;
             }
 
             client
-                .mount_drive(
-                    format!("/dev/{}", drive.drive_id),
-                    format!("/tmp/{}", drive.drive_id),
-                )
+                .mount_drive(guest_device(index), guest_mount_path(&drive.drive_id))
                 .await?;
         }
         Ok(())
@@ -6,7 +6,11 @@ pub enum IpCommand {
     // Corresponds to ip link del $dev
     DeleteDevice { device: String },
     CreateTapDevice { device: String },
+    CreateBridge { device: String },
+    SetMaster { device: String, master: String },
     AddAddress { cidr_block: String, device: String },
+    // Skips duplicate address detection so the address is usable right away
+    AddIpv6Address { cidr_block: String, device: String },
     Activate { device: String },
     CreateVethPair { veth: String, vpeer: String },
     MoveDeviceToNamespace { device: String, namespace: String },
@@ -29,9 +33,18 @@ impl From<IpCommand> for Command {
             IpCommand::CreateTapDevice { device } => {
                 cmd.args(["tuntap", "add", "dev", &device, "mode", "tap"])
             }
+            IpCommand::CreateBridge { device } => {
+                cmd.args(["link", "add", "name", &device, "type", "bridge"])
+            }
+            IpCommand::SetMaster { device, master } => {
+                cmd.args(["link", "set", "dev", &device, "master", &master])
+            }
             IpCommand::AddAddress { cidr_block, device } => {
                 cmd.args(["addr", "add", &cidr_block, "dev", &device])
             }
+            IpCommand::AddIpv6Address { cidr_block, device } => {
+                cmd.args(["-6", "addr", "add", &cidr_block, "dev", &device, "nodad"])
+            }
             IpCommand::Activate { device } => cmd.args(["link", "set", "dev", &device, "up"]),
             IpCommand::CreateVethPair {
                 veth: device_one,
@@ -59,6 +72,43 @@ impl From<IpCommand> for Command {
     }
 }
 
+pub enum SysctlCommand {
+    Set { key: String, value: String },
+}
+
+impl SysctlCommand {
+    pub fn output(self) -> anyhow::Result<Output> {
+        let cmd = Command::from(self);
+        run_command(cmd)
+    }
+}
+
+impl From<SysctlCommand> for Command {
+    fn from(value: SysctlCommand) -> Self {
+        let mut cmd = Command::new("sysctl");
+        let _ = match value {
+            SysctlCommand::Set { key, value } => cmd.args(["-w", &format!("{key}={value}")]),
+        };
+
+        cmd
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    fn iptables(&self) -> &str {
+        match self {
+            IpFamily::V4 => "iptables",
+            IpFamily::V6 => "ip6tables",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Table {
     Forward,
@@ -75,11 +125,13 @@ impl AsRef<str> for Table {
 #[derive(Debug)]
 pub enum Target {
     Accept,
+    Drop,
 }
 impl AsRef<str> for Target {
     fn as_ref(&self) -> &str {
         match self {
             Target::Accept => "ACCEPT",
+            Target::Drop => "DROP",
         }
     }
 }
@@ -97,6 +149,14 @@ pub enum IpTablesCommand {
         input: String,
         output: String,
     },
+    /// Goes ahead of every rule already in the chain
+    InsertPortRule {
+        table: Table,
+        target: Target,
+        input: String,
+        protocol: String,
+        port: u16,
+    },
     EnableMasquerade {
         source_address: Option<String>,
         output: String,
@@ -119,15 +179,17 @@ pub enum IpTablesCommand {
 
 impl IpTablesCommand {
     pub fn output(self) -> anyhow::Result<Output> {
-        let cmd = Command::from(self);
+        self.output_for(IpFamily::V4)
+    }
+
+    pub fn output_for(self, family: IpFamily) -> anyhow::Result<Output> {
+        let cmd = self.into_command(family);
         run_command(cmd)
     }
-}
 
-impl From<IpTablesCommand> for Command {
-    fn from(value: IpTablesCommand) -> Self {
-        let mut cmd = Command::new("iptables");
-        let _ = match value {
+    fn into_command(self, family: IpFamily) -> Command {
+        let mut cmd = Command::new(family.iptables());
+        let _ = match self {
             IpTablesCommand::DeleteRule {
                 table,
                 target,
@@ -158,6 +220,25 @@ impl From<IpTablesCommand> for Command {
                 "-j",
                 target.as_ref(),
             ]),
+            IpTablesCommand::InsertPortRule {
+                table,
+                target,
+                input,
+                protocol,
+                port,
+            } => cmd.args([
+                "-I",
+                table.as_ref(),
+                "1",
+                "-i",
+                &input,
+                "-p",
+                &protocol,
+                "--dport",
+                &port.to_string(),
+                "-j",
+                target.as_ref(),
+            ]),
             IpTablesCommand::EnableMasquerade {
                 source_address,
                 output,
@@ -216,6 +297,12 @@ impl From<IpTablesCommand> for Command {
     }
 }
 
+impl From<IpTablesCommand> for Command {
+    fn from(value: IpTablesCommand) -> Self {
+        value.into_command(IpFamily::V4)
+    }
+}
+
 fn run_command(mut cmd: Command) -> anyhow::Result<Output> {
     let output = cmd.output().context("Failed to run command")?;
 
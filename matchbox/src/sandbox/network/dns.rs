@@ -0,0 +1,481 @@
+use std::{
+    net::{
+        IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener as StdTcpListener,
+        UdpSocket as StdUdpSocket,
+    },
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use netns_rs::NetNs;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    task::JoinHandle,
+};
+
+pub const DNS_PORT: u16 = 53;
+pub const DEFAULT_NAMESERVERS: [&str; 2] = ["8.8.8.8", "1.1.1.1"];
+
+const DNS_HEADER_LENGTH: usize = 12;
+const MAX_DNS_PACKET_SIZE: usize = 4096;
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Domain based egress policy enforced by the per sandbox DNS forwarder.
+/// Domains match themselves and all of their subdomains.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct DnsPolicy {
+    /// When not empty, only these domains resolve
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Domains that never resolve, takes precedence over `allow`
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Log every query the sandbox makes
+    #[serde(default)]
+    pub log_queries: bool,
+}
+
+impl DnsPolicy {
+    pub fn permits(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.').to_lowercase();
+        if self.deny.iter().any(|domain| matches_domain(&name, domain)) {
+            return false;
+        }
+
+        self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|domain| matches_domain(&name, domain))
+    }
+}
+
+fn matches_domain(name: &str, domain: &str) -> bool {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    name == domain || name.ends_with(&format!(".{domain}"))
+}
+
+/// A small DNS forwarder listening on UDP & TCP inside of a sandbox's network
+/// namespace. Queries permitted by the policy are forwarded to the upstream
+/// nameservers over the same transport, everything else gets an NXDOMAIN.
+#[derive(Debug)]
+pub struct DnsForwarder {
+    handles: [JoinHandle<()>; 2],
+}
+
+impl DnsForwarder {
+    pub fn spawn(
+        sandbox_id: &str,
+        netns: &NetNs,
+        listen_address: SocketAddr,
+        upstreams: Vec<IpAddr>,
+        policy: DnsPolicy,
+    ) -> anyhow::Result<DnsForwarder> {
+        // A socket stays in the namespace it was created in, so bind them
+        // while we're inside of the sandbox's network namespace.
+        let (socket, listener) = netns.run(|_| {
+            Ok::<_, std::io::Error>((
+                StdUdpSocket::bind(listen_address)?,
+                StdTcpListener::bind(listen_address)?,
+            ))
+        })??;
+        socket.set_nonblocking(true)?;
+        listener.set_nonblocking(true)?;
+        let socket =
+            UdpSocket::from_std(socket).context("Failed to register the DNS forwarder socket")?;
+        let listener = TcpListener::from_std(listener)
+            .context("Failed to register the DNS forwarder listener")?;
+
+        let resolver = Arc::new(Resolver {
+            sandbox_id: sandbox_id.to_string(),
+            socket,
+            upstreams: upstreams
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, DNS_PORT))
+                .collect(),
+            policy,
+        });
+        let handles = [
+            tokio::spawn(resolver.clone().serve_udp()),
+            tokio::spawn(resolver.serve_tcp(listener)),
+        ];
+
+        Ok(DnsForwarder { handles })
+    }
+}
+
+impl Drop for DnsForwarder {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+/// How a query reached the forwarder, it is forwarded the same way
+#[derive(Clone, Copy, Debug)]
+enum Transport {
+    Udp,
+    /// Messages are prefixed with their length, clients retry truncated UDP
+    /// answers this way
+    Tcp,
+}
+
+struct Resolver {
+    sandbox_id: String,
+    socket: UdpSocket,
+    upstreams: Vec<SocketAddr>,
+    policy: DnsPolicy,
+}
+
+impl Resolver {
+    async fn serve_udp(self: Arc<Self>) {
+        let mut buffer = [0u8; MAX_DNS_PACKET_SIZE];
+        loop {
+            let (length, client) = match self.socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    println!("[dns {}] failed to receive query: {e}", self.sandbox_id);
+                    continue;
+                }
+            };
+
+            let query = buffer[..length].to_vec();
+            let resolver = self.clone();
+            tokio::spawn(async move {
+                let answered = match resolver.answer(&query, Transport::Udp).await {
+                    Ok(response) => resolver.socket.send_to(&response, client).await.map(|_| ()),
+                    Err(e) => Err(std::io::Error::other(e)),
+                };
+                if let Err(e) = answered {
+                    println!(
+                        "[dns {}] failed to answer query from {client}: {e:?}",
+                        resolver.sandbox_id
+                    );
+                }
+            });
+        }
+    }
+
+    async fn serve_tcp(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            let (stream, client) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    println!("[dns {}] failed to accept connection: {e}", self.sandbox_id);
+                    continue;
+                }
+            };
+
+            let resolver = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = resolver.handle_connection(stream).await {
+                    println!(
+                        "[dns {}] failed to answer queries from {client}: {e:?}",
+                        resolver.sandbox_id
+                    );
+                }
+            });
+        }
+    }
+
+    /// Answers the queries of a connection one after another until the client
+    /// closes it
+    async fn handle_connection(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        while let Some(query) = read_message(&mut stream).await? {
+            let response = self.answer(&query, Transport::Tcp).await?;
+            write_message(&mut stream, &response).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn answer(&self, query: &[u8], transport: Transport) -> anyhow::Result<Vec<u8>> {
+        let Some(question) = parse_question(query) else {
+            anyhow::bail!("received a malformed query");
+        };
+
+        let permitted = self.policy.permits(&question.name);
+        if self.policy.log_queries {
+            let verdict = if permitted { "allowed" } else { "denied" };
+            println!("[dns {}] {} {verdict}", self.sandbox_id, question.name);
+        }
+
+        match (permitted, transport) {
+            (true, Transport::Udp) => self.forward(query).await,
+            (true, Transport::Tcp) => self.forward_tcp(query).await,
+            (false, _) => Ok(error_response(query, question.end, RCODE_NXDOMAIN)),
+        }
+    }
+
+    async fn forward(&self, query: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = [0u8; MAX_DNS_PACKET_SIZE];
+        for upstream in &self.upstreams {
+            let exchange = async {
+                // Bound to the upstream's family, a v4 socket can't reach v6
+                let local: SocketAddr = match upstream {
+                    SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                    SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+                };
+                let socket = UdpSocket::bind(local).await?;
+                socket.send_to(query, upstream).await?;
+                let (length, _) = socket.recv_from(&mut buffer).await?;
+                Ok::<usize, anyhow::Error>(length)
+            };
+            match tokio::time::timeout(UPSTREAM_TIMEOUT, exchange).await {
+                Ok(Ok(length)) => return Ok(buffer[..length].to_vec()),
+                Ok(Err(e)) => {
+                    println!("[dns {}] upstream {upstream} failed: {e}", self.sandbox_id)
+                }
+                Err(_) => {
+                    println!("[dns {}] upstream {upstream} timed out", self.sandbox_id)
+                }
+            }
+        }
+
+        anyhow::bail!("none of the upstream nameservers answered")
+    }
+
+    async fn forward_tcp(&self, query: &[u8]) -> anyhow::Result<Vec<u8>> {
+        for upstream in &self.upstreams {
+            let exchange = async {
+                let mut stream = TcpStream::connect(upstream).await?;
+                write_message(&mut stream, query).await?;
+                read_message(&mut stream)
+                    .await?
+                    .context("the connection closed before the answer")
+            };
+            match tokio::time::timeout(UPSTREAM_TIMEOUT, exchange).await {
+                Ok(Ok(response)) => return Ok(response),
+                Ok(Err(e)) => {
+                    println!("[dns {}] upstream {upstream} failed: {e}", self.sandbox_id)
+                }
+                Err(_) => {
+                    println!("[dns {}] upstream {upstream} timed out", self.sandbox_id)
+                }
+            }
+        }
+
+        anyhow::bail!("none of the upstream nameservers answered")
+    }
+}
+
+/// Reads a length prefixed DNS message, `None` once the peer closed the
+/// connection between messages
+async fn read_message(
+    stream: &mut (impl tokio::io::AsyncRead + Unpin),
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let length = match stream.read_u16().await {
+        Ok(length) => length,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut message = vec![0u8; length as usize];
+    stream.read_exact(&mut message).await?;
+
+    Ok(Some(message))
+}
+
+async fn write_message(
+    stream: &mut (impl tokio::io::AsyncWrite + Unpin),
+    message: &[u8],
+) -> anyhow::Result<()> {
+    let length = u16::try_from(message.len()).context("DNS message is too long")?;
+    stream.write_u16(length).await?;
+    stream.write_all(message).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct Question {
+    /// The queried domain name, lowercased & without the trailing dot
+    name: String,
+    /// Offset of the first byte after the question section
+    end: usize,
+}
+
+/// Parses the first question out of a DNS query
+fn parse_question(packet: &[u8]) -> Option<Question> {
+    let question_count = u16::from_be_bytes([*packet.get(4)?, *packet.get(5)?]);
+    if question_count == 0 {
+        return None;
+    }
+
+    let mut labels = vec![];
+    let mut offset = DNS_HEADER_LENGTH;
+    loop {
+        let length = *packet.get(offset)? as usize;
+        offset += 1;
+        if length == 0 {
+            break;
+        }
+        // Compression pointers aren't used for the first question
+        if length & 0xC0 != 0 {
+            return None;
+        }
+
+        let label = packet.get(offset..offset + length)?;
+        labels.push(String::from_utf8_lossy(label).to_lowercase());
+        offset += length;
+    }
+
+    // QTYPE & QCLASS
+    let end = offset + 4;
+    if packet.len() < end {
+        return None;
+    }
+
+    Some(Question {
+        name: labels.join("."),
+        end,
+    })
+}
+
+/// Builds a response to the query which only echoes the question and carries
+/// the given response code
+fn error_response(query: &[u8], question_end: usize, rcode: u8) -> Vec<u8> {
+    let mut response = query[..question_end].to_vec();
+    // QR = response, keep the opcode & RD flag
+    response[2] |= 0x80;
+    // RA = recursion available
+    response[3] = 0x80 | rcode;
+    // One question, no answer, authority or additional records
+    response[4..6].copy_from_slice(&1u16.to_be_bytes());
+    response[6..DNS_HEADER_LENGTH].fill(0);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tokio::net::UdpSocket;
+
+    use super::{
+        error_response, parse_question, read_message, write_message, DnsPolicy, Question, Resolver,
+        RCODE_NXDOMAIN,
+    };
+
+    /// A query for `www.Example.com` with type A & an EDNS additional record
+    fn query() -> Vec<u8> {
+        let mut packet = vec![
+            0x12, 0x34, // id
+            0x01, 0x00, // flags: RD
+            0x00, 0x01, // one question
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // one additional record
+        ];
+        for label in ["www", "Example", "com"] {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x01]);
+        packet.extend_from_slice(&[0x00, 0x00, 0x29, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+        packet
+    }
+
+    #[test]
+    fn parses_the_question() {
+        assert_eq!(
+            parse_question(&query()),
+            Some(Question {
+                name: "www.example.com".into(),
+                end: 33
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        let query = query();
+        assert_eq!(parse_question(&query[..8]), None);
+        assert_eq!(parse_question(&query[..20]), None);
+
+        let mut no_questions = query.clone();
+        no_questions[5] = 0;
+        assert_eq!(parse_question(&no_questions), None);
+    }
+
+    #[test]
+    fn error_response_echoes_the_question() {
+        let query = query();
+        let response = error_response(&query, 33, RCODE_NXDOMAIN);
+
+        assert_eq!(response.len(), 33);
+        assert_eq!(response[..2], query[..2], "the id should be kept");
+        assert_eq!(response[2], 0x81, "should be a response with RD kept");
+        assert_eq!(response[3], 0x83);
+        assert_eq!(response[4..12], [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(response[12..], query[12..33]);
+    }
+
+    #[test]
+    fn policy_matches_subdomains() {
+        let policy = DnsPolicy {
+            allow: vec!["example.com".into(), "pypi.org.".into()],
+            deny: vec!["evil.example.com".into()],
+            log_queries: false,
+        };
+
+        assert!(policy.permits("example.com"));
+        assert!(policy.permits("www.Example.com."));
+        assert!(policy.permits("files.pypi.org"));
+        assert!(!policy.permits("evil.example.com"));
+        assert!(!policy.permits("a.evil.example.com"));
+        assert!(!policy.permits("notexample.com"));
+        assert!(!policy.permits("google.com"));
+    }
+
+    #[test]
+    fn empty_allow_list_permits_everything_not_denied() {
+        let policy = DnsPolicy {
+            deny: vec!["ads.net".into()],
+            ..Default::default()
+        };
+
+        assert!(policy.permits("google.com"));
+        assert!(!policy.permits("tracker.ads.net"));
+    }
+
+    #[tokio::test]
+    async fn tcp_messages_are_length_prefixed() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        write_message(&mut client, &query()).await.unwrap();
+        drop(client);
+
+        assert_eq!(read_message(&mut server).await.unwrap(), Some(query()));
+        assert_eq!(read_message(&mut server).await.unwrap(), None);
+    }
+
+    /// An upstream answering every query with `answer`
+    async fn upstream(address: &str, answer: &'static [u8]) -> SocketAddr {
+        let socket = UdpSocket::bind(address).await.unwrap();
+        let address = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((_, client)) = socket.recv_from(&mut buffer).await {
+                socket.send_to(answer, client).await.unwrap();
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn forwards_to_ipv6_upstreams() {
+        let resolver = Resolver {
+            sandbox_id: "sandbox".into(),
+            socket: UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+            upstreams: vec![
+                upstream("[::1]:0", b"v6").await,
+                upstream("127.0.0.1:0", b"v4").await,
+            ],
+            policy: DnsPolicy::default(),
+        };
+
+        assert_eq!(resolver.forward(&query()).await.unwrap(), b"v6");
+    }
+}
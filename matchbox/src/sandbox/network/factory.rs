@@ -0,0 +1,140 @@
+use std::{fmt::Debug, path::PathBuf};
+
+use firecracker_config_rs::{
+    models::network_interface::NetworkInterface, validation::ValidationErrors,
+};
+
+use crate::sandbox::id::{AddressBlock, VmIdentifier};
+
+use super::{
+    pcap::{CaptureOptions, PacketCapture},
+    private::PrivateNetworkMembership,
+    traffic::TrafficCounters,
+    Network, NetworkOptions, OfflineNetns, SandboxNetwork,
+};
+
+pub trait ProvideNetwork: Debug + Send + Sync {
+    fn provide_network(
+        &self,
+        id: &VmIdentifier,
+        interfaces: &[NetworkInterface],
+        options: &NetworkOptions,
+    ) -> anyhow::Result<Box<dyn SandboxNetwork>>;
+
+    /// Namespace firecracker of a sandbox without a network runs in, none
+    /// when namespaces can't be created
+    fn provide_offline_netns(&self, id: &VmIdentifier) -> anyhow::Result<Option<OfflineNetns>>;
+}
+
+/// Gives every sandbox its own network namespace behind NAT, needs root
+#[derive(Debug, Default)]
+pub struct NetnsNetworkFactory;
+
+impl ProvideNetwork for NetnsNetworkFactory {
+    fn provide_network(
+        &self,
+        id: &VmIdentifier,
+        interfaces: &[NetworkInterface],
+        options: &NetworkOptions,
+    ) -> anyhow::Result<Box<dyn SandboxNetwork>> {
+        Ok(Box::new(Network::new(id, interfaces, options)?))
+    }
+
+    fn provide_offline_netns(&self, id: &VmIdentifier) -> anyhow::Result<Option<OfflineNetns>> {
+        Ok(Some(OfflineNetns::new(id)?))
+    }
+}
+
+/// Refuses to create networks, for hosts where matchbox has no root to
+/// create them with
+#[derive(Debug, Default)]
+pub struct DisabledNetworkFactory;
+
+impl ProvideNetwork for DisabledNetworkFactory {
+    fn provide_network(
+        &self,
+        _id: &VmIdentifier,
+        _interfaces: &[NetworkInterface],
+        _options: &NetworkOptions,
+    ) -> anyhow::Result<Box<dyn SandboxNetwork>> {
+        let mut errors = ValidationErrors::default();
+        errors.add(
+            "network",
+            "Sandboxes on this host can't have a network, create them with network none",
+        );
+        Err(errors.into())
+    }
+
+    fn provide_offline_netns(&self, _id: &VmIdentifier) -> anyhow::Result<Option<OfflineNetns>> {
+        Ok(None)
+    }
+}
+
+/// Hands out the addresses of a network without creating any of it, for
+/// tests which run without privileges
+#[derive(Debug, Default)]
+pub struct InMemoryNetworkFactory;
+
+impl ProvideNetwork for InMemoryNetworkFactory {
+    fn provide_network(
+        &self,
+        id: &VmIdentifier,
+        _interfaces: &[NetworkInterface],
+        options: &NetworkOptions,
+    ) -> anyhow::Result<Box<dyn SandboxNetwork>> {
+        Ok(Box::new(InMemoryNetwork {
+            namespace_name: id.id().into(),
+            address_block: id.address_block().clone(),
+            options: options.clone(),
+            private_network: options
+                .private_network
+                .as_ref()
+                .map(|network| network.join())
+                .transpose()?,
+        }))
+    }
+
+    fn provide_offline_netns(&self, _id: &VmIdentifier) -> anyhow::Result<Option<OfflineNetns>> {
+        Ok(None)
+    }
+}
+
+/// A network which only has addresses, nothing is created on the host
+#[derive(Debug)]
+pub struct InMemoryNetwork {
+    namespace_name: String,
+    address_block: AddressBlock,
+    options: NetworkOptions,
+    private_network: Option<PrivateNetworkMembership>,
+}
+
+impl SandboxNetwork for InMemoryNetwork {
+    fn address_block(&self) -> &AddressBlock {
+        &self.address_block
+    }
+
+    fn options(&self) -> &NetworkOptions {
+        &self.options
+    }
+
+    fn private_network(&self) -> Option<&PrivateNetworkMembership> {
+        self.private_network.as_ref()
+    }
+
+    /// Where the namespace would be, it doesn't exist
+    fn netns_path(&self) -> anyhow::Result<PathBuf> {
+        Ok(PathBuf::from("/var/run/netns").join(&self.namespace_name))
+    }
+
+    fn start_capture(
+        &self,
+        _options: CaptureOptions,
+        _path: PathBuf,
+    ) -> anyhow::Result<PacketCapture> {
+        anyhow::bail!("In memory networks have no devices to capture")
+    }
+
+    fn traffic(&self) -> anyhow::Result<TrafficCounters> {
+        Ok(TrafficCounters::default())
+    }
+}
@@ -1,16 +1,43 @@
 use anyhow::Context;
+use derive_builder::Builder;
 use firecracker_config_rs::models::network_interface::NetworkInterface;
 use netns_rs::NetNs;
-use std::path::PathBuf;
-
-use self::commands::{IpCommand, IpTablesCommand, Table, Target};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Debug,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use self::commands::{IpCommand, IpFamily, IpTablesCommand, SysctlCommand, Table, Target};
+use self::dns::{DnsForwarder, DnsPolicy, DEFAULT_NAMESERVERS, DNS_PORT};
+use self::pcap::{CaptureDevice, CaptureOptions, PacketCapture};
+use self::private::{PrivateNetwork, PrivateNetworkMembership, PRIVATE_TAP_DEVICE};
+use self::traffic::TrafficCounters;
 
 use super::id::{AddressBlock, VmIdentifier};
 
 mod commands;
+pub mod dns;
+pub mod factory;
+pub mod pcap;
+pub mod private;
+pub mod traffic;
 
 pub const HOST_INTERFACE_NAME: &str = "ens4";
 pub const DEFAULT_CIDR_BLOCK: &str = "172.16.0.1/30";
+pub const DEFAULT_IPV6_CIDR_BLOCK: &str = "fd00:ac10::1/64";
+/// Addresses every microvm uses inside of its own network namespace
+pub const GUEST_IP: &str = "172.16.0.2";
+pub const GUEST_GATEWAY: &str = "172.16.0.1";
+pub const GUEST_IPV6: &str = "fd00:ac10::2";
+pub const GUEST_GATEWAY_IPV6: &str = "fd00:ac10::1";
+/// Tap device of the microvm's primary network interface
+pub const PRIMARY_TAP_DEVICE: &str = "tap0";
+/// Bridge connecting the microvm to its private network inside of the
+/// network namespace
+const PRIVATE_BRIDGE_DEVICE: &str = "br0";
 
 enum IpAddressType {
     Veth,
@@ -28,40 +55,196 @@ impl From<IpAddressType> for u64 {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkMode {
+    /// The sandbox gets its own network namespace behind NAT
+    #[default]
+    Default,
+    /// The sandbox has no network devices at all
+    None,
+}
+
+#[derive(Builder, Clone, Debug, Default)]
+#[builder(setter(into))]
+pub struct NetworkOptions {
+    /// Give the microvm an ipv6 address next to its ipv4 address
+    #[builder(default)]
+    pub ipv6: bool,
+    /// Nameservers the microvm resolves through, defaults to
+    /// [`DEFAULT_NAMESERVERS`]
+    #[builder(default)]
+    pub nameservers: Vec<IpAddr>,
+    /// Run a DNS forwarder for the microvm which enforces this policy. The
+    /// microvm then resolves through the forwarder, which uses `nameservers`
+    /// as its upstreams.
+    #[builder(setter(strip_option), default)]
+    pub dns_policy: Option<DnsPolicy>,
+    /// Attach the microvm to a private network shared with other sandboxes
+    #[builder(setter(strip_option), default)]
+    pub private_network: Option<Arc<PrivateNetwork>>,
+}
+
+impl NetworkOptions {
+    /// Nameservers the microvm should put in its resolv.conf
+    pub fn guest_nameservers(&self) -> Vec<IpAddr> {
+        match self.dns_policy {
+            Some(_) => vec![GUEST_GATEWAY.parse().unwrap()],
+            None => self.upstream_nameservers(),
+        }
+    }
+
+    fn upstream_nameservers(&self) -> Vec<IpAddr> {
+        match self.nameservers.is_empty() {
+            true => DEFAULT_NAMESERVERS
+                .iter()
+                .map(|nameserver| nameserver.parse().unwrap())
+                .collect(),
+            false => self.nameservers.clone(),
+        }
+    }
+}
+
+/// A network namespace with nothing but a loopback device, firecracker of a
+/// sandbox without a network runs in one so it can't reach the host's network
+#[derive(Debug)]
+pub struct OfflineNetns {
+    name: String,
+}
+
+impl OfflineNetns {
+    pub fn new(id: &VmIdentifier) -> anyhow::Result<OfflineNetns> {
+        let netns = NetNs::new(id.id())?;
+        netns.run(|_| {
+            IpCommand::Activate {
+                device: "lo".into(),
+            }
+            .output()
+        })??;
+
+        Ok(OfflineNetns {
+            name: id.id().into(),
+        })
+    }
+
+    pub fn path(&self) -> anyhow::Result<PathBuf> {
+        NetNs::get(&self.name)
+            .map(|ns| ns.path().to_owned())
+            .context("Failed to get network namespace")
+    }
+}
+
+impl Drop for OfflineNetns {
+    fn drop(&mut self) {
+        let netns = NetNs::get(&self.name).unwrap();
+        netns.remove().unwrap();
+    }
+}
+
+/// The network of a sandbox as the sandbox uses it
+pub trait SandboxNetwork: Debug + Send + Sync {
+    fn address_block(&self) -> &AddressBlock;
+
+    fn options(&self) -> &NetworkOptions;
+
+    fn private_network(&self) -> Option<&PrivateNetworkMembership>;
+
+    /// Network namespace firecracker runs in
+    fn netns_path(&self) -> anyhow::Result<PathBuf>;
+
+    /// Starts capturing the packets of `options.device`
+    fn start_capture(
+        &self,
+        options: CaptureOptions,
+        path: PathBuf,
+    ) -> anyhow::Result<PacketCapture>;
+
+    /// Traffic the microvm sent & received
+    fn traffic(&self) -> anyhow::Result<TrafficCounters>;
+
+    fn microvm_ip(&self) -> String {
+        self.address_block().get_ip(IpAddressType::Microvm)
+    }
+
+    fn microvm_ipv6(&self) -> Option<String> {
+        self.options()
+            .ipv6
+            .then(|| self.address_block().get_ipv6(IpAddressType::Microvm))
+    }
+}
+
+/// A network namespace behind NAT on the host
 #[derive(Debug)]
 pub struct Network {
     namespace_name: String,
     address_block: AddressBlock,
+    options: NetworkOptions,
+    dns_forwarder: Option<DnsForwarder>,
+    private_network: Option<PrivateNetworkMembership>,
 }
 
 impl Network {
-    pub fn new(id: &VmIdentifier, interfaces: &[NetworkInterface]) -> anyhow::Result<Network> {
+    pub fn new(
+        id: &VmIdentifier,
+        interfaces: &[NetworkInterface],
+        options: &NetworkOptions,
+    ) -> anyhow::Result<Network> {
         // Create the network namespace
         let _ = NetNs::new(id.id())?;
 
-        let network = Self {
+        let mut network = Self {
             namespace_name: id.id().into(),
             address_block: id.address_block().clone(),
+            options: options.clone(),
+            dns_forwarder: None,
+            private_network: options
+                .private_network
+                .as_ref()
+                .map(|network| network.join())
+                .transpose()?,
         };
         network.setup(interfaces)?;
+        network.dns_forwarder = network.setup_dns_forwarder(interfaces)?;
 
         Ok(network)
     }
 
-    pub fn netns_path(&self) -> anyhow::Result<PathBuf> {
-        NetNs::get(&self.namespace_name)
-            .map(|ns| ns.path().to_owned())
-            .context("Failed to get network namespace")
-    }
-
     fn setup(&self, interfaces: &[NetworkInterface]) -> anyhow::Result<()> {
         let netns = NetNs::get(&self.namespace_name)?;
         self.setup_veth_devices(&netns)?;
+        if self.options.ipv6 {
+            self.setup_ipv6(&netns)?;
+        }
         self.setup_interfaces(&netns, interfaces)?;
+        if let Some(membership) = &self.private_network {
+            self.setup_private_network(&netns, membership)?;
+        }
 
         Ok(())
     }
 
+    /// Sandboxes on an isolated private network can't reach the internet
+    fn internet_access(&self) -> bool {
+        self.private_network
+            .as_ref()
+            .is_none_or(|membership| membership.network().internet())
+    }
+
+    /// Target for the forwarding rules between veth and the host interface
+    fn internet_target(&self) -> Target {
+        match self.internet_access() {
+            true => Target::Accept,
+            false => Target::Drop,
+        }
+    }
+
+    pub fn private_veth(&self) -> (String, String) {
+        (
+            format!("{}-pv", self.namespace_name),
+            format!("{}-pp", self.namespace_name),
+        )
+    }
+
     pub fn veth(&self) -> (String, String) {
         let veth_name = format!("{}-veth", self.namespace_name);
         let veth_address = self.address_block.get_ip(IpAddressType::Veth);
@@ -75,8 +258,12 @@ impl Network {
         (vpeer_name, vpeer_address)
     }
 
-    pub fn microvm_ip(&self) -> String {
-        self.address_block.get_ip(IpAddressType::Microvm)
+    fn veth_ipv6(&self) -> String {
+        self.address_block.get_ipv6(IpAddressType::Veth)
+    }
+
+    fn vpeer_ipv6(&self) -> String {
+        self.address_block.get_ipv6(IpAddressType::Vpeer)
     }
 
     fn setup_interfaces(
@@ -87,7 +274,84 @@ impl Network {
         let (veth_device, _) = self.vpeer();
         netns.run(|_| {
             for interface in interfaces {
-                setup_tap_device(&interface.host_dev_name, veth_device.clone())?;
+                // The private tap device is attached to the bridge instead
+                if interface.host_dev_name == PRIVATE_TAP_DEVICE {
+                    continue;
+                }
+                setup_tap_device(
+                    &interface.host_dev_name,
+                    veth_device.clone(),
+                    self.options.ipv6,
+                )?;
+            }
+
+            Ok::<(), anyhow::Error>(())
+        })??;
+
+        Ok(())
+    }
+
+    /// Connects the microvm to the private network's bridge on the host. The
+    /// private tap device & the peer of a dedicated veth pair are bridged
+    /// inside of the namespace, so the microvm sits directly on the segment.
+    fn setup_private_network(
+        &self,
+        netns: &NetNs,
+        membership: &PrivateNetworkMembership,
+    ) -> anyhow::Result<()> {
+        let (veth_device_name, vpeer_device_name) = self.private_veth();
+
+        IpCommand::CreateVethPair {
+            veth: veth_device_name.clone(),
+            vpeer: vpeer_device_name.clone(),
+        }
+        .output()?;
+
+        IpCommand::SetMaster {
+            device: veth_device_name.clone(),
+            master: membership.network().bridge(),
+        }
+        .output()?;
+
+        IpCommand::Activate {
+            device: veth_device_name.clone(),
+        }
+        .output()?;
+
+        IpCommand::MoveDeviceToNamespace {
+            device: vpeer_device_name.clone(),
+            namespace: self.namespace_name.clone(),
+        }
+        .output()?;
+
+        netns.run(|_| {
+            IpCommand::CreateBridge {
+                device: PRIVATE_BRIDGE_DEVICE.into(),
+            }
+            .output()?;
+
+            IpCommand::CreateTapDevice {
+                device: PRIVATE_TAP_DEVICE.into(),
+            }
+            .output()?;
+
+            for device in [vpeer_device_name.as_str(), PRIVATE_TAP_DEVICE] {
+                IpCommand::SetMaster {
+                    device: device.into(),
+                    master: PRIVATE_BRIDGE_DEVICE.into(),
+                }
+                .output()?;
+            }
+
+            for device in [
+                PRIVATE_BRIDGE_DEVICE,
+                vpeer_device_name.as_str(),
+                PRIVATE_TAP_DEVICE,
+            ] {
+                IpCommand::Activate {
+                    device: device.into(),
+                }
+                .output()?;
             }
 
             Ok::<(), anyhow::Error>(())
@@ -96,6 +360,49 @@ impl Network {
         Ok(())
     }
 
+    fn setup_dns_forwarder(
+        &self,
+        interfaces: &[NetworkInterface],
+    ) -> anyhow::Result<Option<DnsForwarder>> {
+        let Some(policy) = &self.options.dns_policy else {
+            return Ok(None);
+        };
+
+        let netns = NetNs::get(&self.namespace_name)?;
+        // Only the forwarder may be used to resolve names, otherwise the
+        // policy could be bypassed by querying a nameserver directly. The
+        // rules are inserted as the tap device's traffic is already accepted.
+        netns.run(|_| {
+            for interface in interfaces {
+                for protocol in ["udp", "tcp"] {
+                    let rule = || IpTablesCommand::InsertPortRule {
+                        table: Table::Forward,
+                        target: Target::Drop,
+                        input: interface.host_dev_name.clone(),
+                        protocol: protocol.into(),
+                        port: DNS_PORT,
+                    };
+                    rule().output()?;
+                    if self.options.ipv6 {
+                        rule().output_for(IpFamily::V6)?;
+                    }
+                }
+            }
+
+            Ok::<(), anyhow::Error>(())
+        })??;
+
+        let forwarder = DnsForwarder::spawn(
+            &self.namespace_name,
+            &netns,
+            SocketAddr::new(GUEST_GATEWAY.parse()?, DNS_PORT),
+            self.options.upstream_nameservers(),
+            policy.clone(),
+        )?;
+
+        Ok(Some(forwarder))
+    }
+
     fn setup_veth_devices(&self, netns: &NetNs) -> anyhow::Result<()> {
         let (veth_device_name, host_address) = self.veth();
         let (vpeer_device_name, peer_address) = self.vpeer();
@@ -158,7 +465,7 @@ impl Network {
 
             IpTablesCommand::RewriteSource {
                 output: vpeer_device_name.clone(),
-                source: "172.16.0.2".into(),
+                source: GUEST_IP.into(),
                 to: self.microvm_ip(),
             }
             .output()?;
@@ -166,7 +473,7 @@ impl Network {
             IpTablesCommand::RewriteDestination {
                 input: vpeer_device_name.clone(),
                 destination: self.microvm_ip(),
-                to: "172.16.0.2".into(),
+                to: GUEST_IP.into(),
             }
             .output()?;
 
@@ -179,22 +486,24 @@ impl Network {
         }
         .output()?;
 
-        IpTablesCommand::EnableMasquerade {
-            source_address: Some(format!("{}/29", peer_address.clone())),
-            output: HOST_INTERFACE_NAME.into(),
+        if self.internet_access() {
+            IpTablesCommand::EnableMasquerade {
+                source_address: Some(format!("{}/29", peer_address.clone())),
+                output: HOST_INTERFACE_NAME.into(),
+            }
+            .output()?;
         }
-        .output()?;
 
         IpTablesCommand::AddRule {
             table: Table::Forward,
-            target: Target::Accept,
+            target: self.internet_target(),
             input: veth_device_name.clone(),
             output: HOST_INTERFACE_NAME.into(),
         }
         .output()?;
         IpTablesCommand::AddRule {
             table: Table::Forward,
-            target: Target::Accept,
+            target: self.internet_target(),
             input: HOST_INTERFACE_NAME.into(),
             output: veth_device_name.clone(),
         }
@@ -202,6 +511,166 @@ impl Network {
 
         Ok(())
     }
+
+    /// Mirrors the ipv4 setup for ipv6. The microvm is NAT'ed (NAT66) from
+    /// the guest address to its own address in the sandbox's /64.
+    fn setup_ipv6(&self, netns: &NetNs) -> anyhow::Result<()> {
+        let (veth_device_name, _) = self.veth();
+        let (vpeer_device_name, _) = self.vpeer();
+        let host_address = self.veth_ipv6();
+        let peer_address = self.vpeer_ipv6();
+        let microvm_address = self.address_block.get_ipv6(IpAddressType::Microvm);
+
+        IpCommand::AddIpv6Address {
+            cidr_block: format!("{host_address}/64"),
+            device: veth_device_name.clone(),
+        }
+        .output()?;
+
+        netns.run(|_| {
+            SysctlCommand::Set {
+                key: "net.ipv6.conf.all.forwarding".into(),
+                value: "1".into(),
+            }
+            .output()?;
+
+            IpCommand::AddIpv6Address {
+                cidr_block: format!("{peer_address}/64"),
+                device: vpeer_device_name.clone(),
+            }
+            .output()?;
+
+            IpCommand::AddDefaultRoute {
+                address: host_address.clone(),
+            }
+            .output()?;
+
+            IpTablesCommand::RewriteSource {
+                output: vpeer_device_name.clone(),
+                source: GUEST_IPV6.into(),
+                to: microvm_address.clone(),
+            }
+            .output_for(IpFamily::V6)?;
+
+            IpTablesCommand::RewriteDestination {
+                input: vpeer_device_name.clone(),
+                destination: microvm_address.clone(),
+                to: GUEST_IPV6.into(),
+            }
+            .output_for(IpFamily::V6)?;
+
+            Ok::<(), anyhow::Error>(())
+        })??;
+
+        IpCommand::AddRoute {
+            to: microvm_address.clone(),
+            via: peer_address.clone(),
+        }
+        .output()?;
+
+        if self.internet_access() {
+            IpTablesCommand::EnableMasquerade {
+                source_address: Some(format!("{peer_address}/64")),
+                output: HOST_INTERFACE_NAME.into(),
+            }
+            .output_for(IpFamily::V6)?;
+        }
+
+        IpTablesCommand::AddRule {
+            table: Table::Forward,
+            target: self.internet_target(),
+            input: veth_device_name.clone(),
+            output: HOST_INTERFACE_NAME.into(),
+        }
+        .output_for(IpFamily::V6)?;
+        IpTablesCommand::AddRule {
+            table: Table::Forward,
+            target: self.internet_target(),
+            input: HOST_INTERFACE_NAME.into(),
+            output: veth_device_name.clone(),
+        }
+        .output_for(IpFamily::V6)?;
+
+        Ok(())
+    }
+
+    fn teardown_ipv6(&self) {
+        let (veth_name, _) = self.veth();
+
+        IpTablesCommand::DeleteRule {
+            table: Table::Forward,
+            target: self.internet_target(),
+            input: veth_name.clone(),
+            output: HOST_INTERFACE_NAME.into(),
+        }
+        .output_for(IpFamily::V6)
+        .unwrap();
+
+        IpTablesCommand::DeleteRule {
+            table: Table::Forward,
+            target: self.internet_target(),
+            output: veth_name,
+            input: HOST_INTERFACE_NAME.into(),
+        }
+        .output_for(IpFamily::V6)
+        .unwrap();
+
+        if self.internet_access() {
+            IpTablesCommand::DisableMasquerade {
+                source_address: Some(format!("{}/64", self.vpeer_ipv6())),
+                output: HOST_INTERFACE_NAME.into(),
+            }
+            .output_for(IpFamily::V6)
+            .unwrap();
+        }
+    }
+}
+
+impl SandboxNetwork for Network {
+    fn address_block(&self) -> &AddressBlock {
+        &self.address_block
+    }
+
+    fn options(&self) -> &NetworkOptions {
+        &self.options
+    }
+
+    fn private_network(&self) -> Option<&PrivateNetworkMembership> {
+        self.private_network.as_ref()
+    }
+
+    fn netns_path(&self) -> anyhow::Result<PathBuf> {
+        NetNs::get(&self.namespace_name)
+            .map(|ns| ns.path().to_owned())
+            .context("Failed to get network namespace")
+    }
+
+    /// Captures inside of the network namespace
+    fn start_capture(
+        &self,
+        options: CaptureOptions,
+        path: PathBuf,
+    ) -> anyhow::Result<PacketCapture> {
+        let interface = match options.device {
+            CaptureDevice::Tap => PRIMARY_TAP_DEVICE.to_string(),
+            CaptureDevice::Vpeer => self.vpeer().0,
+        };
+        let netns = NetNs::get(&self.namespace_name)?;
+        PacketCapture::start(&netns, &interface, options, path)
+    }
+
+    /// Traffic over the veth devices of the network namespace, the private
+    /// network's included
+    fn traffic(&self) -> anyhow::Result<TrafficCounters> {
+        let (veth_device_name, _) = self.veth();
+        let traffic = TrafficCounters::from_host_device(&veth_device_name)?;
+        if self.private_network.is_none() {
+            return Ok(traffic);
+        }
+
+        let (private_veth_device_name, _) = self.private_veth();
+        Ok(traffic + TrafficCounters::from_host_device(&private_veth_device_name)?)
+    }
 }
 
 impl Drop for Network {
@@ -221,7 +690,7 @@ impl Drop for Network {
 
         IpTablesCommand::DeleteRule {
             table: Table::Forward,
-            target: Target::Accept,
+            target: self.internet_target(),
             input: veth_name.clone(),
             output: HOST_INTERFACE_NAME.into(),
         }
@@ -230,23 +699,38 @@ impl Drop for Network {
 
         IpTablesCommand::DeleteRule {
             table: Table::Forward,
-            target: Target::Accept,
+            target: self.internet_target(),
             output: veth_name,
             input: HOST_INTERFACE_NAME.into(),
         }
         .output()
         .unwrap();
 
-        IpTablesCommand::DisableMasquerade {
-            source_address: Some(format!("{peer_address}/29")),
-            output: HOST_INTERFACE_NAME.into(),
+        if self.internet_access() {
+            IpTablesCommand::DisableMasquerade {
+                source_address: Some(format!("{peer_address}/29")),
+                output: HOST_INTERFACE_NAME.into(),
+            }
+            .output()
+            .unwrap();
+        }
+
+        if self.private_network.is_some() {
+            let (private_veth_name, _) = self.private_veth();
+            IpCommand::DeleteDevice {
+                device: private_veth_name,
+            }
+            .output()
+            .unwrap();
+        }
+
+        if self.options.ipv6 {
+            self.teardown_ipv6();
         }
-        .output()
-        .unwrap();
     }
 }
 
-fn setup_tap_device(device: &str, veth_device: String) -> anyhow::Result<()> {
+fn setup_tap_device(device: &str, veth_device: String, ipv6: bool) -> anyhow::Result<()> {
     IpCommand::CreateTapDevice {
         device: device.to_string(),
     }
@@ -267,8 +751,24 @@ fn setup_tap_device(device: &str, veth_device: String) -> anyhow::Result<()> {
         table: Table::Forward,
         target: Target::Accept,
         input: device.to_string(),
-        output: veth_device,
+        output: veth_device.clone(),
     }
     .output()?;
+
+    if ipv6 {
+        IpCommand::AddIpv6Address {
+            cidr_block: DEFAULT_IPV6_CIDR_BLOCK.into(),
+            device: device.to_string(),
+        }
+        .output()?;
+
+        IpTablesCommand::AddRule {
+            table: Table::Forward,
+            target: Target::Accept,
+            input: device.to_string(),
+            output: veth_device,
+        }
+        .output_for(IpFamily::V6)?;
+    }
     Ok(())
 }
@@ -0,0 +1,423 @@
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{self, BufWriter, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use derive_builder::Builder;
+use firecracker_config_rs::validation::ValidationErrors;
+use netns_rs::NetNs;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_CAPTURE_DURATION: Duration = Duration::from_secs(30);
+pub const MAX_CAPTURE_DURATION: Duration = Duration::from_secs(300);
+pub const DEFAULT_CAPTURE_BYTES: u64 = 10 * 1024 * 1024;
+pub const MAX_CAPTURE_BYTES: u64 = 100 * 1024 * 1024;
+/// The kernel refuses longer classic BPF programs
+const MAX_FILTER_INSTRUCTIONS: usize = 4096;
+
+/// Longest part of a packet which is captured
+const SNAPSHOT_LENGTH: usize = 65535;
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const LINKTYPE_ETHERNET: u32 = 1;
+const PCAP_HEADER_LENGTH: u64 = 24;
+const PCAP_RECORD_HEADER_LENGTH: u64 = 16;
+/// How often the capture checks whether it should stop while no packets arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Device inside of the sandbox's network namespace to capture on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureDevice {
+    /// The tap device of the microvm, sees all of the microvm's traffic
+    #[default]
+    Tap,
+    /// The namespace end of the veth pair, sees the traffic after NAT
+    Vpeer,
+}
+
+/// A classic BPF instruction, in the format printed by `tcpdump -dd`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BpfInstruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+#[derive(Builder, Clone, Debug)]
+#[builder(setter(into))]
+pub struct CaptureOptions {
+    #[builder(default)]
+    pub device: CaptureDevice,
+    /// Only packets accepted by this program are captured, everything is
+    /// captured when it's empty
+    #[builder(default)]
+    pub filter: Vec<BpfInstruction>,
+    #[builder(default = "DEFAULT_CAPTURE_DURATION")]
+    pub duration: Duration,
+    /// Size limit of the capture file
+    #[builder(default = "DEFAULT_CAPTURE_BYTES")]
+    pub max_bytes: u64,
+}
+
+impl CaptureOptions {
+    /// Errors are reported at the fields of a capture request
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if self.duration > MAX_CAPTURE_DURATION {
+            errors.add(
+                "duration_secs",
+                format!(
+                    "Captures can't run longer than {}s",
+                    MAX_CAPTURE_DURATION.as_secs()
+                ),
+            );
+        }
+        if self.max_bytes > MAX_CAPTURE_BYTES {
+            errors.add(
+                "max_bytes",
+                format!("Captures can't be larger than {MAX_CAPTURE_BYTES} bytes"),
+            );
+        }
+        if self.filter.len() > MAX_FILTER_INSTRUCTIONS {
+            errors.add(
+                "filter",
+                format!("Filters can't have more than {MAX_FILTER_INSTRUCTIONS} instructions"),
+            );
+        }
+
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CaptureStatus {
+    pub device: CaptureDevice,
+    pub packets: u64,
+    /// Size of the capture file
+    pub bytes: u64,
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    finished: AtomicBool,
+    error: Mutex<Option<String>>,
+}
+
+/// A capture running on its own thread, stopped once its duration or size
+/// limit is reached or when it's dropped
+#[derive(Debug)]
+pub struct PacketCapture {
+    device: CaptureDevice,
+    path: PathBuf,
+    progress: Arc<Progress>,
+    stop: Arc<AtomicBool>,
+}
+
+impl PacketCapture {
+    pub fn start(
+        netns: &NetNs,
+        interface: &str,
+        options: CaptureOptions,
+        path: PathBuf,
+    ) -> anyhow::Result<PacketCapture> {
+        // A socket stays in the namespace it was created in, so open it while
+        // we're inside of the sandbox's network namespace.
+        let socket = netns.run(|_| open_socket(interface, &options.filter))??;
+
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create capture file {}", path.display()))?;
+
+        let capture = PacketCapture {
+            device: options.device,
+            path,
+            progress: Default::default(),
+            stop: Default::default(),
+        };
+
+        let progress = capture.progress.clone();
+        let stop = capture.stop.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = capture_packets(&socket, file, &options, &progress, &stop) {
+                println!("packet capture failed: {e:?}");
+                *progress.error.lock().unwrap() = Some(e.to_string());
+            }
+            progress.finished.store(true, Ordering::SeqCst);
+        });
+
+        Ok(capture)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress.finished.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> CaptureStatus {
+        CaptureStatus {
+            device: self.device,
+            packets: self.progress.packets.load(Ordering::SeqCst),
+            bytes: self.progress.bytes.load(Ordering::SeqCst),
+            finished: self.is_finished(),
+            error: self.progress.error.lock().unwrap().clone(),
+        }
+    }
+}
+
+impl Drop for PacketCapture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Opens an AF_PACKET socket which only receives the packets of `interface`
+/// that pass the filter
+fn open_socket(interface: &str, filter: &[BpfInstruction]) -> anyhow::Result<OwnedFd> {
+    let name = CString::new(interface)?;
+    // SAFETY: `name` is a valid nul terminated string
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Device {interface} does not exist"));
+    }
+
+    // The socket doesn't receive anything until it's bound, so unfiltered
+    // packets of other devices never end up in the capture.
+    // SAFETY: the returned descriptor is checked & owned right away
+    let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to open a packet socket");
+    }
+    // SAFETY: `fd` is a freshly opened descriptor nothing else owns
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    if !filter.is_empty() {
+        let mut instructions = filter
+            .iter()
+            .map(|instruction| libc::sock_filter {
+                code: instruction.code,
+                jt: instruction.jt,
+                jf: instruction.jf,
+                k: instruction.k,
+            })
+            .collect::<Vec<_>>();
+        let program = libc::sock_fprog {
+            len: instructions.len() as u16,
+            filter: instructions.as_mut_ptr(),
+        };
+        set_option(&socket, libc::SO_ATTACH_FILTER, &program)
+            .context("Failed to attach the capture filter")?;
+    }
+
+    let timeout = libc::timeval {
+        tv_sec: 0,
+        tv_usec: POLL_INTERVAL.as_micros() as libc::suseconds_t,
+    };
+    set_option(&socket, libc::SO_RCVTIMEO, &timeout)?;
+
+    // SAFETY: an all zero sockaddr_ll is valid, the relevant fields are set below
+    let mut address: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+    address.sll_family = libc::AF_PACKET as u16;
+    address.sll_protocol = (libc::ETH_P_ALL as u16).to_be();
+    address.sll_ifindex = index as i32;
+    // SAFETY: `address` is a sockaddr_ll & its size is passed along
+    let result = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            &address as *const libc::sockaddr_ll as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Failed to bind the packet socket to {interface}"));
+    }
+
+    Ok(socket)
+}
+
+fn set_option<T>(socket: &OwnedFd, option: libc::c_int, value: &T) -> io::Result<()> {
+    // SAFETY: `value` points to a `T` & its size is passed along
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+fn capture_packets(
+    socket: &OwnedFd,
+    file: File,
+    options: &CaptureOptions,
+    progress: &Progress,
+    stop: &AtomicBool,
+) -> anyhow::Result<()> {
+    let mut writer = PcapWriter::new(BufWriter::new(file))?;
+    let deadline = Instant::now() + options.duration;
+    let mut buffer = vec![0u8; SNAPSHOT_LENGTH];
+
+    while !stop.load(Ordering::SeqCst) && Instant::now() < deadline {
+        // With MSG_TRUNC the real length of truncated packets is returned
+        // SAFETY: the buffer is valid for writes of its length
+        let length = unsafe {
+            libc::recv(
+                socket.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                libc::MSG_TRUNC,
+            )
+        };
+        if length < 0 {
+            let error = io::Error::last_os_error();
+            match error.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => continue,
+                _ => return Err(error).context("Failed to receive a packet"),
+            }
+        }
+
+        let original_length = length as usize;
+        let data = &buffer[..original_length.min(buffer.len())];
+        if writer.bytes_written() + PCAP_RECORD_HEADER_LENGTH + data.len() as u64
+            > options.max_bytes
+        {
+            break;
+        }
+
+        writer.write_packet(SystemTime::now(), data, original_length)?;
+        progress.packets.fetch_add(1, Ordering::SeqCst);
+        progress
+            .bytes
+            .store(writer.bytes_written(), Ordering::SeqCst);
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes packets in the classic libpcap file format
+struct PcapWriter<W: Write> {
+    writer: W,
+    bytes_written: u64,
+}
+
+impl<W: Write> PcapWriter<W> {
+    fn new(mut writer: W) -> io::Result<PcapWriter<W>> {
+        writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        // Version 2.4
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        // Timestamps are in UTC & their accuracy is unknown
+        writer.write_all(&0i32.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&(SNAPSHOT_LENGTH as u32).to_le_bytes())?;
+        writer.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+
+        Ok(PcapWriter {
+            writer,
+            bytes_written: PCAP_HEADER_LENGTH,
+        })
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    fn write_packet(
+        &mut self,
+        timestamp: SystemTime,
+        data: &[u8],
+        original_length: usize,
+    ) -> io::Result<()> {
+        let timestamp = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.writer
+            .write_all(&(timestamp.as_secs() as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&timestamp.subsec_micros().to_le_bytes())?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&(original_length as u32).to_le_bytes())?;
+        self.writer.write_all(data)?;
+
+        self.bytes_written += PCAP_RECORD_HEADER_LENGTH + data.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{CaptureOptionsBuilder, PcapWriter, MAX_CAPTURE_DURATION};
+
+    #[test]
+    fn writes_the_pcap_header_and_records() {
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        let timestamp = UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_250);
+        writer
+            .write_packet(timestamp, &[0xde, 0xad, 0xbe, 0xef], 60)
+            .unwrap();
+        assert_eq!(writer.bytes_written(), 24 + 16 + 4);
+
+        let bytes = writer.writer;
+        assert_eq!(bytes.len(), 44);
+        assert_eq!(bytes[..4], [0xd4, 0xc3, 0xb2, 0xa1], "little endian magic");
+        assert_eq!(bytes[4..8], [2, 0, 4, 0], "version 2.4");
+        assert_eq!(bytes[16..20], 65535u32.to_le_bytes(), "snapshot length");
+        assert_eq!(bytes[20..24], 1u32.to_le_bytes(), "ethernet link type");
+
+        assert_eq!(bytes[24..28], 1_700_000_000u32.to_le_bytes());
+        assert_eq!(bytes[28..32], 250u32.to_le_bytes());
+        assert_eq!(bytes[32..36], 4u32.to_le_bytes(), "captured length");
+        assert_eq!(bytes[36..40], 60u32.to_le_bytes(), "original length");
+        assert_eq!(bytes[40..], [0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let options = |builder: &mut CaptureOptionsBuilder| builder.build().unwrap().validate();
+        assert!(options(&mut CaptureOptionsBuilder::default()).is_ok());
+        let errors = options(
+            CaptureOptionsBuilder::default()
+                .duration(MAX_CAPTURE_DURATION + Duration::from_secs(1))
+                .max_bytes(u64::MAX),
+        )
+        .unwrap_err();
+        let paths: Vec<_> = errors
+            .errors
+            .iter()
+            .map(|error| error.path.as_str())
+            .collect();
+        assert_eq!(paths, ["duration_secs", "max_bytes"]);
+    }
+}
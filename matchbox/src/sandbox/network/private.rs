@@ -0,0 +1,217 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use firecracker_config_rs::validation::ValidationErrors;
+use serde::{Deserialize, Serialize};
+
+use crate::server::routes::error::NotFound;
+
+use super::commands::IpCommand;
+
+/// Private networks get a /24 out of 10.201.0.0/16
+const PRIVATE_NETWORK_BASE: &str = "10.201";
+const MAX_PRIVATE_NETWORKS: usize = 256;
+const FIRST_MEMBER_OCTET: u8 = 2;
+const LAST_MEMBER_OCTET: u8 = 254;
+
+/// Device names the microvm is attached to its private network with
+pub const PRIVATE_TAP_DEVICE: &str = "tap1";
+pub const PRIVATE_GUEST_INTERFACE: &str = "eth1";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PrivateNetworkOptions {
+    /// Whether members of the network can reach the internet
+    #[serde(default = "default_internet")]
+    pub internet: bool,
+}
+
+fn default_internet() -> bool {
+    true
+}
+
+impl Default for PrivateNetworkOptions {
+    fn default() -> Self {
+        Self { internet: true }
+    }
+}
+
+/// A layer 2 segment shared by a group of sandboxes. The segment is a bridge
+/// on the host which every member's private veth device is attached to.
+#[derive(Debug)]
+pub struct PrivateNetwork {
+    name: String,
+    index: u8,
+    options: PrivateNetworkOptions,
+    members: Mutex<BTreeSet<u8>>,
+}
+
+impl PrivateNetwork {
+    fn create(
+        name: &str,
+        index: u8,
+        options: PrivateNetworkOptions,
+    ) -> anyhow::Result<PrivateNetwork> {
+        let network = PrivateNetwork {
+            name: name.to_string(),
+            index,
+            options,
+            members: Default::default(),
+        };
+
+        IpCommand::CreateBridge {
+            device: network.bridge(),
+        }
+        .output()?;
+        IpCommand::Activate {
+            device: network.bridge(),
+        }
+        .output()?;
+
+        Ok(network)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn internet(&self) -> bool {
+        self.options.internet
+    }
+
+    pub fn subnet(&self) -> String {
+        format!("{PRIVATE_NETWORK_BASE}.{}.0/24", self.index)
+    }
+
+    pub fn bridge(&self) -> String {
+        format!("mbn-{}", self.index)
+    }
+
+    pub fn member_count(&self) -> usize {
+        self.members.lock().unwrap().len()
+    }
+
+    /// Reserves an address on the network for a new member
+    pub fn join(self: &Arc<Self>) -> anyhow::Result<PrivateNetworkMembership> {
+        let mut members = self.members.lock().unwrap();
+        let octet = (FIRST_MEMBER_OCTET..=LAST_MEMBER_OCTET)
+            .find(|octet| !members.contains(octet))
+            .ok_or_else(|| anyhow::anyhow!("Private network {} is full", self.name))?;
+        members.insert(octet);
+
+        Ok(PrivateNetworkMembership {
+            network: self.clone(),
+            octet,
+        })
+    }
+}
+
+impl Drop for PrivateNetwork {
+    fn drop(&mut self) {
+        IpCommand::DeleteDevice {
+            device: self.bridge(),
+        }
+        .output()
+        .unwrap();
+    }
+}
+
+/// A sandbox's address on a private network, released when dropped
+#[derive(Debug)]
+pub struct PrivateNetworkMembership {
+    network: Arc<PrivateNetwork>,
+    octet: u8,
+}
+
+impl PrivateNetworkMembership {
+    pub fn network(&self) -> &PrivateNetwork {
+        &self.network
+    }
+
+    pub fn address(&self) -> String {
+        format!(
+            "{PRIVATE_NETWORK_BASE}.{}.{}",
+            self.network.index, self.octet
+        )
+    }
+
+    pub fn cidr_block(&self) -> String {
+        format!("{}/24", self.address())
+    }
+
+    /// Locally administered MAC address which is unique on the network
+    pub fn guest_mac(&self) -> String {
+        format!("06:00:0A:C9:{:02X}:{:02X}", self.network.index, self.octet)
+    }
+}
+
+impl Drop for PrivateNetworkMembership {
+    fn drop(&mut self) {
+        self.network.members.lock().unwrap().remove(&self.octet);
+    }
+}
+
+/// All private networks known to matchbox, by name
+#[derive(Debug, Default)]
+pub struct PrivateNetworks {
+    networks: Mutex<HashMap<String, Arc<PrivateNetwork>>>,
+}
+
+impl PrivateNetworks {
+    pub fn create(
+        &self,
+        name: &str,
+        options: PrivateNetworkOptions,
+    ) -> anyhow::Result<Arc<PrivateNetwork>> {
+        let mut networks = self.networks.lock().unwrap();
+        if networks.contains_key(name) {
+            let mut errors = ValidationErrors::default();
+            errors.add("name", format!("Private network {name} already exists"));
+            return Err(errors.into());
+        }
+
+        let index = (0..MAX_PRIVATE_NETWORKS)
+            .map(|index| index as u8)
+            .find(|index| !networks.values().any(|network| network.index == *index))
+            .ok_or_else(|| anyhow::anyhow!("No private network ranges left"))?;
+
+        let network = Arc::new(PrivateNetwork::create(name, index, options)?);
+        networks.insert(name.to_string(), network.clone());
+        Ok(network)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<PrivateNetwork>> {
+        self.networks.lock().unwrap().get(name).cloned()
+    }
+
+    /// Looks up the network, creating it with the default options if this is
+    /// the first time it's used
+    pub fn get_or_create(&self, name: &str) -> anyhow::Result<Arc<PrivateNetwork>> {
+        match self.get(name) {
+            Some(network) => Ok(network),
+            None => self.create(name, PrivateNetworkOptions::default()),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Arc<PrivateNetwork>> {
+        self.networks.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn remove(&self, name: &str) -> anyhow::Result<Arc<PrivateNetwork>> {
+        let mut networks = self.networks.lock().unwrap();
+        let Some(network) = networks.get(name) else {
+            return Err(NotFound(format!("Private network {name} does not exist")).into());
+        };
+        if network.member_count() > 0 {
+            let mut errors = ValidationErrors::default();
+            errors.add(
+                "name",
+                format!("Private network {name} still has sandboxes attached to it"),
+            );
+            return Err(errors.into());
+        }
+
+        Ok(networks.remove(name).unwrap())
+    }
+}
@@ -0,0 +1,99 @@
+use std::{ops::Add, path::Path};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Where the kernel lists network devices
+const SYSFS_NET: &str = "/sys/class/net";
+
+/// Traffic counters from the point of view of the microvm
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrafficCounters {
+    /// Bytes received by the microvm
+    pub rx_bytes: u64,
+    /// Packets received by the microvm
+    pub rx_packets: u64,
+    /// Bytes sent by the microvm
+    pub tx_bytes: u64,
+    /// Packets sent by the microvm
+    pub tx_packets: u64,
+}
+
+impl TrafficCounters {
+    /// Reads the counters of a host level device which carries the microvm's
+    /// traffic. Whatever the device receives was sent by the microvm and vice
+    /// versa.
+    pub fn from_host_device(device: &str) -> anyhow::Result<TrafficCounters> {
+        Self::from_sysfs(Path::new(SYSFS_NET), device)
+    }
+
+    fn from_sysfs(sysfs_net: &Path, device: &str) -> anyhow::Result<TrafficCounters> {
+        let statistics = sysfs_net.join(device).join("statistics");
+        read_counters(&statistics)
+            .with_context(|| format!("Failed to read traffic counters of {device}"))
+    }
+}
+
+impl Add for TrafficCounters {
+    type Output = TrafficCounters;
+
+    fn add(self, other: TrafficCounters) -> TrafficCounters {
+        TrafficCounters {
+            rx_bytes: self.rx_bytes + other.rx_bytes,
+            rx_packets: self.rx_packets + other.rx_packets,
+            tx_bytes: self.tx_bytes + other.tx_bytes,
+            tx_packets: self.tx_packets + other.tx_packets,
+        }
+    }
+}
+
+fn read_counters(statistics: &Path) -> anyhow::Result<TrafficCounters> {
+    let read = |name: &str| -> anyhow::Result<u64> {
+        let path = statistics.join(name);
+        let value = std::fs::read_to_string(&path)?;
+        value
+            .trim()
+            .parse()
+            .with_context(|| format!("{} is not a counter", path.display()))
+    };
+
+    Ok(TrafficCounters {
+        rx_bytes: read("tx_bytes")?,
+        rx_packets: read("tx_packets")?,
+        tx_bytes: read("rx_bytes")?,
+        tx_packets: read("rx_packets")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrafficCounters;
+
+    #[test]
+    fn counters_are_flipped_to_the_microvm_perspective() {
+        let sysfs_net = tempfile::tempdir().unwrap();
+        let statistics = sysfs_net.path().join("vm-veth").join("statistics");
+        std::fs::create_dir_all(&statistics).unwrap();
+        // The host side of the veth received a download's request & sent
+        // its response
+        for (name, value) in [
+            ("rx_bytes", "98\n"),
+            ("rx_packets", "1\n"),
+            ("tx_bytes", "1500\n"),
+            ("tx_packets", "3\n"),
+        ] {
+            std::fs::write(statistics.join(name), value).unwrap();
+        }
+
+        assert_eq!(
+            TrafficCounters::from_sysfs(sysfs_net.path(), "vm-veth").unwrap(),
+            TrafficCounters {
+                rx_bytes: 1500,
+                rx_packets: 3,
+                tx_bytes: 98,
+                tx_packets: 1,
+            }
+        );
+        assert!(TrafficCounters::from_sysfs(sysfs_net.path(), "missing").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use firecracker_config_rs::validation::ValidationErrors;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_VCPUS: u8 = 1;
+pub const DEFAULT_MEMORY_MIB: u32 = 128;
+const MAX_VCPUS: u8 = 32;
+const MIN_MEMORY_MIB: u32 = 64;
+
+/// Block device limits of the VMM process
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct IoLimits {
+    pub read_bytes_per_sec: Option<u64>,
+    pub write_bytes_per_sec: Option<u64>,
+    pub read_iops: Option<u64>,
+    pub write_iops: Option<u64>,
+}
+
+impl IoLimits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == IoLimits::default()
+    }
+}
+
+/// The resources a sandbox gets. vCPUs & memory are what the guest sees, the
+/// remaining limits are enforced on the VMM process by the host.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SandboxResources {
+    pub vcpus: u8,
+    pub memory_mib: u32,
+    /// Cpu time of the VMM process in percent of a host core, defaults to a
+    /// full core per vCPU
+    pub cpu_percent: Option<u32>,
+    /// Most processes & threads the VMM process can have
+    pub pids: Option<u32>,
+    pub io: IoLimits,
+}
+
+impl Default for SandboxResources {
+    fn default() -> Self {
+        Self {
+            vcpus: DEFAULT_VCPUS,
+            memory_mib: DEFAULT_MEMORY_MIB,
+            cpu_percent: None,
+            pids: None,
+            io: IoLimits::default(),
+        }
+    }
+}
+
+impl SandboxResources {
+    /// Every problem with the requested resources, fields are below
+    /// `resources`
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if self.vcpus == 0 || self.vcpus > MAX_VCPUS {
+            errors.add(
+                "resources.vcpus",
+                format!("Has to be between 1 and {MAX_VCPUS}"),
+            );
+        }
+        if self.memory_mib < MIN_MEMORY_MIB {
+            errors.add(
+                "resources.memory_mib",
+                format!("Has to be at least {MIN_MEMORY_MIB}"),
+            );
+        }
+        if self.cpu_percent == Some(0) {
+            errors.add("resources.cpu_percent", "Has to be positive");
+        }
+        if self.pids == Some(0) {
+            errors.add("resources.pids", "Has to be positive");
+        }
+
+        errors.into_result()
+    }
+}
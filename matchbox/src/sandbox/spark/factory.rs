@@ -1,10 +1,10 @@
 use std::fmt::Debug;
 
-use super::SparkClient;
+use super::{SparkAddress, SparkClient};
 
 #[async_trait::async_trait]
 pub trait ProvideSparkClient: Debug + Send + Sync {
-    async fn provide_spark_client(&self, address: &str) -> anyhow::Result<SparkClient>;
+    async fn provide_spark_client(&self, address: &SparkAddress) -> anyhow::Result<SparkClient>;
 }
 
 #[derive(Debug, Default)]
@@ -12,7 +12,7 @@ pub struct SparkClientFactory;
 
 #[async_trait::async_trait]
 impl ProvideSparkClient for SparkClientFactory {
-    async fn provide_spark_client(&self, address: &str) -> anyhow::Result<SparkClient> {
+    async fn provide_spark_client(&self, address: &SparkAddress) -> anyhow::Result<SparkClient> {
         SparkClient::initialize(address).await
     }
 }
@@ -1,26 +1,228 @@
+use std::{collections::HashMap, io, path::PathBuf};
+
 use anyhow::Context;
-use sparklib::grpc::{
-    guest_agent_client::GuestAgentClient, ExecuteRequest, ExecuteResponse, HealthCheckRequest,
-    HealthCheckResponse, MountRequest, MountResponse,
+use futures_util::{
+    future,
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use sparklib::{
+    grpc::{
+        self, execute_stream_response, exit_status, guest_agent_client::GuestAgentClient,
+        write_file_request::Message, ExecuteRequest, ExecuteStreamResponse, HealthCheckRequest,
+        HealthCheckResponse, MountRequest, MountResponse, ReadFileRequest, StatFileRequest,
+        StatFileResponse, UnmountRequest, UnmountResponse, WriteFileHeader, WriteFileRequest,
+        WriteFileResponse,
+    },
+    SPARK_PORT,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
 };
 use tonic::{
-    transport::{Channel, Endpoint},
+    transport::{Channel, Endpoint, Uri},
     Request,
 };
+use tower::service_fn;
 
 pub mod factory;
 
+/// Longest acknowledgement firecracker sends for a vsock connection
+const MAX_VSOCK_ACK_LENGTH: usize = 32;
+
+/// How the host reaches spark-server inside of the microvm
+#[derive(Debug, Clone)]
+pub enum SparkAddress {
+    /// Over the sandbox's network
+    Tcp { ip: String },
+    /// Through the Unix socket backing the microvm's vsock device
+    Vsock { uds_path: PathBuf },
+}
+
+/// How a command in the guest exited
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    Code(i32),
+    /// The command was killed by this signal
+    Signal(i32),
+    /// The command & every process it started were killed once its timeout
+    /// passed
+    TimedOut,
+}
+
+/// How a command is executed in the guest
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ExecuteOptions {
+    /// Environment of the command, it doesn't inherit spark-server's
+    pub env: HashMap<String, String>,
+    /// Working directory, the code drive when unset
+    pub cwd: Option<String>,
+    /// The command & every process it started are killed after this long
+    pub timeout_ms: Option<u64>,
+    /// User the command runs as, root when unset
+    pub uid: Option<u32>,
+    /// Group the command runs as, root when unset
+    pub gid: Option<u32>,
+    /// Written to the command's stdin, which is closed afterwards
+    pub stdin: Option<String>,
+}
+
+impl ExecuteOptions {
+    fn request(&self, command: String, arguments: Vec<String>) -> ExecuteRequest {
+        ExecuteRequest {
+            command,
+            arguments,
+            env: self.env.clone(),
+            cwd: self.cwd.clone(),
+            timeout_ms: self.timeout_ms,
+            uid: self.uid,
+            gid: self.gid,
+            stdin: self.stdin.clone().unwrap_or_default().into_bytes(),
+        }
+    }
+}
+
+/// Output of a command as it is written, the last event is how it exited.
+/// Output is decoded as UTF-8, invalid sequences are replaced.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteEvent {
+    Stdout(String),
+    Stderr(String),
+    Exit(ExitStatus),
+}
+
+impl ExecuteEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExecuteEvent::Stdout(_) => "stdout",
+            ExecuteEvent::Stderr(_) => "stderr",
+            ExecuteEvent::Exit(_) => "exit",
+        }
+    }
+}
+
+/// Decodes a command's output as it is streamed, a character split across
+/// chunks is decoded once all of it arrived
+#[derive(Debug, Default)]
+struct OutputDecoder {
+    stdout: Utf8Tail,
+    stderr: Utf8Tail,
+}
+
+impl OutputDecoder {
+    /// Events of a streamed response, output which is only part of a
+    /// character yet has none
+    fn decode(&mut self, response: ExecuteStreamResponse) -> anyhow::Result<Vec<ExecuteEvent>> {
+        use execute_stream_response::Event;
+
+        let events = match response
+            .event
+            .context("spark sent an empty execute event")?
+        {
+            Event::Stdout(output) => vec![ExecuteEvent::Stdout(self.stdout.decode(output))],
+            Event::Stderr(output) => vec![ExecuteEvent::Stderr(self.stderr.decode(output))],
+            Event::Exit(exit) => vec![
+                ExecuteEvent::Stdout(self.stdout.finish()),
+                ExecuteEvent::Stderr(self.stderr.finish()),
+                ExecuteEvent::Exit(exit.try_into()?),
+            ],
+        };
+
+        Ok(events
+            .into_iter()
+            .filter(|event| match event {
+                ExecuteEvent::Stdout(output) | ExecuteEvent::Stderr(output) => !output.is_empty(),
+                ExecuteEvent::Exit(_) => true,
+            })
+            .collect())
+    }
+}
+
+/// The start of a UTF-8 character at the end of an output chunk
+#[derive(Debug, Default)]
+struct Utf8Tail(Vec<u8>);
+
+impl Utf8Tail {
+    /// Decodes the chunk after the previous tail & keeps its own tail
+    fn decode(&mut self, chunk: Vec<u8>) -> String {
+        let mut bytes = std::mem::take(&mut self.0);
+        bytes.extend(chunk);
+        // A character is at most 4 bytes long, so an incomplete one starts
+        // within the last 3
+        let incomplete = (bytes.len().saturating_sub(3)..bytes.len()).find(|start| {
+            matches!(
+                std::str::from_utf8(&bytes[*start..]),
+                Err(e) if e.valid_up_to() == 0 && e.error_len().is_none()
+            )
+        });
+        if let Some(start) = incomplete {
+            self.0 = bytes.split_off(start);
+        }
+
+        String::from_utf8_lossy(&bytes).into()
+    }
+
+    /// A tail left once the output ended was never a complete character
+    fn finish(&mut self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut self.0)).into()
+    }
+}
+
+impl TryFrom<grpc::ExitStatus> for ExitStatus {
+    type Error = anyhow::Error;
+
+    fn try_from(value: grpc::ExitStatus) -> Result<Self, Self::Error> {
+        Ok(
+            match value.status.context("spark sent an empty exit status")? {
+                exit_status::Status::Code(code) => ExitStatus::Code(code),
+                exit_status::Status::Signal(signal) => ExitStatus::Signal(signal),
+                exit_status::Status::TimedOut(_) => ExitStatus::TimedOut,
+            },
+        )
+    }
+}
+
+/// Output of a command which ran to completion
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecuteOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit: ExitStatus,
+}
+
 #[derive(Debug, Clone)]
 pub struct SparkClient {
     client: GuestAgentClient<Channel>,
 }
 
 impl SparkClient {
-    pub async fn initialize(ip: &str) -> anyhow::Result<SparkClient> {
-        let address = format!("http://{ip}:5001");
-        let channel = Endpoint::new(address)?.connect_lazy();
-        let client = GuestAgentClient::new(channel);
-        Ok(SparkClient { client })
+    pub async fn initialize(address: &SparkAddress) -> anyhow::Result<SparkClient> {
+        let channel = match address {
+            SparkAddress::Tcp { ip } => {
+                Endpoint::new(format!("http://{ip}:{SPARK_PORT}"))?.connect_lazy()
+            }
+            SparkAddress::Vsock { uds_path } => {
+                let uds_path = uds_path.clone();
+                // The uri is ignored, every connection goes through the socket
+                Endpoint::new(format!("http://localhost:{SPARK_PORT}"))?
+                    .connect_with_connector_lazy(service_fn(move |_: Uri| {
+                        connect_vsock(uds_path.clone())
+                    }))
+            }
+        };
+        Ok(SparkClient::from_channel(channel))
+    }
+
+    /// A client for spark-server behind an already configured channel
+    pub fn from_channel(channel: Channel) -> SparkClient {
+        SparkClient {
+            client: GuestAgentClient::new(channel),
+        }
     }
 
     pub async fn health_check(&mut self) -> anyhow::Result<HealthCheckResponse> {
@@ -45,16 +247,233 @@ impl SparkClient {
             .context("failed to mount drive in uVM")
     }
 
+    pub async fn unmount_drive(&mut self, path: String) -> anyhow::Result<UnmountResponse> {
+        let request = Request::new(UnmountRequest { path });
+        self.client
+            .unmount(request)
+            .await
+            .map(|r| r.into_inner())
+            .context("failed to unmount drive in uVM")
+    }
+
     pub async fn execute(
         &mut self,
         command: String,
         arguments: Vec<String>,
-    ) -> anyhow::Result<ExecuteResponse> {
-        let request = Request::new(ExecuteRequest { command, arguments });
-        self.client
+        options: &ExecuteOptions,
+    ) -> anyhow::Result<ExecuteOutput> {
+        let request = Request::new(options.request(command, arguments));
+        let response = self
+            .client
             .execute(request)
             .await
+            .context("failed to execute command in uVM")?
+            .into_inner();
+
+        Ok(ExecuteOutput {
+            stdout: response.output,
+            stderr: response.stderr,
+            exit: response
+                .exit
+                .context("spark sent no exit status")?
+                .try_into()?,
+        })
+    }
+
+    /// Executes a command & streams its output while it runs
+    pub async fn execute_stream(
+        &mut self,
+        command: String,
+        arguments: Vec<String>,
+        options: &ExecuteOptions,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<ExecuteEvent>>> {
+        let request = Request::new(options.request(command, arguments));
+        let events = self
+            .client
+            .execute_stream(request)
+            .await
+            .context("failed to execute command in uVM")?
+            .into_inner();
+
+        let mut decoder = OutputDecoder::default();
+        Ok(events
+            .map(move |event| {
+                event
+                    .context("failed to stream command output from uVM")
+                    .and_then(|event| decoder.decode(event))
+            })
+            .flat_map(|events| match events {
+                Ok(events) => stream::iter(events.into_iter().map(Ok).collect::<Vec<_>>()),
+                Err(e) => stream::iter(vec![Err(e)]),
+            })
+            .boxed())
+    }
+
+    /// Uploads a file in chunks, it is moved into place when the upload is
+    /// committed. Uploads whose chunks fail end early & can be resumed.
+    pub async fn write_file(
+        &mut self,
+        header: WriteFileHeader,
+        chunks: impl Stream<Item = anyhow::Result<Vec<u8>>> + Send + 'static,
+        commit: bool,
+    ) -> anyhow::Result<WriteFileResponse> {
+        let chunks = chunks
+            .map(Some)
+            .chain(stream::once(future::ready(None)))
+            .scan((), move |_, chunk| {
+                future::ready(match chunk {
+                    Some(Ok(chunk)) => Some(Message::Chunk(chunk)),
+                    Some(Err(_)) => None,
+                    None if commit => Some(Message::Commit(true)),
+                    None => None,
+                })
+            });
+        let requests = stream::once(future::ready(Message::Header(header)))
+            .chain(chunks)
+            .map(|message| WriteFileRequest {
+                message: Some(message),
+            });
+
+        self.client
+            .write_file(Request::new(requests))
+            .await
             .map(|r| r.into_inner())
-            .context("failed to execute command in uVM")
+            .context("failed to write file in uVM")
+    }
+
+    /// Downloads a file in chunks from `offset` on
+    pub async fn read_file(
+        &mut self,
+        path: String,
+        offset: u64,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<Vec<u8>>>> {
+        let request = Request::new(ReadFileRequest { path, offset });
+        let chunks = self
+            .client
+            .read_file(request)
+            .await
+            .context("failed to read file in uVM")?
+            .into_inner();
+
+        Ok(chunks
+            .map(|chunk| {
+                chunk
+                    .map(|chunk| chunk.chunk)
+                    .context("failed to stream file from uVM")
+            })
+            .boxed())
+    }
+
+    /// The file & its partial upload
+    pub async fn stat_file(&mut self, path: String) -> anyhow::Result<StatFileResponse> {
+        let request = Request::new(StatFileRequest { path });
+        self.client
+            .stat_file(request)
+            .await
+            .map(|r| r.into_inner())
+            .context("failed to stat file in uVM")
+    }
+}
+
+/// Firecracker forwards host initiated connections on the vsock socket to a
+/// guest port after a `CONNECT <port>` handshake
+async fn connect_vsock(uds_path: PathBuf) -> io::Result<UnixStream> {
+    let mut stream = UnixStream::connect(&uds_path).await?;
+    stream
+        .write_all(format!("CONNECT {SPARK_PORT}\n").as_bytes())
+        .await?;
+
+    // Read the acknowledgement byte by byte so none of the gRPC traffic after
+    // it is consumed
+    let mut ack = vec![];
+    loop {
+        let byte = stream.read_u8().await?;
+        if byte == b'\n' || ack.len() >= MAX_VSOCK_ACK_LENGTH {
+            break;
+        }
+        ack.push(byte);
+    }
+
+    if !ack.starts_with(b"OK ") {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!(
+                "vsock handshake with {} failed: {}",
+                uds_path.display(),
+                String::from_utf8_lossy(&ack)
+            ),
+        ));
+    }
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::UnixListener,
+    };
+
+    use sparklib::grpc::{execute_stream_response::Event, exit_status, ExecuteStreamResponse};
+
+    use super::{connect_vsock, ExecuteEvent, ExitStatus, OutputDecoder};
+
+    #[tokio::test]
+    async fn vsock_handshake_leaves_the_stream_untouched() {
+        let uds_path = std::env::temp_dir().join(format!("vsock-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&uds_path).unwrap();
+        let firecracker = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut request = String::new();
+            stream.read_line(&mut request).await.unwrap();
+            stream
+                .get_mut()
+                .write_all(b"OK 1073741824\nhello")
+                .await
+                .unwrap();
+            request
+        });
+
+        let mut stream = connect_vsock(uds_path.clone()).await.unwrap();
+        let mut data = [0u8; 5];
+        stream.read_exact(&mut data).await.unwrap();
+        std::fs::remove_file(&uds_path).unwrap();
+
+        assert_eq!(firecracker.await.unwrap(), "CONNECT 5001\n");
+        assert_eq!(&data, b"hello");
+    }
+
+    #[test]
+    fn characters_split_across_chunks_are_decoded_whole() {
+        let response = |event| ExecuteStreamResponse { event: Some(event) };
+        let output = "é🦀".as_bytes();
+        let mut decoder = OutputDecoder::default();
+
+        let mut events = vec![];
+        for event in [
+            Event::Stdout(output[..1].to_vec()),
+            Event::Stderr(vec![b'!', 0xff]),
+            Event::Stdout(output[1..4].to_vec()),
+            Event::Stdout(output[4..].to_vec()),
+            Event::Stderr(vec![0xf0, 0x9f]),
+            Event::Exit(sparklib::grpc::ExitStatus {
+                status: Some(exit_status::Status::Code(0)),
+            }),
+        ] {
+            events.extend(decoder.decode(response(event)).unwrap());
+        }
+
+        assert_eq!(
+            events,
+            [
+                ExecuteEvent::Stderr("!\u{fffd}".into()),
+                ExecuteEvent::Stdout("é".into()),
+                ExecuteEvent::Stdout("🦀".into()),
+                ExecuteEvent::Stderr("\u{fffd}".into()),
+                ExecuteEvent::Exit(ExitStatus::Code(0)),
+            ]
+        );
     }
 }
@@ -0,0 +1,298 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use firecracker_config_rs::models::{drive::DriveBuilder, logger::LoggerBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use super::{
+    limits::SandboxLimits,
+    network::{dns::DnsPolicy, NetworkMode},
+    resources::SandboxResources,
+    DRIVE_IDS,
+};
+
+/// Extension of template files
+const TEMPLATE_EXTENSION: &str = "toml";
+/// Guests name virtio-blk disks `/dev/vda` to `/dev/vdz`
+const MAX_DRIVES: usize = 26;
+
+/// Kernel & rootfs on the host a sandbox boots from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SandboxImage {
+    pub rootfs: PathBuf,
+    pub kernel: PathBuf,
+}
+
+/// Network of a template's sandboxes, requests can add to it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkTemplate {
+    pub mode: NetworkMode,
+    pub ipv6: bool,
+    pub nameservers: Vec<IpAddr>,
+    pub dns_policy: Option<DnsPolicy>,
+}
+
+/// A drive every sandbox of a template gets on top of the rootfs & code drive,
+/// it is mounted at `/tmp/<drive_id>` in the guest
+#[derive(Deserialize, Clone, Debug)]
+pub struct TemplateDrive {
+    pub drive_id: String,
+    /// Image on the host, every sandbox gets its own copy
+    pub source: PathBuf,
+    /// Firecracker's settings of the drive, its path & whether it is the root
+    /// device are managed by matchbox
+    #[serde(flatten)]
+    pub options: DriveBuilder,
+}
+
+/// The shape of a sandbox, loaded from a TOML file named after the template
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxTemplate {
+    pub description: Option<String>,
+    /// The server's image when unset
+    pub image: Option<SandboxImage>,
+    /// Kernel command line, matchbox appends the network's configuration
+    pub boot_args: Option<String>,
+    pub resources: SandboxResources,
+    pub network: NetworkTemplate,
+    /// Limits sandboxes ask for, the server's policy still applies
+    pub limits: SandboxLimits,
+    /// Firecracker's logger, it always logs to the same file in the jail
+    pub logger: Option<LoggerBuilder>,
+    pub drives: Vec<TemplateDrive>,
+}
+
+impl SandboxTemplate {
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<SandboxTemplate> {
+        let path = path.as_ref();
+        let template = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read template {}", path.display()))?;
+        let template: SandboxTemplate = toml::from_str(&template)
+            .with_context(|| format!("Template {} is invalid", path.display()))?;
+        template
+            .validate()
+            .with_context(|| format!("Template {} is invalid", path.display()))?;
+
+        Ok(template)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        self.resources.validate()?;
+
+        if DRIVE_IDS.len() + self.drives.len() > MAX_DRIVES {
+            anyhow::bail!("Sandboxes have at most {MAX_DRIVES} drives");
+        }
+
+        let mut drive_ids = HashSet::new();
+        for drive in &self.drives {
+            let drive_id = drive.drive_id.as_str();
+            if drive_id.is_empty()
+                || !drive_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                anyhow::bail!("Drive id {drive_id} has to be alphanumeric, hyphens or underscores");
+            }
+            if DRIVE_IDS.contains(&drive_id) || !drive_ids.insert(drive_id) {
+                anyhow::bail!("Drive id {drive_id} is used more than once");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ids of every drive of the template's sandboxes
+    pub fn drive_ids(&self) -> Vec<&str> {
+        DRIVE_IDS
+            .into_iter()
+            .chain(self.drives.iter().map(|drive| drive.drive_id.as_str()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct LoadedTemplates {
+    /// Modification times of the files the templates were loaded from
+    files: Vec<(PathBuf, SystemTime)>,
+    templates: BTreeMap<String, Arc<SandboxTemplate>>,
+}
+
+/// The templates of a directory, clones share them
+#[derive(Clone, Debug, Default)]
+pub struct SandboxTemplates {
+    directory: Option<PathBuf>,
+    loaded: Arc<RwLock<LoadedTemplates>>,
+}
+
+impl SandboxTemplates {
+    /// Templates of `directory`, there are none until they are reloaded
+    pub fn new(directory: impl Into<PathBuf>) -> SandboxTemplates {
+        SandboxTemplates {
+            directory: Some(directory.into()),
+            loaded: Default::default(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<SandboxTemplate>> {
+        self.loaded.read().unwrap().templates.get(name).cloned()
+    }
+
+    /// Every template by name
+    pub fn list(&self) -> Vec<(String, Arc<SandboxTemplate>)> {
+        let loaded = self.loaded.read().unwrap();
+        loaded
+            .templates
+            .iter()
+            .map(|(name, template)| (name.clone(), template.clone()))
+            .collect()
+    }
+
+    /// Loads the templates again when a file changed & returns whether it
+    /// did. Templates are replaced all at once, an invalid file keeps the
+    /// previous ones.
+    pub fn reload(&self) -> anyhow::Result<bool> {
+        let Some(directory) = &self.directory else {
+            return Ok(false);
+        };
+        let files = template_files(directory)?;
+        if files == self.loaded.read().unwrap().files {
+            return Ok(false);
+        }
+
+        let mut templates = BTreeMap::new();
+        for (path, _) in &files {
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            templates.insert(name, Arc::new(SandboxTemplate::from_file(path)?));
+        }
+        *self.loaded.write().unwrap() = LoadedTemplates { files, templates };
+
+        Ok(true)
+    }
+
+    /// Reloads the templates every `interval`
+    pub fn watch(&self, interval: Duration) -> JoinHandle<()> {
+        let templates = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match templates.reload() {
+                    Ok(true) => println!("reloaded {} templates", templates.list().len()),
+                    Ok(false) => {}
+                    Err(e) => println!("failed to reload templates: {e:?}"),
+                }
+            }
+        })
+    }
+}
+
+fn template_files(directory: &Path) -> anyhow::Result<Vec<(PathBuf, SystemTime)>> {
+    let entries = std::fs::read_dir(directory)
+        .with_context(|| format!("Failed to read templates in {}", directory.display()))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == TEMPLATE_EXTENSION) {
+            let modified = std::fs::metadata(&path)?.modified()?;
+            files.push((path, modified));
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{SandboxTemplates, TemplateDrive};
+    use crate::sandbox::network::NetworkMode;
+
+    const PYTHON: &str = r#"
+        description = "Python 3.12"
+        boot_args = "console=ttyS0 reboot=k panic=1 pci=off"
+
+        [resources]
+        vcpus = 2
+        memory_mib = 1024
+
+        [network]
+        mode = "none"
+
+        [limits.network_tx]
+        bandwidth = { per_second = 1048576 }
+
+        [logger]
+        level = "Debug"
+
+        [[drives]]
+        drive_id = "packages"
+        source = "/var/lib/matchbox/packages.ext4"
+        is_read_only = true
+    "#;
+
+    #[test]
+    fn templates_are_loaded_by_file_name() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("python.toml"), PYTHON).unwrap();
+        std::fs::write(directory.path().join("README.md"), "not a template").unwrap();
+        let templates = SandboxTemplates::new(directory.path());
+
+        assert!(templates.reload().unwrap());
+
+        let python = templates.get("python").unwrap();
+        assert_eq!(python.resources.vcpus, 2);
+        assert_eq!(python.network.mode, NetworkMode::None);
+        assert_eq!(python.drive_ids(), ["rootfs", "vdb", "packages"]);
+        let TemplateDrive { options, .. } = &python.drives[0];
+        let drive = options
+            .clone()
+            .drive_id("packages")
+            .path_on_host("/drives/packages.ext4")
+            .is_root_device(false)
+            .build()
+            .unwrap();
+        assert_eq!(drive.is_read_only, Some(true));
+        assert_eq!(templates.list().len(), 1);
+    }
+
+    #[test]
+    fn changed_files_are_reloaded() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("python.toml");
+        std::fs::write(&path, PYTHON).unwrap();
+        let templates = SandboxTemplates::new(directory.path());
+        templates.reload().unwrap();
+        assert!(!templates.reload().unwrap(), "nothing changed");
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        std::fs::write(&path, "[resources]\nvcpus = 0\n").unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(1))
+            .unwrap();
+        assert!(templates.reload().is_err());
+        assert_eq!(
+            templates.get("python").unwrap().resources.vcpus,
+            2,
+            "invalid templates keep the previous ones"
+        );
+
+        std::fs::write(directory.path().join("node.toml"), "").unwrap();
+        std::fs::write(&path, "").unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(2))
+            .unwrap();
+        assert!(templates.reload().unwrap());
+        assert_eq!(templates.get("python").unwrap().resources.vcpus, 1);
+        assert!(templates.get("node").is_some());
+    }
+}
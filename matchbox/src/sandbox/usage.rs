@@ -0,0 +1,27 @@
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use super::network::traffic::TrafficCounters;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SandboxUsage {
+    pub id: String,
+    pub uptime_secs: u64,
+    pub network: Option<TrafficCounters>,
+}
+
+/// Receives the final usage of every sandbox when it's deleted
+pub trait RecordUsage: Debug + Send + Sync {
+    fn record_usage(&self, usage: SandboxUsage);
+}
+
+/// Writes usage records to stdout as JSON lines
+#[derive(Debug, Default)]
+pub struct StdoutUsageRecorder;
+
+impl RecordUsage for StdoutUsageRecorder {
+    fn record_usage(&self, usage: SandboxUsage) {
+        println!("sandbox usage: {}", serde_json::to_string(&usage).unwrap());
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    jailer::{config::JailerHardening, direct::DirectConfig},
+    sandbox::{limits::LimitPolicy, VmmBoot},
+};
+
+/// How firecracker processes are run
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FirecrackerMode {
+    /// Jailed in a chroot as an unprivileged user, for production
+    #[default]
+    Jailed,
+    /// Unjailed as matchbox's user, for development hosts. Only trusted
+    /// workloads may run in this mode.
+    Direct,
+}
+
+#[derive(Builder, Serialize, Deserialize, Clone, Debug, Default)]
+#[builder(setter(into))]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Domain used for host based proxying. When set, requests for
+    /// `<port>-<sandbox id>.<proxy_domain>` are forwarded to that port on the
+    /// sandbox
+    #[builder(setter(strip_option), default)]
+    pub proxy_domain: Option<String>,
+    /// Default & maximum rate limits of sandbox devices
+    #[builder(default)]
+    pub limits: LimitPolicy,
+    #[builder(default)]
+    pub firecracker: FirecrackerMode,
+    /// Enforce sandbox resources on firecracker through cgroups created
+    /// under this cgroup
+    #[builder(setter(strip_option), default)]
+    pub parent_cgroup: Option<String>,
+    /// Hardening of every jailed firecracker, unused in direct mode
+    #[builder(default)]
+    pub jailer: JailerHardening,
+    /// Where firecracker runs from in direct mode, unused when jailed
+    #[builder(default)]
+    pub direct: DirectConfig,
+    /// Whether microvms boot from a config file or are configured through
+    /// firecracker's API
+    #[builder(default)]
+    pub boot: VmmBoot,
+    /// Directory of the sandbox templates, one TOML file per template
+    #[builder(setter(strip_option), default)]
+    pub templates: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    /// Reads the configuration from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<ServerConfig> {
+        let path = path.as_ref();
+        let config = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config: ServerConfig = serde_json::from_str(&config)
+            .with_context(|| format!("Config file {} is invalid", path.display()))?;
+        config.jailer.validate()?;
+
+        Ok(config)
+    }
+}
@@ -1,17 +1,28 @@
 use std::{collections::HashMap, sync::Arc};
 
 use axum::{
-    routing::{delete, get, post},
+    body::Body,
+    middleware,
+    routing::{any, delete, get, patch, post},
     Router,
 };
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
 use tokio::{
     net::{TcpListener, ToSocketAddrs},
     signal,
     sync::RwLock,
 };
 
-use crate::sandbox::{ProvideSandbox, Sandbox};
+use crate::sandbox::{
+    network::private::PrivateNetworks, template::SandboxTemplates, ProvideSandbox, Sandbox,
+};
+
+use self::config::ServerConfig;
 
+pub mod config;
 pub mod routes;
 
 #[derive(Clone)]
@@ -19,21 +30,59 @@ pub struct ApplicationState(Arc<ApplicationStateInner>);
 
 pub struct ApplicationStateInner {
     sandbox_factory: Box<dyn ProvideSandbox + Send + Sync>,
-    sandboxes: RwLock<HashMap<String, Sandbox>>,
+    sandboxes: RwLock<HashMap<String, Arc<Sandbox>>>,
+    networks: PrivateNetworks,
+    templates: SandboxTemplates,
+    config: ServerConfig,
+    proxy_client: Client<HttpConnector, Body>,
 }
 
 impl ApplicationState {
     pub fn new(sandbox_factory: Box<dyn ProvideSandbox + Send + Sync>) -> Self {
+        Self::with_config(sandbox_factory, ServerConfig::default())
+    }
+
+    pub fn with_config(
+        sandbox_factory: Box<dyn ProvideSandbox + Send + Sync>,
+        config: ServerConfig,
+    ) -> Self {
+        let proxy_client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+        let templates = config
+            .templates
+            .as_ref()
+            .map(SandboxTemplates::new)
+            .unwrap_or_default();
         Self(Arc::new(ApplicationStateInner {
             sandbox_factory,
             sandboxes: Default::default(),
+            networks: Default::default(),
+            templates,
+            config,
+            proxy_client,
         }))
     }
 
-    pub fn sandboxes(&self) -> &RwLock<HashMap<String, Sandbox>> {
+    pub fn config(&self) -> &ServerConfig {
+        &self.0.config
+    }
+
+    pub fn proxy_client(&self) -> &Client<HttpConnector, Body> {
+        &self.0.proxy_client
+    }
+
+    pub fn sandboxes(&self) -> &RwLock<HashMap<String, Arc<Sandbox>>> {
         &self.0.sandboxes
     }
 
+    pub fn networks(&self) -> &PrivateNetworks {
+        &self.0.networks
+    }
+
+    /// Templates of the configured directory, none until they are reloaded
+    pub fn templates(&self) -> &SandboxTemplates {
+        &self.0.templates
+    }
+
     #[allow(clippy::borrowed_box)]
     pub fn sandbox_factory(&self) -> &Box<dyn ProvideSandbox + Send + Sync> {
         &self.0.sandbox_factory
@@ -59,6 +108,63 @@ impl Application {
                 "/sandbox/:id/execute",
                 post(routes::sandbox::execute::execute_sandbox),
             )
+            .route(
+                "/sandbox/:id/execute/stream",
+                post(routes::sandbox::execute::execute_sandbox_stream),
+            )
+            .route(
+                "/sandbox/:id/stats",
+                get(routes::sandbox::stats::sandbox_stats),
+            )
+            .route("/template", get(routes::template::list::list_templates))
+            .route("/network", get(routes::network::list::list_networks))
+            .route("/network", post(routes::network::create::create_network))
+            .route(
+                "/network/:name",
+                delete(routes::network::delete::delete_network),
+            )
+            .route(
+                "/sandbox/:id/limits",
+                patch(routes::sandbox::limits::update_limits),
+            )
+            .route(
+                "/sandbox/:id/drives/:drive_id",
+                patch(routes::sandbox::drives::update_drive),
+            )
+            .route(
+                "/sandbox/:id/files",
+                get(routes::sandbox::files::download_file)
+                    .put(routes::sandbox::files::upload_file)
+                    .head(routes::sandbox::files::stat_file),
+            )
+            .route(
+                "/sandbox/:id/network",
+                patch(routes::sandbox::network::update_network),
+            )
+            .route(
+                "/sandbox/:id/pcap",
+                post(routes::sandbox::pcap::start_capture),
+            )
+            .route(
+                "/sandbox/:id/pcap/:capture_id",
+                get(routes::sandbox::pcap::capture_status),
+            )
+            .route(
+                "/sandbox/:id/pcap/:capture_id/download",
+                get(routes::sandbox::pcap::download_capture),
+            )
+            .route(
+                "/sandbox/:id/proxy/:port",
+                any(routes::sandbox::proxy::proxy_sandbox),
+            )
+            .route(
+                "/sandbox/:id/proxy/:port/*path",
+                any(routes::sandbox::proxy::proxy_sandbox),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                routes::sandbox::proxy::route_by_host,
+            ))
             .with_state(state);
         Ok(Application { listener, router })
     }
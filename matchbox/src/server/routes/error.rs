@@ -1,6 +1,50 @@
+use std::fmt::Display;
+
+use firecracker_config_rs::validation::ValidationErrors;
+
+/// A valid request this server doesn't allow
+#[derive(Debug)]
+pub struct Forbidden(pub String);
+
+impl Display for Forbidden {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Forbidden {}
+
+/// The request is about something that doesn't exist
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+#[derive(Debug)]
 pub struct ApiError(anyhow::Error);
 impl axum::response::IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
+        if let Some(Forbidden(message)) = self.0.downcast_ref::<Forbidden>() {
+            return (axum::http::StatusCode::FORBIDDEN, message.clone()).into_response();
+        }
+        if let Some(NotFound(message)) = self.0.downcast_ref::<NotFound>() {
+            return (axum::http::StatusCode::NOT_FOUND, message.clone()).into_response();
+        }
+        // The request asked for a microvm firecracker would refuse
+        if let Some(errors) = self.0.downcast_ref::<ValidationErrors>() {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::Json(errors.clone()),
+            )
+                .into_response();
+        }
+
         (
             axum::http::StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {}", self.0),
@@ -1,4 +1,6 @@
 pub mod error;
+pub mod network;
 pub mod sandbox;
+pub mod template;
 
 pub type ApiResult<T> = Result<T, error::ApiError>;
@@ -0,0 +1,24 @@
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sandbox::network::private::PrivateNetworkOptions,
+    server::{routes::ApiResult, ApplicationState},
+};
+
+use super::NetworkResponse;
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateNetworkRequest {
+    pub name: String,
+    #[serde(flatten)]
+    pub options: PrivateNetworkOptions,
+}
+
+pub async fn create_network(
+    State(state): State<ApplicationState>,
+    Json(payload): Json<CreateNetworkRequest>,
+) -> ApiResult<NetworkResponse> {
+    let network = state.networks().create(&payload.name, payload.options)?;
+    Ok(NetworkResponse::from(network.as_ref()))
+}
@@ -0,0 +1,13 @@
+use axum::extract::{Path, State};
+
+use crate::server::{routes::ApiResult, ApplicationState};
+
+use super::NetworkResponse;
+
+pub async fn delete_network(
+    Path(name): Path<String>,
+    State(state): State<ApplicationState>,
+) -> ApiResult<NetworkResponse> {
+    let network = state.networks().remove(&name)?;
+    Ok(NetworkResponse::from(network.as_ref()))
+}
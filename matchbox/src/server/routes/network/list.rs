@@ -0,0 +1,29 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::server::{routes::ApiResult, ApplicationState};
+
+use super::NetworkResponse;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListNetworksResponse {
+    networks: Vec<NetworkResponse>,
+}
+
+impl IntoResponse for ListNetworksResponse {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
+
+pub async fn list_networks(
+    State(state): State<ApplicationState>,
+) -> ApiResult<ListNetworksResponse> {
+    let networks = state
+        .networks()
+        .list()
+        .iter()
+        .map(|network| NetworkResponse::from(network.as_ref()))
+        .collect();
+    Ok(ListNetworksResponse { networks })
+}
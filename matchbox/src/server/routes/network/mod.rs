@@ -0,0 +1,33 @@
+use axum::{response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::sandbox::network::private::PrivateNetwork;
+
+pub mod create;
+pub mod delete;
+pub mod list;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NetworkResponse {
+    pub name: String,
+    pub subnet: String,
+    pub internet: bool,
+    pub members: usize,
+}
+
+impl From<&PrivateNetwork> for NetworkResponse {
+    fn from(value: &PrivateNetwork) -> Self {
+        NetworkResponse {
+            name: value.name().to_string(),
+            subnet: value.subnet(),
+            internet: value.internet(),
+            members: value.member_count(),
+        }
+    }
+}
+
+impl IntoResponse for NetworkResponse {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
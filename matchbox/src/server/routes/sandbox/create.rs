@@ -1,16 +1,54 @@
+use std::{net::IpAddr, sync::Arc};
+
 use axum::{extract::State, Json};
+use firecracker_config_rs::validation::ValidationErrors;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    sandbox::{Location, ProvideSandboxOptionsBuilder},
-    server::{routes::ApiResult, ApplicationState},
+    sandbox::{
+        limits::SandboxLimits,
+        network::{dns::DnsPolicy, NetworkMode, NetworkOptionsBuilder},
+        resources::SandboxResources,
+        Location, ProvideSandboxOptionsBuilder,
+    },
+    server::{
+        config::FirecrackerMode,
+        routes::{error::Forbidden, ApiResult},
+        ApplicationState,
+    },
 };
 
 use super::SandboxResponse;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct CreateSandboxRequest {
+    /// Name of the template the sandbox is created from, its settings are
+    /// the defaults of the ones below
+    pub template: Option<String>,
     pub code_drive_path: Option<Location>,
+    /// The template's network mode when unset
+    pub network: Option<NetworkMode>,
+    /// Give the sandbox an ipv6 address next to its ipv4 address
+    #[serde(default)]
+    pub ipv6: bool,
+    /// Nameservers the sandbox resolves names through
+    #[serde(default)]
+    pub nameservers: Vec<IpAddr>,
+    /// Resolve names through a DNS forwarder which enforces this policy
+    pub dns_policy: Option<DnsPolicy>,
+    /// Attach the sandbox to this private network, creating it if needed
+    pub private_network: Option<String>,
+    /// Rate limits of the sandbox's devices, on top of the template's & the
+    /// server's defaults
+    #[serde(default)]
+    pub limits: SandboxLimits,
+    /// vCPUs & memory of the microvm & host limits of its VMM process, the
+    /// template's when unset
+    pub resources: Option<SandboxResources>,
+    /// The workload is trusted not to attack the host, required when
+    /// firecracker runs without the jailer
+    #[serde(default)]
+    pub trusted: bool,
 }
 
 #[axum_macros::debug_handler]
@@ -18,17 +56,89 @@ pub async fn create_sandbox(
     State(state): State<ApplicationState>,
     Json(payload): Json<CreateSandboxRequest>,
 ) -> ApiResult<SandboxResponse> {
+    if state.config().firecracker == FirecrackerMode::Direct && !payload.trusted {
+        return Err(Forbidden(
+            "Firecracker runs without the jailer, only trusted workloads are allowed".into(),
+        )
+        .into());
+    }
+
+    let template = match &payload.template {
+        Some(name) => {
+            let Some(template) = state.templates().get(name) else {
+                let mut errors = ValidationErrors::default();
+                errors.add("template", format!("Template {name} does not exist"));
+                return Err(anyhow::Error::from(errors).into());
+            };
+            template
+        }
+        None => Default::default(),
+    };
+
     let factory = state.sandbox_factory();
     let mut builder = ProvideSandboxOptionsBuilder::default();
     if let Some(path) = payload.code_drive_path {
         builder.code_drive_location(path);
     }
+    let resources = payload
+        .resources
+        .unwrap_or_else(|| template.resources.clone());
+    resources.validate()?;
+    builder.resources(resources);
+    let limits = payload.limits.or(&template.limits);
+    builder.limits(
+        state
+            .config()
+            .limits
+            .resolve(&limits, &template.drive_ids())?,
+    );
+    let network_template = &template.network;
+    match payload.network.unwrap_or(network_template.mode) {
+        NetworkMode::Default => {
+            let nameservers = match payload.nameservers.is_empty() {
+                true => network_template.nameservers.clone(),
+                false => payload.nameservers,
+            };
+            let mut network = NetworkOptionsBuilder::default();
+            network
+                .ipv6(payload.ipv6 || network_template.ipv6)
+                .nameservers(nameservers);
+            if let Some(policy) = payload
+                .dns_policy
+                .or_else(|| network_template.dns_policy.clone())
+            {
+                network.dns_policy(policy);
+            }
+            if let Some(name) = payload.private_network {
+                network.private_network(state.networks().get_or_create(&name)?);
+            }
+            builder.network(network.build()?);
+        }
+        NetworkMode::None => {
+            let mut errors = ValidationErrors::default();
+            let options = [
+                ("ipv6", payload.ipv6),
+                ("nameservers", !payload.nameservers.is_empty()),
+                ("dns_policy", payload.dns_policy.is_some()),
+                ("private_network", payload.private_network.is_some()),
+            ];
+            for (field, _) in options.iter().filter(|(_, set)| *set) {
+                errors.add(
+                    *field,
+                    "Network options can't be used for a sandbox without a network",
+                );
+            }
+            errors.into_result()?;
+            builder.network(None);
+        }
+    }
+    builder.template(template);
     let sandbox = factory.provide_sandbox(builder.build()?).await?;
 
     let response = SandboxResponse::from(&sandbox);
     {
         let mut sandboxes = state.sandboxes().write().await;
-        sandboxes.insert(sandbox.id().to_string(), sandbox);
+        sandboxes.insert(sandbox.id().to_string(), Arc::new(sandbox));
     }
     Ok(response)
 }
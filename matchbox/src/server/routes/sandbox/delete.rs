@@ -1,6 +1,9 @@
 use axum::extract::{Path, State};
 
-use crate::server::{routes::ApiResult, ApplicationState};
+use crate::server::{
+    routes::{error::NotFound, ApiResult},
+    ApplicationState,
+};
 
 use super::SandboxResponse;
 
@@ -14,7 +17,7 @@ pub async fn delete_sandbox(
     };
 
     match sandbox {
-        Some(sandbox) => Ok(SandboxResponse::from(&sandbox)),
-        None => Err(anyhow::anyhow!("Sandbox with id {sandbox_id} does not exist").into()),
+        Some(sandbox) => Ok(SandboxResponse::from(sandbox.as_ref())),
+        None => Err(NotFound(format!("Sandbox with id {sandbox_id} does not exist")).into()),
     }
 }
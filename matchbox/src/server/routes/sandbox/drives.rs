@@ -0,0 +1,32 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use firecracker_config_rs::models::drive::Drive;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sandbox::Location,
+    server::{routes::ApiResult, ApplicationState},
+};
+
+use super::find_sandbox;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateDriveRequest {
+    /// New backing file of the drive, the sandbox gets a copy of it
+    pub location: Location,
+}
+
+/// Swaps the backing file of a running sandbox's drive & responds with the
+/// drive
+pub async fn update_drive(
+    Path((sandbox_id, drive_id)): Path<(String, String)>,
+    State(state): State<ApplicationState>,
+    Json(payload): Json<UpdateDriveRequest>,
+) -> ApiResult<Json<Drive>> {
+    let sandbox = find_sandbox(&state, &sandbox_id).await?;
+    let drive = sandbox.swap_drive(&drive_id, &payload.location).await?;
+
+    Ok(Json(drive))
+}
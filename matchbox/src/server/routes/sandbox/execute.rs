@@ -1,15 +1,68 @@
+use std::{convert::Infallible, time::Instant};
+
 use axum::{
+    body::Bytes,
     extract::{Path, State},
-    response::IntoResponse,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     Json,
 };
-use serde::{Deserialize, Serialize};
+use firecracker_config_rs::validation::ValidationErrors;
+use futures_util::{Stream, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    sandbox::spark::{ExecuteOptions, ExitStatus},
+    server::{routes::ApiResult, ApplicationState},
+};
+
+use super::sandbox_client;
 
-use crate::server::{routes::ApiResult, ApplicationState};
+/// Command the execute requests run when they ask for none
+const DEFAULT_COMMAND: [&str; 2] = ["sh", "entrypoint"];
 
-#[derive(Serialize, Deserialize)]
+/// Body of the execute requests, it may be left out entirely
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExecuteSandboxRequest {
+    /// `sh entrypoint` when unset
+    pub command: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<String>,
+    #[serde(flatten)]
+    pub options: ExecuteOptions,
+}
+
+impl ExecuteSandboxRequest {
+    /// The command & its arguments
+    fn command_line(&self) -> anyhow::Result<(String, Vec<String>)> {
+        match &self.command {
+            Some(command) => Ok((command.clone(), self.arguments.clone())),
+            None if self.arguments.is_empty() => {
+                let [command, argument] = DEFAULT_COMMAND.map(String::from);
+                Ok((command, vec![argument]))
+            }
+            None => {
+                let mut errors = ValidationErrors::default();
+                errors.add("command", "Arguments need a command");
+                Err(errors.into())
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ExecuteResponse {
-    output: String,
+    pub stdout: String,
+    pub stderr: String,
+    /// Unset when the command was killed
+    pub exit_code: Option<i32>,
+    /// Signal the command was killed by
+    pub signal: Option<i32>,
+    /// The command was killed once its timeout passed
+    pub timed_out: bool,
+    pub duration_ms: u64,
 }
 
 impl IntoResponse for ExecuteResponse {
@@ -18,25 +71,73 @@ impl IntoResponse for ExecuteResponse {
     }
 }
 
+/// Executes a command in the sandbox & responds once it exited
 #[axum_macros::debug_handler]
 pub async fn execute_sandbox(
     Path(sandbox_id): Path<String>,
     State(state): State<ApplicationState>,
+    body: Bytes,
 ) -> ApiResult<ExecuteResponse> {
-    let sandboxes = state.sandboxes().read().await;
-    let sandbox = match sandboxes.get(&sandbox_id) {
-        Some(s) => s,
-        None => {
-            return Err(
-                anyhow::Error::msg(format!("Sandbox with id {sandbox_id} was not found")).into(),
-            )
-        }
-    };
-    let mut client = sandbox.client().await;
-    let response = client
-        .execute("sh".to_string(), ["entrypoint"].map(String::from).to_vec())
-        .await?;
+    let payload: ExecuteSandboxRequest = parse_body(&body)?;
+    let (command, arguments) = payload.command_line()?;
+    let mut client = sandbox_client(&state, &sandbox_id).await?;
+
+    let start = Instant::now();
+    let output = client.execute(command, arguments, &payload.options).await?;
+    let duration_ms = start.elapsed().as_millis() as u64;
+
     Ok(ExecuteResponse {
-        output: response.output,
+        stdout: output.stdout,
+        stderr: output.stderr,
+        exit_code: match output.exit {
+            ExitStatus::Code(code) => Some(code),
+            _ => None,
+        },
+        signal: match output.exit {
+            ExitStatus::Signal(signal) => Some(signal),
+            _ => None,
+        },
+        timed_out: output.exit == ExitStatus::TimedOut,
+        duration_ms,
+    })
+}
+
+/// Executes a command in the sandbox & streams its output as server sent
+/// events named after the output, like `stdout`, ending with an `exit` event.
+/// Failures while streaming end it with an `error` event.
+pub async fn execute_sandbox_stream(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+    body: Bytes,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let payload: ExecuteSandboxRequest = parse_body(&body)?;
+    let (command, arguments) = payload.command_line()?;
+    let mut client = sandbox_client(&state, &sandbox_id).await?;
+    let events = client
+        .execute_stream(command, arguments, &payload.options)
+        .await?;
+
+    let events = events.map(|event| {
+        let event = match event {
+            Ok(event) => Event::default().event(event.name()).json_data(&event),
+            Err(e) => Event::default()
+                .event("error")
+                .json_data(serde_json::json!({ "error": format!("{e:#}") })),
+        };
+        Ok(event.expect("execute events serialize to json"))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Parses a json body, an empty body is the default
+fn parse_body<T: DeserializeOwned + Default>(body: &[u8]) -> anyhow::Result<T> {
+    if body.is_empty() {
+        return Ok(T::default());
+    }
+
+    serde_json::from_slice(body).map_err(|e| {
+        let mut errors = ValidationErrors::default();
+        errors.add("body", e.to_string());
+        errors.into()
     })
 }
@@ -0,0 +1,188 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use firecracker_config_rs::validation::ValidationErrors;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sparklib::grpc::{FileInfo, WriteFileHeader};
+
+use crate::server::{routes::ApiResult, ApplicationState};
+
+use super::sandbox_client;
+
+/// Size of the partial upload of a file, uploads are resumed from there
+pub const UPLOAD_OFFSET_HEADER: &str = "upload-offset";
+/// Permissions of a file in octal, like `644`
+pub const FILE_MODE_HEADER: &str = "x-file-mode";
+pub const FILE_UID_HEADER: &str = "x-file-uid";
+pub const FILE_GID_HEADER: &str = "x-file-gid";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UploadQuery {
+    /// Absolute path of the file in the sandbox
+    pub path: String,
+    /// Size of the partial upload the body continues, a new upload starts at 0
+    #[serde(default)]
+    pub offset: u64,
+    /// Permissions in octal, like `755`. 644 when unset.
+    pub mode: Option<String>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Keep the upload to resume it later instead of moving the file into
+    /// place
+    #[serde(default)]
+    pub partial: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DownloadQuery {
+    /// Absolute path of the file in the sandbox
+    pub path: String,
+    /// Where the download starts, to resume it
+    #[serde(default)]
+    pub offset: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UploadResponse {
+    pub path: String,
+    /// Bytes uploaded so far
+    pub size: u64,
+    /// The file was moved into place
+    pub committed: bool,
+}
+
+/// Uploads the body to a file in the sandbox, the file is replaced at once
+/// when the upload completes
+pub async fn upload_file(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+    Query(query): Query<UploadQuery>,
+    body: Body,
+) -> ApiResult<Json<UploadResponse>> {
+    let mut errors = ValidationErrors::default();
+    check_path(&query.path, &mut errors);
+    let mode = query.mode.as_deref().map(|mode| {
+        let mode = u32::from_str_radix(mode, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777);
+        if mode.is_none() {
+            errors.add("mode", "Mode has to be octal permissions, like 644");
+        }
+        mode.unwrap_or_default()
+    });
+    errors.into_result()?;
+
+    let mut client = sandbox_client(&state, &sandbox_id).await?;
+    if query.offset > 0 {
+        let upload_size = client.stat_file(query.path.clone()).await?.upload_size;
+        if upload_size != query.offset {
+            let mut errors = ValidationErrors::default();
+            errors.add(
+                "offset",
+                format!("The upload of {} has {upload_size} bytes", query.path),
+            );
+            return Err(anyhow::Error::from(errors).into());
+        }
+    }
+
+    let header = WriteFileHeader {
+        path: query.path.clone(),
+        mode,
+        uid: query.uid,
+        gid: query.gid,
+        offset: query.offset,
+    };
+    let chunks = body.into_data_stream().map(|chunk| Ok(chunk?.to_vec()));
+    let response = client.write_file(header, chunks, !query.partial).await?;
+
+    Ok(Json(UploadResponse {
+        path: query.path,
+        size: response.size,
+        committed: response.committed,
+    }))
+}
+
+/// Downloads a file of the sandbox, its permissions & owner are in the
+/// headers
+pub async fn download_file(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+    Query(query): Query<DownloadQuery>,
+) -> ApiResult<Response> {
+    let mut errors = ValidationErrors::default();
+    check_path(&query.path, &mut errors);
+    errors.into_result()?;
+
+    let mut client = sandbox_client(&state, &sandbox_id).await?;
+    let mut errors = ValidationErrors::default();
+    match client.stat_file(query.path.clone()).await?.file {
+        None => errors.add("path", format!("File {} does not exist", query.path)),
+        Some(file) if query.offset > file.size => errors.add(
+            "offset",
+            format!("File {} has {} bytes", query.path, file.size),
+        ),
+        Some(file) => {
+            let chunks = client.read_file(query.path, query.offset).await?;
+            let mut headers = file_headers(&file);
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/octet-stream"),
+            );
+            headers.insert(header::CONTENT_LENGTH, (file.size - query.offset).into());
+            return Ok((headers, Body::from_stream(chunks)).into_response());
+        }
+    }
+
+    Err(anyhow::Error::from(errors).into())
+}
+
+/// Size, permissions & owner of a file of the sandbox & the size of its
+/// partial upload
+pub async fn stat_file(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+    Query(query): Query<DownloadQuery>,
+) -> ApiResult<Response> {
+    let mut errors = ValidationErrors::default();
+    check_path(&query.path, &mut errors);
+    errors.into_result()?;
+
+    let mut client = sandbox_client(&state, &sandbox_id).await?;
+    let stat = client.stat_file(query.path).await?;
+    let (status, mut headers) = match &stat.file {
+        Some(file) => {
+            let mut headers = file_headers(file);
+            headers.insert(header::CONTENT_LENGTH, file.size.into());
+            (StatusCode::OK, headers)
+        }
+        None => (StatusCode::NOT_FOUND, HeaderMap::new()),
+    };
+    headers.insert(UPLOAD_OFFSET_HEADER, stat.upload_size.into());
+
+    Ok((status, headers).into_response())
+}
+
+/// Files are addressed by absolute paths which stay inside of their
+/// directory
+fn check_path(path: &str, errors: &mut ValidationErrors) {
+    let path = std::path::Path::new(path);
+    if !path.is_absolute() || path.components().any(|c| c.as_os_str() == "..") {
+        errors.add("path", "Path has to be absolute, without `..`");
+    }
+}
+
+fn file_headers(file: &FileInfo) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        FILE_MODE_HEADER,
+        HeaderValue::from_str(&format!("{:o}", file.mode)).unwrap(),
+    );
+    headers.insert(FILE_UID_HEADER, file.uid.into());
+    headers.insert(FILE_GID_HEADER, file.gid.into());
+    headers
+}
@@ -0,0 +1,27 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    sandbox::limits::LimitsUpdate,
+    server::{routes::ApiResult, ApplicationState},
+};
+
+use super::find_sandbox;
+
+/// Changes the limits of a running sandbox & responds with the limits that
+/// were applied
+pub async fn update_limits(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+    Json(payload): Json<LimitsUpdate>,
+) -> ApiResult<Json<LimitsUpdate>> {
+    let sandbox = find_sandbox(&state, &sandbox_id).await?;
+    let drive_ids = sandbox.drive_ids().await;
+    let drive_ids: Vec<&str> = drive_ids.iter().map(String::as_str).collect();
+    let limits = state.config().limits.resolve_update(&payload, &drive_ids)?;
+    sandbox.update_limits(&limits).await?;
+
+    Ok(Json(limits))
+}
@@ -21,8 +21,8 @@ pub async fn list_sandboxes(
 ) -> ApiResult<ListSandboxesResponse> {
     let sandboxes = state.sandboxes().read().await;
     let sandboxes = sandboxes
-        .iter()
-        .map(|(_, sb)| SandboxResponse::from(sb))
+        .values()
+        .map(|sandbox| SandboxResponse::from(sandbox.as_ref()))
         .collect();
     Ok(ListSandboxesResponse { sandboxes })
 }
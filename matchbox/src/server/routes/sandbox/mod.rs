@@ -1,24 +1,43 @@
+use std::sync::Arc;
+
 use axum::{response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 
-use crate::sandbox::Sandbox;
+use crate::{
+    sandbox::{spark::SparkClient, Sandbox},
+    server::{routes::error::NotFound, ApplicationState},
+};
 
 pub mod create;
 pub mod delete;
+pub mod drives;
 pub mod execute;
+pub mod files;
+pub mod limits;
 pub mod list;
+pub mod network;
+pub mod pcap;
+pub mod proxy;
+pub mod stats;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SandboxResponse {
     pub id: String,
-    pub ip: String,
+    pub ip: Option<String>,
+    pub ipv6: Option<String>,
+    pub private_ip: Option<String>,
 }
 
 impl From<&Sandbox> for SandboxResponse {
     fn from(value: &Sandbox) -> Self {
         SandboxResponse {
             id: value.id().to_string(),
-            ip: value.network().microvm_ip(),
+            ip: value.network().map(|network| network.microvm_ip()),
+            ipv6: value.network().and_then(|network| network.microvm_ipv6()),
+            private_ip: value
+                .network()
+                .and_then(|network| network.private_network())
+                .map(|membership| membership.address()),
         }
     }
 }
@@ -28,3 +47,27 @@ impl IntoResponse for SandboxResponse {
         Json(self).into_response()
     }
 }
+
+/// The sandbox with the given id, requests using it don't keep the other
+/// sandboxes locked
+pub(crate) async fn find_sandbox(
+    state: &ApplicationState,
+    sandbox_id: &str,
+) -> anyhow::Result<Arc<Sandbox>> {
+    let sandboxes = state.sandboxes().read().await;
+    match sandboxes.get(sandbox_id) {
+        Some(sandbox) => Ok(sandbox.clone()),
+        None => Err(NotFound(format!("Sandbox with id {sandbox_id} was not found")).into()),
+    }
+}
+
+/// A client of the sandbox's spark-server, long running requests through it
+/// don't hold on to the sandbox
+pub(crate) async fn sandbox_client(
+    state: &ApplicationState,
+    sandbox_id: &str,
+) -> anyhow::Result<SparkClient> {
+    let sandbox = find_sandbox(state, sandbox_id).await?;
+    let client = sandbox.client().await.clone();
+    Ok(client)
+}
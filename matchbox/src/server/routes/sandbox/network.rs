@@ -0,0 +1,53 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use firecracker_config_rs::validation::ValidationErrors;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sandbox::limits::{DeviceLimits, LimitsUpdate},
+    server::{routes::ApiResult, ApplicationState},
+};
+
+use super::find_sandbox;
+
+/// Rate limits of a sandbox's network interfaces, unset directions keep
+/// their current limits & `{}` removes them
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct NetworkLimits {
+    /// Traffic received by the sandbox
+    #[serde(default)]
+    pub rx: Option<DeviceLimits>,
+    /// Traffic sent by the sandbox
+    #[serde(default)]
+    pub tx: Option<DeviceLimits>,
+}
+
+/// Changes the rate limits of a running sandbox's network interfaces &
+/// responds with the limits that were applied
+pub async fn update_network(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+    Json(payload): Json<NetworkLimits>,
+) -> ApiResult<Json<NetworkLimits>> {
+    let sandbox = find_sandbox(&state, &sandbox_id).await?;
+    if sandbox.network().is_none() {
+        let mut errors = ValidationErrors::default();
+        errors.add("network", format!("Sandbox {sandbox_id} has no network"));
+        return Err(anyhow::Error::from(errors).into());
+    }
+
+    let requested = LimitsUpdate {
+        network_rx: payload.rx,
+        network_tx: payload.tx,
+        ..Default::default()
+    };
+    let limits = state.config().limits.resolve_update(&requested, &[])?;
+    sandbox.update_limits(&limits).await?;
+
+    Ok(Json(NetworkLimits {
+        rx: limits.network_rx,
+        tx: limits.network_tx,
+    }))
+}
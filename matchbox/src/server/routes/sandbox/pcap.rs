@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sandbox::network::pcap::{
+        BpfInstruction, CaptureDevice, CaptureOptionsBuilder, CaptureStatus, DEFAULT_CAPTURE_BYTES,
+        DEFAULT_CAPTURE_DURATION,
+    },
+    server::{routes::ApiResult, ApplicationState},
+};
+
+use super::find_sandbox;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartCaptureRequest {
+    #[serde(default)]
+    pub device: CaptureDevice,
+    /// Classic BPF program in the format printed by `tcpdump -dd`
+    #[serde(default)]
+    pub filter: Vec<BpfInstruction>,
+    #[serde(default = "default_duration_secs")]
+    pub duration_secs: u64,
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+}
+
+fn default_duration_secs() -> u64 {
+    DEFAULT_CAPTURE_DURATION.as_secs()
+}
+
+fn default_max_bytes() -> u64 {
+    DEFAULT_CAPTURE_BYTES
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CaptureResponse {
+    pub id: String,
+    #[serde(flatten)]
+    pub status: CaptureStatus,
+}
+
+impl IntoResponse for CaptureResponse {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CapturePath {
+    id: String,
+    capture_id: String,
+}
+
+pub async fn start_capture(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+    Json(payload): Json<StartCaptureRequest>,
+) -> ApiResult<CaptureResponse> {
+    let options = CaptureOptionsBuilder::default()
+        .device(payload.device)
+        .filter(payload.filter)
+        .duration(Duration::from_secs(payload.duration_secs))
+        .max_bytes(payload.max_bytes)
+        .build()?;
+
+    let sandbox = find_sandbox(&state, &sandbox_id).await?;
+
+    let capture_id = sandbox.start_capture(options).await?;
+    let status = sandbox.capture_status(&capture_id).await?;
+    Ok(CaptureResponse {
+        id: capture_id,
+        status,
+    })
+}
+
+pub async fn capture_status(
+    Path(params): Path<CapturePath>,
+    State(state): State<ApplicationState>,
+) -> ApiResult<CaptureResponse> {
+    let sandbox = find_sandbox(&state, &params.id).await?;
+
+    let status = sandbox.capture_status(&params.capture_id).await?;
+    Ok(CaptureResponse {
+        id: params.capture_id,
+        status,
+    })
+}
+
+pub async fn download_capture(
+    Path(params): Path<CapturePath>,
+    State(state): State<ApplicationState>,
+) -> ApiResult<Response> {
+    let sandbox = find_sandbox(&state, &params.id).await?;
+    let path = sandbox.capture_file(&params.capture_id).await?;
+
+    let capture = tokio::fs::read(&path).await?;
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/vnd.tcpdump.pcap".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.pcap\"", params.capture_id),
+            ),
+        ],
+        capture,
+    )
+        .into_response())
+}
@@ -0,0 +1,380 @@
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        FromRequestParts, Path, Request, State,
+    },
+    http::{header, HeaderMap, Uri, Version},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use firecracker_config_rs::validation::ValidationErrors;
+use futures_util::{SinkExt, StreamExt};
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+use crate::server::{routes::ApiResult, ApplicationState};
+
+use super::find_sandbox;
+
+/// Headers that only apply to a single connection and must not be forwarded
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Deserialize, Debug)]
+pub struct ProxyPath {
+    id: String,
+    port: u16,
+    path: Option<String>,
+}
+
+pub async fn proxy_sandbox(
+    Path(params): Path<ProxyPath>,
+    State(state): State<ApplicationState>,
+    websocket: Option<WebSocketUpgrade>,
+    request: Request,
+) -> ApiResult<Response> {
+    let path = format!("/{}", params.path.unwrap_or_default());
+    forward(&state, &params.id, params.port, &path, websocket, request).await
+}
+
+/// Forwards requests addressed to `<port>-<sandbox id>.<proxy_domain>` to the
+/// sandbox, everything else goes through the regular routes.
+pub async fn route_by_host(
+    State(state): State<ApplicationState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let target = state.config().proxy_domain.as_deref().and_then(|domain| {
+        let host = request.headers().get(header::HOST)?.to_str().ok()?;
+        parse_proxy_host(host, domain)
+    });
+    let Some((port, sandbox_id)) = target else {
+        return next.run(request).await;
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let websocket = WebSocketUpgrade::from_request_parts(&mut parts, &state)
+        .await
+        .ok();
+    let request = Request::from_parts(parts, body);
+    let path = request.uri().path().to_string();
+
+    forward(&state, &sandbox_id, port, &path, websocket, request)
+        .await
+        .into_response()
+}
+
+async fn forward(
+    state: &ApplicationState,
+    sandbox_id: &str,
+    port: u16,
+    path: &str,
+    websocket: Option<WebSocketUpgrade>,
+    request: Request,
+) -> ApiResult<Response> {
+    let sandbox = find_sandbox(state, sandbox_id).await?;
+    let Some(network) = sandbox.network() else {
+        let mut errors = ValidationErrors::default();
+        errors.add(
+            "network",
+            format!("Sandbox with id {sandbox_id} has no network to proxy to"),
+        );
+        return Err(anyhow::Error::from(errors).into());
+    };
+    let ip = network.microvm_ip();
+
+    let path_and_query = match request.uri().query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_string(),
+    };
+
+    match websocket {
+        Some(websocket) => {
+            proxy_websocket(websocket, format!("ws://{ip}:{port}{path_and_query}")).await
+        }
+        None => {
+            proxy_http(
+                state.proxy_client(),
+                format!("http://{ip}:{port}{path_and_query}"),
+                request,
+            )
+            .await
+        }
+    }
+}
+
+async fn proxy_http(
+    client: &Client<HttpConnector, Body>,
+    uri: String,
+    request: Request,
+) -> ApiResult<Response> {
+    let (mut parts, body) = request.into_parts();
+    parts.uri = Uri::try_from(&uri)?;
+    parts.version = Version::HTTP_11;
+    remove_hop_by_hop_headers(&mut parts.headers);
+    // Let the client fill in the host of the sandbox
+    parts.headers.remove(header::HOST);
+
+    let response = client
+        .request(Request::from_parts(parts, body))
+        .await
+        .with_context(|| format!("Failed to proxy request to {uri}"))?;
+
+    let (mut parts, body) = response.into_parts();
+    remove_hop_by_hop_headers(&mut parts.headers);
+    Ok(Response::from_parts(parts, Body::new(body)))
+}
+
+async fn proxy_websocket(websocket: WebSocketUpgrade, uri: String) -> ApiResult<Response> {
+    let (upstream, _) = tokio_tungstenite::connect_async(uri.as_str())
+        .await
+        .with_context(|| format!("Failed to open websocket to {uri}"))?;
+
+    Ok(websocket.on_upgrade(|client| pipe_websocket(client, upstream)))
+}
+
+async fn pipe_websocket(client: WebSocket, upstream: WebSocketStream<MaybeTlsStream<TcpStream>>) {
+    let (mut client_sender, mut client_receiver) = client.split();
+    let (mut upstream_sender, mut upstream_receiver) = upstream.split();
+
+    let client_to_upstream = async {
+        while let Some(Ok(message)) = client_receiver.next().await {
+            if upstream_sender.send(into_upstream(message)).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    let upstream_to_client = async {
+        while let Some(Ok(message)) = upstream_receiver.next().await {
+            let Some(message) = from_upstream(message) else {
+                continue;
+            };
+            if client_sender.send(message).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    // Once either side hangs up there is nothing left to forward
+    tokio::select! {
+        _ = client_to_upstream => {}
+        _ = upstream_to_client => {}
+    }
+}
+
+fn into_upstream(message: Message) -> tungstenite::Message {
+    match message {
+        Message::Text(text) => tungstenite::Message::Text(text),
+        Message::Binary(data) => tungstenite::Message::Binary(data),
+        Message::Ping(data) => tungstenite::Message::Ping(data),
+        Message::Pong(data) => tungstenite::Message::Pong(data),
+        Message::Close(frame) => {
+            tungstenite::Message::Close(frame.map(|frame| tungstenite::protocol::CloseFrame {
+                code: frame.code.into(),
+                reason: frame.reason,
+            }))
+        }
+    }
+}
+
+fn from_upstream(message: tungstenite::Message) -> Option<Message> {
+    let message = match message {
+        tungstenite::Message::Text(text) => Message::Text(text),
+        tungstenite::Message::Binary(data) => Message::Binary(data),
+        tungstenite::Message::Ping(data) => Message::Ping(data),
+        tungstenite::Message::Pong(data) => Message::Pong(data),
+        tungstenite::Message::Close(frame) => Message::Close(frame.map(|frame| CloseFrame {
+            code: frame.code.into(),
+            reason: frame.reason,
+        })),
+        // Raw frames are never yielded when reading messages
+        tungstenite::Message::Frame(_) => return None,
+    };
+
+    Some(message)
+}
+
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+/// Parses `<port>-<sandbox id>.<domain>` (optionally followed by `:<port>`)
+/// into the sandbox port & id. Hostnames are case insensitive, the sandbox
+/// id is taken as it was sent.
+fn parse_proxy_host(host: &str, domain: &str) -> Option<(u16, String)> {
+    let host = host.split(':').next()?;
+    let suffix_start = host
+        .len()
+        .checked_sub(domain.len())
+        .filter(|start| host.is_char_boundary(*start))?;
+    if !host[suffix_start..].eq_ignore_ascii_case(domain) {
+        return None;
+    }
+    let label = host[..suffix_start].strip_suffix('.')?;
+    let (port, sandbox_id) = label.split_once('-')?;
+    if sandbox_id.is_empty() {
+        return None;
+    }
+
+    Some((port.parse().ok()?, sandbox_id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        body::{to_bytes, Body},
+        extract::{ws::WebSocketUpgrade, Request},
+        http::{header, HeaderMap, StatusCode, Uri},
+        routing::{get, post},
+        Router,
+    };
+    use futures_util::{SinkExt, StreamExt};
+    use hyper_util::{
+        client::legacy::{connect::HttpConnector, Client},
+        rt::TokioExecutor,
+    };
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::{parse_proxy_host, proxy_http, proxy_websocket};
+
+    /// Serves `app` on a free local port, standing in for a sandbox's service
+    async fn serve(app: Router) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        address
+    }
+
+    #[tokio::test]
+    async fn forwards_http_requests_without_hop_by_hop_headers() {
+        let upstream = serve(Router::new().route(
+            "/echo",
+            post(|uri: Uri, headers: HeaderMap, body: String| async move {
+                let forwarded = |name| headers.contains_key(name);
+                (
+                    [("x-upstream", "sandbox"), ("proxy-authenticate", "Basic")],
+                    format!(
+                        "{uri} {body} host={} proxy-authorization={}",
+                        headers[header::HOST].to_str().unwrap(),
+                        forwarded("proxy-authorization"),
+                    ),
+                )
+            }),
+        ))
+        .await;
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/sandbox/abc/proxy/8080/echo?name=value")
+            .header(header::HOST, "8080-abc.sandbox.local")
+            .header("proxy-authorization", "Basic secret")
+            .body(Body::from("hello"))
+            .unwrap();
+        let client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+        let response = proxy_http(
+            &client,
+            format!("http://{upstream}/echo?name=value"),
+            request,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-upstream"], "sandbox");
+        assert!(!response.headers().contains_key("proxy-authenticate"));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            body,
+            format!("/echo?name=value hello host={upstream} proxy-authorization=false")
+        );
+    }
+
+    #[tokio::test]
+    async fn forwards_websocket_messages_both_ways() {
+        let upstream = serve(Router::new().route(
+            "/echo",
+            get(|websocket: WebSocketUpgrade| async {
+                websocket.on_upgrade(|mut socket| async move {
+                    while let Some(Ok(message)) = socket.recv().await {
+                        if socket.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            }),
+        ))
+        .await;
+        let proxy = serve(Router::new().route(
+            "/",
+            get(move |websocket: WebSocketUpgrade| {
+                proxy_websocket(websocket, format!("ws://{upstream}/echo"))
+            }),
+        ))
+        .await;
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{proxy}/"))
+            .await
+            .unwrap();
+        for message in [
+            Message::Text("hello".into()),
+            Message::Binary(vec![0, 1, 2]),
+        ] {
+            socket.send(message.clone()).await.unwrap();
+            assert_eq!(socket.next().await.unwrap().unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn parses_port_and_sandbox_id() {
+        assert_eq!(
+            parse_proxy_host("8080-yYKkWVB1K.sandbox.local", "sandbox.local"),
+            Some((8080, "yYKkWVB1K".to_string()))
+        );
+        assert_eq!(
+            parse_proxy_host("3000-vm-1.sandbox.local:3000", "sandbox.local"),
+            Some((3000, "vm-1".to_string())),
+            "the listener port should be ignored & ids may contain hyphens"
+        );
+        assert_eq!(
+            parse_proxy_host("8080-yYKkWVB1K.Sandbox.LOCAL", "sandbox.local"),
+            Some((8080, "yYKkWVB1K".to_string())),
+            "the domain should match regardless of its case"
+        );
+    }
+
+    #[test]
+    fn ignores_other_hosts() {
+        assert_eq!(parse_proxy_host("localhost:3000", "sandbox.local"), None);
+        assert_eq!(parse_proxy_host("sandbox.local", "sandbox.local"), None);
+        assert_eq!(
+            parse_proxy_host("8080-abc.notsandbox.local", "sandbox.local"),
+            None
+        );
+        assert_eq!(
+            parse_proxy_host("web-abc.sandbox.local", "sandbox.local"),
+            None
+        );
+        assert_eq!(
+            parse_proxy_host("8080-.sandbox.local", "sandbox.local"),
+            None
+        );
+    }
+}
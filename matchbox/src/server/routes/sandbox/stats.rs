@@ -0,0 +1,19 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    sandbox::usage::SandboxUsage,
+    server::{routes::ApiResult, ApplicationState},
+};
+
+use super::find_sandbox;
+
+pub async fn sandbox_stats(
+    Path(sandbox_id): Path<String>,
+    State(state): State<ApplicationState>,
+) -> ApiResult<Json<SandboxUsage>> {
+    let sandbox = find_sandbox(&state, &sandbox_id).await?;
+    Ok(Json(sandbox.usage()?))
+}
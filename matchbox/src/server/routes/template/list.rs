@@ -0,0 +1,29 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::server::{routes::ApiResult, ApplicationState};
+
+use super::TemplateResponse;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListTemplatesResponse {
+    templates: Vec<TemplateResponse>,
+}
+
+impl IntoResponse for ListTemplatesResponse {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
+
+pub async fn list_templates(
+    State(state): State<ApplicationState>,
+) -> ApiResult<ListTemplatesResponse> {
+    let templates = state
+        .templates()
+        .list()
+        .iter()
+        .map(|(name, template)| TemplateResponse::new(name, template))
+        .collect();
+    Ok(ListTemplatesResponse { templates })
+}
@@ -0,0 +1,43 @@
+use axum::{response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::sandbox::{
+    network::NetworkMode,
+    resources::SandboxResources,
+    template::{SandboxImage, SandboxTemplate},
+};
+
+pub mod list;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TemplateResponse {
+    pub name: String,
+    pub description: Option<String>,
+    pub image: Option<SandboxImage>,
+    pub resources: SandboxResources,
+    pub network: NetworkMode,
+    pub drives: Vec<String>,
+}
+
+impl TemplateResponse {
+    pub fn new(name: &str, template: &SandboxTemplate) -> TemplateResponse {
+        TemplateResponse {
+            name: name.to_string(),
+            description: template.description.clone(),
+            image: template.image.clone(),
+            resources: template.resources.clone(),
+            network: template.network.mode,
+            drives: template
+                .drive_ids()
+                .into_iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+impl IntoResponse for TemplateResponse {
+    fn into_response(self) -> axum::response::Response {
+        Json(self).into_response()
+    }
+}
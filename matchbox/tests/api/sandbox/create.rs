@@ -1,5 +1,6 @@
 use matchbox::{
-    sandbox::spark::SparkClient, server::routes::sandbox::create::CreateSandboxRequest,
+    sandbox::spark::{ExecuteOptions, SparkAddress, SparkClient},
+    server::routes::sandbox::create::CreateSandboxRequest,
 };
 
 use crate::common::{ping, TestServer};
@@ -8,15 +9,14 @@ use crate::common::{ping, TestServer};
 #[ignore]
 async fn test_create_sandbox_with_no_code_drive_success() {
     let server = TestServer::default().await;
-    let response = server
-        .create_vm(CreateSandboxRequest {
-            code_drive_path: None,
-        })
-        .await;
+    let response = server.create_vm(CreateSandboxRequest::default()).await;
 
-    ping(&response.ip).expect("We should be able to ping the guest");
+    let ip = response.ip.expect("The sandbox should have a network");
+    ping(&ip).expect("We should be able to ping the guest");
 
-    let mut client = SparkClient::initialize(&response.ip).await.unwrap();
+    let mut client = SparkClient::initialize(&SparkAddress::Tcp { ip })
+        .await
+        .unwrap();
 
     assert!(
         client.health_check().await.is_ok(),
@@ -27,10 +27,11 @@ async fn test_create_sandbox_with_no_code_drive_success() {
         .execute(
             "ping".into(),
             ["-c", "1", "8.8.8.8"].map(String::from).to_vec(),
+            &ExecuteOptions::default(),
         )
         .await
         .unwrap()
-        .output;
+        .stdout;
 
     assert!(
         output.contains("1 packets received"),
@@ -0,0 +1,449 @@
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use hyper::{server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode};
+use matchbox::{
+    jailer::{
+        client::FirecrackerClient,
+        factory::{ProvideFirecracker, ProvideFirecrackerOptions},
+        FirecrackerProcess, LaunchFirecracker, PathResolver,
+    },
+    sandbox::{
+        id::VmIdentifierFactory,
+        network::factory::InMemoryNetworkFactory,
+        spark::factory::{ProvideSparkClient, SparkClientFactory},
+        InitializeSandbox, ProvideSandbox, ProvideSandboxOptions, ProvideSandboxOptionsBuilder,
+        Sandbox, SandboxFactory, SandboxInitializer,
+    },
+};
+use serde_json::{json, Value};
+
+use super::spark::FakeSpark;
+use tempfile::TempDir;
+
+/// A request the fake VMM received
+#[derive(Clone, Debug)]
+pub struct VmmCall {
+    pub method: Method,
+    pub path: String,
+    pub body: Option<Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VmmState {
+    NotStarted,
+    Running,
+}
+
+/// A request the fake VMM rejects with `status`
+#[derive(Clone, Debug)]
+struct Fault {
+    method: Method,
+    path: String,
+    status: StatusCode,
+}
+
+#[derive(Debug)]
+struct VmmInner {
+    calls: Vec<VmmCall>,
+    state: VmmState,
+    has_boot_source: bool,
+    machine_config: Value,
+    /// Config file the VMM was launched with
+    config_file: Option<Value>,
+}
+
+/// Firecracker's API without a microvm behind it. It checks requests the way
+/// firecracker does, including that the files they point to exist in the
+/// jail, & records every one of them.
+#[derive(Clone, Debug)]
+pub struct FakeVmm {
+    resolver: Arc<PathResolver>,
+    faults: Arc<Vec<Fault>>,
+    inner: Arc<Mutex<VmmInner>>,
+}
+
+impl FakeVmm {
+    pub fn calls(&self) -> Vec<VmmCall> {
+        self.inner.lock().unwrap().calls.clone()
+    }
+
+    /// The method & path of every call, like `PUT /boot-source`
+    pub fn requests(&self) -> Vec<String> {
+        self.calls()
+            .iter()
+            .map(|call| format!("{} {}", call.method, call.path))
+            .collect()
+    }
+
+    pub fn state(&self) -> VmmState {
+        self.inner.lock().unwrap().state
+    }
+
+    pub fn config_file(&self) -> Option<Value> {
+        self.inner.lock().unwrap().config_file.clone()
+    }
+
+    /// Path on the host of a file in the VMM's jail
+    pub fn jailed_file(&self, path: &str) -> PathBuf {
+        self.resolver.resolve(path)
+    }
+
+    /// Boots the microvm from a config file the way firecracker does, any
+    /// invalid config stops firecracker before it serves its API
+    fn boot(&self, config_file: &Path) -> anyhow::Result<()> {
+        let config: Value =
+            serde_json::from_slice(&std::fs::read(self.resolver.resolve(config_file))?)?;
+        let mut files = vec![
+            &config["logger"]["log_path"],
+            &config["boot-source"]["kernel_image_path"],
+        ];
+        if let Some(drives) = config["drives"].as_array() {
+            files.extend(drives.iter().map(|drive| &drive["path_on_host"]));
+        }
+        for file in files.into_iter().filter_map(Value::as_str) {
+            anyhow::ensure!(
+                self.resolver.resolve(file).exists(),
+                "No such file or directory: {file}"
+            );
+        }
+        anyhow::ensure!(
+            config["boot-source"].is_object(),
+            "Cannot start microvm without kernel configuration"
+        );
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.has_boot_source = true;
+        if config["machine-config"].is_object() {
+            inner.machine_config = config["machine-config"].clone();
+        }
+        inner.state = VmmState::Running;
+        inner.config_file = Some(config);
+        Ok(())
+    }
+
+    async fn serve(self, listener: std::os::unix::net::UnixListener) {
+        let listener = tokio::net::UnixListener::from_std(listener).unwrap();
+        while let Ok((stream, _)) = listener.accept().await {
+            let vmm = self.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let vmm = vmm.clone();
+                    async move { Ok::<_, Infallible>(vmm.handle(request).await) }
+                });
+                let _ = Http::new().serve_connection(stream, service).await;
+            });
+        }
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        let body: Option<Value> = serde_json::from_slice(&body).ok();
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.calls.push(VmmCall {
+            method: method.clone(),
+            path: path.clone(),
+            body: body.clone(),
+        });
+
+        if let Some(fault) = self
+            .faults
+            .iter()
+            .find(|fault| fault.method == method && fault.path == path)
+        {
+            return fault_response(fault.status, "Injected fault");
+        }
+
+        let body = body.unwrap_or_default();
+        match (&method, path.as_str()) {
+            (&Method::GET, "/version") => {
+                json_response(StatusCode::OK, json!({ "firecracker_version": "1.7.0" }))
+            }
+            (&Method::GET, "/") => {
+                let state = match inner.state {
+                    VmmState::NotStarted => "Not started",
+                    VmmState::Running => "Running",
+                };
+                let info = json!({
+                    "app_name": "Firecracker",
+                    "id": "anonymous-instance",
+                    "state": state,
+                    "vmm_version": "1.7.0",
+                });
+                json_response(StatusCode::OK, info)
+            }
+            (&Method::GET, "/machine-config") => {
+                json_response(StatusCode::OK, inner.machine_config.clone())
+            }
+            (&Method::PUT, "/actions") => match body["action_type"].as_str() {
+                Some("InstanceStart") if inner.state == VmmState::Running => {
+                    fault_response(StatusCode::BAD_REQUEST, "The microVM is already running")
+                }
+                Some("InstanceStart") if !inner.has_boot_source => fault_response(
+                    StatusCode::BAD_REQUEST,
+                    "Cannot start microvm without kernel configuration",
+                ),
+                Some("InstanceStart") => {
+                    inner.state = VmmState::Running;
+                    no_content()
+                }
+                _ => fault_response(StatusCode::BAD_REQUEST, "Unsupported action"),
+            },
+            (&Method::PUT, path) if is_pre_boot(path) => {
+                if inner.state == VmmState::Running {
+                    return fault_response(
+                        StatusCode::BAD_REQUEST,
+                        "The requested operation is not supported after starting the microVM",
+                    );
+                }
+                for field in ["log_path", "kernel_image_path", "path_on_host"] {
+                    if let Some(file) = body[field].as_str() {
+                        if !self.resolver.resolve(file).exists() {
+                            return fault_response(
+                                StatusCode::BAD_REQUEST,
+                                &format!("No such file or directory: {file}"),
+                            );
+                        }
+                    }
+                }
+                match path {
+                    "/boot-source" => inner.has_boot_source = true,
+                    "/machine-config" => inner.machine_config = body,
+                    _ => {}
+                }
+                no_content()
+            }
+            (&Method::PATCH, path)
+                if path.starts_with("/drives/") || path.starts_with("/network-interfaces/") =>
+            {
+                if inner.state == VmmState::NotStarted {
+                    return fault_response(
+                        StatusCode::BAD_REQUEST,
+                        "The requested operation is not supported before starting the microVM",
+                    );
+                }
+                if let Some(file) = body["path_on_host"].as_str() {
+                    if !self.resolver.resolve(file).exists() {
+                        return fault_response(
+                            StatusCode::BAD_REQUEST,
+                            &format!("No such file or directory: {file}"),
+                        );
+                    }
+                }
+                no_content()
+            }
+            _ => fault_response(
+                StatusCode::BAD_REQUEST,
+                "Invalid request method and/or path",
+            ),
+        }
+    }
+}
+
+fn is_pre_boot(path: &str) -> bool {
+    ["/logger", "/boot-source", "/machine-config", "/vsock"].contains(&path)
+        || path.starts_with("/drives/")
+        || path.starts_with("/network-interfaces/")
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn fault_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, json!({ "fault_message": message }))
+}
+
+fn no_content() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[derive(Debug)]
+struct FactoryInner {
+    base_dir: TempDir,
+    faults: Arc<Vec<Fault>>,
+    vmms: Mutex<Vec<FakeVmm>>,
+}
+
+/// Serves a fake VMM on the socket of every firecracker it provides, clones
+/// share the VMMs
+#[derive(Clone, Debug)]
+pub struct FakeFirecrackerFactory(Arc<FactoryInner>);
+
+impl FakeFirecrackerFactory {
+    pub fn new() -> FakeFirecrackerFactory {
+        Self::with_faults(Vec::new())
+    }
+
+    /// Every VMM answers `method` requests for `path` with `status`
+    pub fn failing(method: Method, path: impl Into<String>, status: StatusCode) -> Self {
+        Self::with_faults(vec![Fault {
+            method,
+            path: path.into(),
+            status,
+        }])
+    }
+
+    fn with_faults(faults: Vec<Fault>) -> FakeFirecrackerFactory {
+        FakeFirecrackerFactory(Arc::new(FactoryInner {
+            base_dir: TempDir::new().unwrap(),
+            faults: Arc::new(faults),
+            vmms: Default::default(),
+        }))
+    }
+
+    /// VMMs in the order they were provided
+    pub fn vmms(&self) -> Vec<FakeVmm> {
+        self.0.vmms.lock().unwrap().clone()
+    }
+}
+
+impl ProvideFirecracker for FakeFirecrackerFactory {
+    fn provide_firecracker(
+        &self,
+        options: &ProvideFirecrackerOptions,
+    ) -> anyhow::Result<FirecrackerProcess> {
+        let root_directory = self.0.base_dir.path().join(&options.id).join("root");
+        let resolver = PathResolver::new(&root_directory, true);
+        for directory in ["/drives/", "/log/", "/run/"] {
+            std::fs::create_dir_all(resolver.resolve(directory))?;
+        }
+
+        let socket = resolver.resolve("/run/firecracker.socket");
+        let vmm = FakeVmm {
+            resolver: Arc::new(PathResolver::new(&root_directory, true)),
+            faults: self.0.faults.clone(),
+            inner: Arc::new(Mutex::new(VmmInner {
+                calls: Vec::new(),
+                state: VmmState::NotStarted,
+                has_boot_source: false,
+                machine_config: json!({ "vcpu_count": 1, "mem_size_mib": 128 }),
+                config_file: None,
+            })),
+        };
+        self.0.vmms.lock().unwrap().push(vmm.clone());
+
+        Ok(FirecrackerProcess {
+            path_resolver: resolver,
+            client: FirecrackerClient::new(socket.clone()),
+            owner: None,
+            pid_file: None,
+            launcher: Box::new(FakeLauncher { vmm, socket }),
+        })
+    }
+}
+
+/// Serves the fake VMM on firecracker's API socket once it is launched
+#[derive(Debug)]
+struct FakeLauncher {
+    vmm: FakeVmm,
+    socket: PathBuf,
+}
+
+impl LaunchFirecracker for FakeLauncher {
+    fn launch(&self, config_file: Option<&Path>) -> anyhow::Result<()> {
+        if let Some(config_file) = config_file {
+            self.vmm.boot(config_file)?;
+        }
+
+        // Binding fails when the VMM already runs
+        let listener = std::os::unix::net::UnixListener::bind(&self.socket)?;
+        listener.set_nonblocking(true)?;
+        tokio::spawn(self.vmm.clone().serve(listener));
+        Ok(())
+    }
+}
+
+/// Leaves sandboxes unconfigured so tests can drive the initializer themselves
+#[derive(Debug)]
+pub struct NoopInitializer;
+
+#[async_trait::async_trait]
+impl InitializeSandbox for NoopInitializer {
+    async fn initialize_sandbox(&self, _sandbox: &mut Sandbox) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Offline sandboxes backed by fake VMMs, with placeholder kernel & drive
+/// images. `kernel.bin` names the vsock driver, `kernel-without-vsock.bin`
+/// doesn't
+pub struct FakeSandboxes {
+    pub firecracker: FakeFirecrackerFactory,
+    spark: Arc<Box<dyn ProvideSparkClient>>,
+    images: TempDir,
+}
+
+impl FakeSandboxes {
+    pub fn new(firecracker: FakeFirecrackerFactory) -> FakeSandboxes {
+        let images = TempDir::new().unwrap();
+        for image in ["rootfs.ext4", "kernel-without-vsock.bin", "dummy.ext4"] {
+            std::fs::write(images.path().join(image), image).unwrap();
+        }
+        std::fs::write(
+            images.path().join("kernel.bin"),
+            "kernel.bin vmw_vsock_virtio_transport",
+        )
+        .unwrap();
+
+        FakeSandboxes {
+            firecracker,
+            spark: Arc::new(Box::<SparkClientFactory>::default()),
+            images,
+        }
+    }
+
+    /// Sandboxes talk to `spark` instead of their guest agent
+    pub fn with_spark(self, spark: FakeSpark) -> Self {
+        Self {
+            spark: Arc::new(Box::new(spark)),
+            ..self
+        }
+    }
+
+    pub fn image(&self, name: &str) -> PathBuf {
+        self.images.path().join(name)
+    }
+
+    pub fn initializer(&self) -> SandboxInitializer {
+        SandboxInitializer::new(self.image("rootfs.ext4"), self.image("kernel.bin"))
+    }
+
+    /// An offline sandbox whose VMM hasn't been configured yet
+    pub async fn sandbox(&self) -> Sandbox {
+        let options = ProvideSandboxOptionsBuilder::default()
+            .network(None)
+            .build()
+            .unwrap();
+        self.sandbox_with(options).await
+    }
+
+    /// A sandbox whose VMM hasn't been configured yet, its network only
+    /// exists in memory
+    pub async fn sandbox_with(&self, options: ProvideSandboxOptions) -> Sandbox {
+        let factory = SandboxFactory::new(
+            Arc::new(Box::<VmIdentifierFactory>::default()),
+            self.spark.clone(),
+            Arc::new(Box::new(self.firecracker.clone())),
+            Arc::new(Box::<InMemoryNetworkFactory>::default()),
+            Arc::new(Box::new(NoopInitializer)),
+            self.image("dummy.ext4"),
+            self.image("kernel.bin"),
+        );
+
+        factory.provide_sandbox(options).await.unwrap()
+    }
+}
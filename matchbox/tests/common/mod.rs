@@ -1,12 +1,18 @@
 #![allow(dead_code)]
+pub mod firecracker;
+pub mod spark;
+
 use std::{
     process::Command,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
 use matchbox::{
     dependency::DependencyFactory,
+    sandbox::usage::{RecordUsage, SandboxUsage},
     server::{
+        config::ServerConfig,
         routes::sandbox::{create::CreateSandboxRequest, SandboxResponse},
         Application, ApplicationState,
     },
@@ -20,11 +26,20 @@ pub struct TestServer {
 
 impl TestServer {
     pub async fn default() -> TestServer {
+        Self::with_dependencies(DependencyFactory::default()).await
+    }
+
+    pub async fn with_dependencies(dependency: DependencyFactory) -> TestServer {
+        Self::with_config(dependency, ServerConfig::default()).await
+    }
+
+    /// A server configured by `config`, its templates are loaded
+    pub async fn with_config(dependency: DependencyFactory, config: ServerConfig) -> TestServer {
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
         let port = listener.local_addr().unwrap().port();
         drop(listener);
-        let dependency = DependencyFactory::default();
-        let state = ApplicationState::new(dependency.sandbox_provider());
+        let state = ApplicationState::with_config(dependency.sandbox_provider(), config);
+        state.templates().reload().unwrap();
         let application = Application::new(format!("127.0.0.1:{port}"), state)
             .await
             .unwrap();
@@ -36,19 +51,181 @@ impl TestServer {
         TestServer { address, handle }
     }
 
-    pub async fn create_vm(&self, request: CreateSandboxRequest) -> SandboxResponse {
+    pub async fn create(&self, request: &CreateSandboxRequest) -> reqwest::Response {
         reqwest::Client::new()
             .post(format!("{}/sandbox", self.address))
             .header(reqwest::header::CONTENT_TYPE, "application/json")
-            .body(serde_json::to_string(&request).unwrap())
+            .body(serde_json::to_string(request).unwrap())
             .send()
             .await
             .expect("failed to send the create vm request")
+    }
+
+    pub async fn create_vm(&self, request: CreateSandboxRequest) -> SandboxResponse {
+        self.create(&request)
+            .await
             .text()
             .await
             .map(|body| serde_json::from_str::<SandboxResponse>(&body).unwrap())
             .expect("failed to get or deserialize response")
     }
+
+    pub async fn execute(&self, sandbox_id: &str, body: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/sandbox/{sandbox_id}/execute", self.address))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("failed to send the execute request")
+    }
+
+    /// Sends `body` as json to `PATCH /sandbox/<sandbox_id>/<path>`
+    pub async fn patch(
+        &self,
+        sandbox_id: &str,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!("{}/sandbox/{sandbox_id}/{path}", self.address))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("failed to send the patch request")
+    }
+
+    pub async fn execute_stream(&self, sandbox_id: &str, body: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/sandbox/{sandbox_id}/execute/stream",
+                self.address
+            ))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("failed to send the streaming execute request")
+    }
+
+    /// Uploads `body` to `PUT /sandbox/<sandbox_id>/files?<query>`
+    pub async fn upload(&self, sandbox_id: &str, query: &str, body: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!(
+                "{}/sandbox/{sandbox_id}/files?{query}",
+                self.address
+            ))
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("failed to send the upload request")
+    }
+
+    pub async fn download(&self, sandbox_id: &str, query: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/sandbox/{sandbox_id}/files?{query}",
+                self.address
+            ))
+            .send()
+            .await
+            .expect("failed to send the download request")
+    }
+
+    pub async fn stat_file(&self, sandbox_id: &str, query: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .head(format!(
+                "{}/sandbox/{sandbox_id}/files?{query}",
+                self.address
+            ))
+            .send()
+            .await
+            .expect("failed to send the stat file request")
+    }
+
+    pub async fn list_templates(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/template", self.address))
+            .send()
+            .await
+            .expect("failed to send the list templates request")
+    }
+
+    pub async fn stats(&self, sandbox_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/sandbox/{sandbox_id}/stats", self.address))
+            .send()
+            .await
+            .expect("failed to send the stats request")
+    }
+
+    pub async fn start_capture(
+        &self,
+        sandbox_id: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/sandbox/{sandbox_id}/pcap", self.address))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("failed to send the start capture request")
+    }
+
+    /// Gets `path` of the sandbox's captures, a capture's status or download
+    pub async fn capture(&self, sandbox_id: &str, path: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/sandbox/{sandbox_id}/pcap/{path}", self.address))
+            .send()
+            .await
+            .expect("failed to send the capture request")
+    }
+
+    /// Sends a request for `path` to `port` of the sandbox through the proxy
+    pub async fn proxy(&self, sandbox_id: &str, port: u16, path: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/sandbox/{sandbox_id}/proxy/{port}/{path}",
+                self.address
+            ))
+            .send()
+            .await
+            .expect("failed to send the proxy request")
+    }
+
+    pub async fn delete_network(&self, name: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/network/{name}", self.address))
+            .send()
+            .await
+            .expect("failed to send the delete network request")
+    }
+
+    pub async fn delete_vm(&self, sandbox_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/sandbox/{sandbox_id}", self.address))
+            .send()
+            .await
+            .expect("failed to send the delete vm request")
+    }
+}
+
+/// Keeps the usage records of deleted sandboxes
+#[derive(Debug, Clone, Default)]
+pub struct RecordedUsage(Arc<Mutex<Vec<SandboxUsage>>>);
+
+impl RecordedUsage {
+    pub fn records(&self) -> Vec<SandboxUsage> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl RecordUsage for RecordedUsage {
+    fn record_usage(&self, usage: SandboxUsage) {
+        self.0.lock().unwrap().push(usage);
+    }
 }
 
 pub fn ping(ip_address: impl AsRef<str>) -> anyhow::Result<()> {
@@ -0,0 +1,354 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use matchbox::sandbox::spark::{factory::ProvideSparkClient, SparkAddress, SparkClient};
+use sparklib::grpc::{
+    execute_stream_response::Event,
+    exit_status,
+    guest_agent_server::{GuestAgent, GuestAgentServer},
+    write_file_request::Message,
+    ExecuteRequest, ExecuteResponse, ExecuteStreamResponse, ExitStatus, FileInfo,
+    HealthCheckRequest, HealthCheckResponse, MountRequest, MountResponse, ReadFileRequest,
+    ReadFileResponse, StatFileRequest, StatFileResponse, UnmountRequest, UnmountResponse,
+    WriteFileRequest, WriteFileResponse,
+};
+use tokio::net::TcpListener;
+use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+use tonic::{
+    transport::{Endpoint, Server},
+    Request, Response, Status, Streaming,
+};
+
+/// What the fake guest agent answers with
+#[derive(Clone, Debug, Default)]
+pub struct SparkScript {
+    /// Health checks failing before the agent becomes healthy
+    pub unhealthy_checks: usize,
+    /// Output by command line, like `sh entrypoint`. Other commands have no
+    /// output.
+    pub outputs: HashMap<String, String>,
+    /// Errors by command line
+    pub errors: HashMap<String, String>,
+    /// Exit codes by command line, other commands succeed
+    pub exit_codes: HashMap<String, i32>,
+    /// Devices which fail to mount
+    pub failing_mounts: HashSet<String>,
+}
+
+impl SparkScript {
+    pub fn with_output(mut self, command_line: &str, output: &str) -> Self {
+        self.outputs
+            .insert(command_line.to_string(), output.to_string());
+        self
+    }
+
+    pub fn with_error(mut self, command_line: &str, error: &str) -> Self {
+        self.errors
+            .insert(command_line.to_string(), error.to_string());
+        self
+    }
+
+    pub fn with_exit_code(mut self, command_line: &str, code: i32) -> Self {
+        self.exit_codes.insert(command_line.to_string(), code);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+struct AgentInner {
+    health_checks: usize,
+    mounts: Vec<MountRequest>,
+    unmounts: Vec<String>,
+    executions: Vec<ExecuteRequest>,
+    /// Committed files by path
+    files: HashMap<String, FakeFile>,
+    /// Partial uploads by path
+    uploads: HashMap<String, Vec<u8>>,
+}
+
+/// A file written to the fake agent
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeFile {
+    pub content: Vec<u8>,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[derive(Clone, Debug)]
+struct ScriptedAgent {
+    script: Arc<SparkScript>,
+    inner: Arc<Mutex<AgentInner>>,
+}
+
+#[tonic::async_trait]
+impl GuestAgent for ScriptedAgent {
+    async fn health_check(
+        &self,
+        _request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.health_checks += 1;
+        match inner.health_checks > self.script.unhealthy_checks {
+            true => Ok(Response::new(HealthCheckResponse {})),
+            false => Err(Status::unavailable("spark-server is starting")),
+        }
+    }
+
+    async fn mount(
+        &self,
+        request: Request<MountRequest>,
+    ) -> Result<Response<MountResponse>, Status> {
+        let request = request.into_inner();
+        let fails = self.script.failing_mounts.contains(&request.device);
+        let device = request.device.clone();
+        self.inner.lock().unwrap().mounts.push(request);
+        match fails {
+            true => Err(Status::internal(format!("failed to mount {device}"))),
+            false => Ok(Response::new(MountResponse {})),
+        }
+    }
+
+    async fn unmount(
+        &self,
+        request: Request<UnmountRequest>,
+    ) -> Result<Response<UnmountResponse>, Status> {
+        let path = request.into_inner().path;
+        self.inner.lock().unwrap().unmounts.push(path);
+        Ok(Response::new(UnmountResponse {}))
+    }
+
+    async fn execute(
+        &self,
+        request: Request<ExecuteRequest>,
+    ) -> Result<Response<ExecuteResponse>, Status> {
+        let command_line = self.record_execution(request.into_inner());
+        let script = &self.script;
+        let code = script.exit_codes.get(&command_line).copied();
+
+        Ok(Response::new(ExecuteResponse {
+            output: script
+                .outputs
+                .get(&command_line)
+                .cloned()
+                .unwrap_or_default(),
+            stderr: script
+                .errors
+                .get(&command_line)
+                .cloned()
+                .unwrap_or_default(),
+            exit: Some(ExitStatus {
+                status: Some(exit_status::Status::Code(code.unwrap_or_default())),
+            }),
+        }))
+    }
+
+    type ExecuteStreamStream =
+        tokio_stream::Iter<std::vec::IntoIter<Result<ExecuteStreamResponse, Status>>>;
+
+    async fn execute_stream(
+        &self,
+        request: Request<ExecuteRequest>,
+    ) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        let command_line = self.record_execution(request.into_inner());
+        let mut events = Vec::new();
+        if let Some(output) = self.script.outputs.get(&command_line) {
+            events.push(Event::Stdout(output.clone().into_bytes()));
+        }
+        if let Some(error) = self.script.errors.get(&command_line) {
+            events.push(Event::Stderr(error.clone().into_bytes()));
+        }
+        let code = self.script.exit_codes.get(&command_line).copied();
+        events.push(Event::Exit(ExitStatus {
+            status: Some(exit_status::Status::Code(code.unwrap_or_default())),
+        }));
+
+        let events = events
+            .into_iter()
+            .map(|event| ExecuteStreamResponse { event: Some(event) })
+            .map(Ok)
+            .collect::<Vec<_>>();
+        Ok(Response::new(tokio_stream::iter(events)))
+    }
+
+    async fn write_file(
+        &self,
+        request: Request<Streaming<WriteFileRequest>>,
+    ) -> Result<Response<WriteFileResponse>, Status> {
+        let mut requests = request.into_inner();
+        let header = match requests.next().await.transpose()?.and_then(|r| r.message) {
+            Some(Message::Header(header)) => header,
+            _ => return Err(Status::invalid_argument("uploads start with a header")),
+        };
+
+        let mut upload = match header.offset {
+            0 => Vec::new(),
+            offset => {
+                let inner = self.inner.lock().unwrap();
+                let upload = inner.uploads.get(&header.path).cloned().unwrap_or_default();
+                if upload.len() as u64 != offset {
+                    return Err(Status::internal("upload offset mismatch"));
+                }
+                upload
+            }
+        };
+        let mut committed = false;
+        while let Some(Ok(request)) = requests.next().await {
+            match request.message {
+                Some(Message::Chunk(chunk)) => upload.extend(chunk),
+                Some(Message::Commit(commit)) => committed = commit,
+                _ => return Err(Status::invalid_argument("uploads have a single header")),
+            }
+        }
+
+        let size = upload.len() as u64;
+        let mut inner = self.inner.lock().unwrap();
+        match committed {
+            true => {
+                inner.uploads.remove(&header.path);
+                let file = FakeFile {
+                    content: upload,
+                    mode: header.mode.unwrap_or(0o644),
+                    uid: header.uid.unwrap_or_default(),
+                    gid: header.gid.unwrap_or_default(),
+                };
+                inner.files.insert(header.path, file);
+            }
+            false => {
+                inner.uploads.insert(header.path, upload);
+            }
+        }
+        Ok(Response::new(WriteFileResponse { size, committed }))
+    }
+
+    type ReadFileStream = tokio_stream::Iter<std::vec::IntoIter<Result<ReadFileResponse, Status>>>;
+
+    async fn read_file(
+        &self,
+        request: Request<ReadFileRequest>,
+    ) -> Result<Response<Self::ReadFileStream>, Status> {
+        let request = request.into_inner();
+        let inner = self.inner.lock().unwrap();
+        let file = inner
+            .files
+            .get(&request.path)
+            .ok_or_else(|| Status::not_found(request.path.clone()))?;
+
+        // Small chunks, so downloads take several of them
+        let chunks = file.content[request.offset as usize..]
+            .chunks(4)
+            .map(|chunk| ReadFileResponse {
+                chunk: chunk.to_vec(),
+            })
+            .map(Ok)
+            .collect::<Vec<_>>();
+        Ok(Response::new(tokio_stream::iter(chunks)))
+    }
+
+    async fn stat_file(
+        &self,
+        request: Request<StatFileRequest>,
+    ) -> Result<Response<StatFileResponse>, Status> {
+        let path = request.into_inner().path;
+        let inner = self.inner.lock().unwrap();
+        let file = inner.files.get(&path).map(|file| FileInfo {
+            size: file.content.len() as u64,
+            mode: file.mode,
+            uid: file.uid,
+            gid: file.gid,
+        });
+        let upload_size = inner.uploads.get(&path).map_or(0, Vec::len) as u64;
+
+        Ok(Response::new(StatFileResponse { file, upload_size }))
+    }
+}
+
+impl ScriptedAgent {
+    /// Records the command line of an execution, like `sh entrypoint`
+    fn record_execution(&self, request: ExecuteRequest) -> String {
+        let line = command_line(&request);
+        self.inner.lock().unwrap().executions.push(request);
+        line
+    }
+}
+
+fn command_line(request: &ExecuteRequest) -> String {
+    [&request.command]
+        .into_iter()
+        .chain(&request.arguments)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A real tonic guest agent on localhost following a script. Every spark
+/// client it provides talks to it, wherever the sandbox's agent would be.
+#[derive(Clone, Debug)]
+pub struct FakeSpark {
+    address: SocketAddr,
+    agent: ScriptedAgent,
+}
+
+impl FakeSpark {
+    pub async fn start(script: SparkScript) -> FakeSpark {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let agent = ScriptedAgent {
+            script: Arc::new(script),
+            inner: Default::default(),
+        };
+        let server = Server::builder()
+            .add_service(GuestAgentServer::new(agent.clone()))
+            .serve_with_incoming(TcpListenerStream::new(listener));
+        tokio::spawn(server);
+
+        FakeSpark { address, agent }
+    }
+
+    pub fn health_checks(&self) -> usize {
+        self.agent.inner.lock().unwrap().health_checks
+    }
+
+    /// Devices the agent was asked to mount
+    pub fn mounts(&self) -> Vec<String> {
+        let inner = self.agent.inner.lock().unwrap();
+        inner.mounts.iter().map(|m| m.device.clone()).collect()
+    }
+
+    /// Paths the agent was asked to unmount
+    pub fn unmounts(&self) -> Vec<String> {
+        self.agent.inner.lock().unwrap().unmounts.clone()
+    }
+
+    /// Command lines the agent executed
+    pub fn executions(&self) -> Vec<String> {
+        let inner = self.agent.inner.lock().unwrap();
+        inner.executions.iter().map(command_line).collect()
+    }
+
+    /// Every execution the agent was asked for, with its options
+    pub fn execute_requests(&self) -> Vec<ExecuteRequest> {
+        self.agent.inner.lock().unwrap().executions.clone()
+    }
+
+    /// The committed file at `path`
+    pub fn file(&self, path: &str) -> Option<FakeFile> {
+        self.agent.inner.lock().unwrap().files.get(path).cloned()
+    }
+
+    /// The partial upload of the file at `path`
+    pub fn upload(&self, path: &str) -> Option<Vec<u8>> {
+        self.agent.inner.lock().unwrap().uploads.get(path).cloned()
+    }
+}
+
+#[async_trait::async_trait]
+impl ProvideSparkClient for FakeSpark {
+    async fn provide_spark_client(&self, _address: &SparkAddress) -> anyhow::Result<SparkClient> {
+        let channel = Endpoint::new(format!("http://{}", self.address))?.connect_lazy();
+        Ok(SparkClient::from_channel(channel))
+    }
+}
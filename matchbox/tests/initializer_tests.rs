@@ -0,0 +1,178 @@
+use firecracker_config_rs::models::instance_info::InstanceState;
+use hyper::{Method, StatusCode};
+use matchbox::{
+    jailer::client::FirecrackerError,
+    sandbox::{resources::SandboxResources, ProvideSandboxOptionsBuilder},
+};
+
+use crate::common::firecracker::{FakeFirecrackerFactory, FakeSandboxes, VmmState};
+
+mod common;
+
+#[tokio::test]
+async fn test_vmm_is_configured_before_the_microvm_starts() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let mut sandbox = sandboxes.sandbox().await;
+
+    sandboxes
+        .initializer()
+        .configure_vmm(&sandbox)
+        .await
+        .expect("the fake VMM should accept the configuration");
+    sandbox.start().await.expect("the microvm should start");
+
+    let vmm = &sandboxes.firecracker.vmms()[0];
+    assert_eq!(
+        vmm.requests(),
+        [
+            "GET /version",
+            "PUT /logger",
+            "PUT /boot-source",
+            "PUT /machine-config",
+            "PUT /drives/rootfs",
+            "PUT /drives/vdb",
+            "PUT /vsock",
+            "PUT /actions",
+        ]
+    );
+    assert_eq!(vmm.state(), VmmState::Running);
+}
+
+#[tokio::test]
+async fn test_vmm_faults_stop_the_configuration() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::failing(
+        Method::PUT,
+        "/boot-source",
+        StatusCode::BAD_REQUEST,
+    ));
+    let sandbox = sandboxes.sandbox().await;
+
+    let error = sandboxes
+        .initializer()
+        .configure_vmm(&sandbox)
+        .await
+        .expect_err("the boot source should be rejected");
+
+    assert!(error
+        .to_string()
+        .contains("PUT /boot-source failed with 400"));
+    match error.downcast_ref::<FirecrackerError>() {
+        Some(FirecrackerError::Fault { message, .. }) => assert_eq!(message, "Injected fault"),
+        _ => panic!("the error should be firecracker's fault, got {error:?}"),
+    }
+    let vmm = &sandboxes.firecracker.vmms()[0];
+    assert!(
+        !vmm.requests()
+            .iter()
+            .any(|request| request.contains("/drives/")),
+        "nothing should be configured after the fault"
+    );
+    assert_eq!(vmm.state(), VmmState::NotStarted);
+}
+
+#[tokio::test]
+async fn test_running_microvms_can_not_be_reconfigured() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let mut sandbox = sandboxes.sandbox().await;
+    let initializer = sandboxes.initializer();
+
+    assert!(
+        sandbox.start().await.is_err(),
+        "firecracker isn't launched yet"
+    );
+    initializer.configure_vmm(&sandbox).await.unwrap();
+    sandbox.start().await.unwrap();
+
+    assert!(initializer.configure_vmm(&sandbox).await.is_err());
+    assert!(sandbox.start().await.is_err());
+}
+
+#[tokio::test]
+async fn test_networked_sandboxes_are_created_without_privileges() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let mut sandbox = sandboxes
+        .sandbox_with(ProvideSandboxOptionsBuilder::default().build().unwrap())
+        .await;
+
+    sandboxes
+        .initializer()
+        .configure_vmm(&sandbox)
+        .await
+        .unwrap();
+    sandbox.start().await.unwrap();
+
+    let network = sandbox
+        .network()
+        .expect("the sandbox should have a network");
+    assert!(network.microvm_ip().starts_with("10."));
+    assert_eq!(network.traffic().unwrap(), Default::default());
+    let requests = sandboxes.firecracker.vmms()[0].requests();
+    assert!(requests.contains(&"PUT /network-interfaces/eth0".to_string()));
+    assert!(!requests.contains(&"PUT /vsock".to_string()));
+}
+
+#[tokio::test]
+async fn test_vmm_responses_are_typed() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let resources = SandboxResources {
+        vcpus: 2,
+        memory_mib: 512,
+        ..Default::default()
+    };
+    let mut sandbox = sandboxes
+        .sandbox_with(
+            ProvideSandboxOptionsBuilder::default()
+                .network(None)
+                .resources(resources)
+                .build()
+                .unwrap(),
+        )
+        .await;
+
+    sandboxes
+        .initializer()
+        .configure_vmm(&sandbox)
+        .await
+        .unwrap();
+    let vmm = &sandbox.jailed_firecracker.client;
+    assert_eq!(vmm.version().await.unwrap().firecracker_version, "1.7.0");
+    assert_eq!(
+        vmm.instance_info().await.unwrap().state,
+        InstanceState::NotStarted
+    );
+    sandbox.start().await.unwrap();
+
+    let vmm = &sandbox.jailed_firecracker.client;
+    let machine_config = vmm.machine_config().await.unwrap();
+    assert_eq!(
+        (machine_config.vcpu_count, machine_config.mem_size_mib),
+        (2, 512)
+    );
+    assert_eq!(
+        vmm.instance_info().await.unwrap().state,
+        InstanceState::Running
+    );
+}
+
+#[tokio::test]
+async fn test_vmm_boots_from_its_config_file() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let mut sandbox = sandboxes.sandbox().await;
+
+    sandboxes
+        .initializer()
+        .boot_from_config_file(&mut sandbox)
+        .await
+        .expect("the microvm should boot from its config file");
+
+    let vmm = &sandboxes.firecracker.vmms()[0];
+    assert_eq!(vmm.requests(), ["GET /version", "GET /"]);
+    assert_eq!(vmm.state(), VmmState::Running);
+    let config = vmm
+        .config_file()
+        .expect("firecracker should get a config file");
+    assert_eq!(config["boot-source"]["kernel_image_path"], "/kernel.bin");
+    assert_eq!(config["drives"][0]["path_on_host"], "/drives/rootfs.ext4");
+    assert_eq!(config["vsock"]["uds_path"], "/run/vsock.socket");
+    assert!(config.get("network-interfaces").is_none());
+}
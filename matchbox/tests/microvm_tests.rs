@@ -10,7 +10,7 @@ use matchbox::{
     dependency::DependencyFactory,
     sandbox::{
         id::{ProvideIdentifier, VmIdentifier},
-        ProvideSandboxOptions,
+        ProvideSandboxOptions, ProvideSandboxOptionsBuilder,
     },
 };
 
@@ -29,7 +29,7 @@ async fn test_spawning_a_uvm() {
         .await
         .expect("failed to create sandbox");
 
-    let uvm_ip = sandbox.network().microvm_ip();
+    let uvm_ip = sandbox.network().unwrap().microvm_ip();
     println!("Connecting to IP {uvm_ip}");
     wait_until(Duration::from_secs(10), || ping(&uvm_ip)).expect("failed to ping microvm");
 }
@@ -56,7 +56,7 @@ async fn test_spawning_next_door_vms() {
         .await
         .expect("failed to create sandbox");
 
-    let uvm_ip = sandbox.network().microvm_ip();
+    let uvm_ip = sandbox.network().unwrap().microvm_ip();
     println!("Connecting to IP {uvm_ip}");
     wait_until(Duration::from_secs(10), || ping(&uvm_ip)).expect("failed to ping microvm");
 
@@ -65,10 +65,32 @@ async fn test_spawning_next_door_vms() {
         .await
         .expect("failed to create sandbox");
 
-    let other_ip = other.network().microvm_ip();
+    let other_ip = other.network().unwrap().microvm_ip();
     println!("Connecting to IP {other_ip}");
     wait_until(Duration::from_secs(10), || ping(&other_ip)).expect("failed to ping other microvm");
 
     ping(&uvm_ip).expect("The original uVM should still be reachable");
     ping(&other_ip).expect("The next door uVM should still be reachable");
 }
+
+#[tokio::test]
+#[ignore]
+async fn test_spawning_an_offline_uvm() {
+    let dependency_factory = DependencyFactory::default();
+    let factory = dependency_factory.sandbox_provider();
+
+    let options = ProvideSandboxOptionsBuilder::default()
+        .network(None)
+        .build()
+        .unwrap();
+    let sandbox = factory
+        .provide_sandbox(options)
+        .await
+        .expect("failed to create sandbox");
+
+    assert!(sandbox.network().is_none());
+    assert!(
+        sandbox.client().await.health_check().await.is_ok(),
+        "spark should be reachable over vsock"
+    );
+}
@@ -1,4 +1,16 @@
-use matchbox::sandbox::{id::VmIdentifier, network::Network};
+use std::process::Command;
+
+use firecracker_config_rs::{
+    models::network_interface::NetworkInterfaceBuilder, validation::ValidationErrors,
+};
+use matchbox::sandbox::{
+    id::VmIdentifier,
+    network::{
+        dns::DnsPolicy,
+        private::{PrivateNetworkOptions, PrivateNetworks},
+        Network, NetworkOptions, NetworkOptionsBuilder, OfflineNetns, SandboxNetwork,
+    },
+};
 use netns_rs::NetNs;
 
 use crate::common::ping;
@@ -11,7 +23,7 @@ fn test_namespaced_network_communication_works() -> anyhow::Result<()> {
     let id = VmIdentifier::default();
     println!("Creating network with id {id:?}");
     // Creates the network namespace & relevant configuration
-    let _network = Network::new(&id, &[])?;
+    let _network = Network::new(&id, &[], &NetworkOptions::default())?;
 
     let netns = NetNs::get(id.id())?;
     let output = netns.run(|_| {
@@ -31,7 +43,7 @@ fn test_namespaced_network_communication_works() -> anyhow::Result<()> {
 #[ignore]
 fn test_next_door_namespaces_can_connect_to_internet() -> anyhow::Result<()> {
     let id = VmIdentifier::new("network-1".into(), 1);
-    let _network = Network::new(&id, &[])?;
+    let _network = Network::new(&id, &[], &NetworkOptions::default())?;
 
     let netns = NetNs::get(id.id())?;
     let output = netns.run(|_| {
@@ -45,7 +57,7 @@ fn test_next_door_namespaces_can_connect_to_internet() -> anyhow::Result<()> {
     );
 
     let other = VmIdentifier::new("network-2".into(), 2);
-    let _other_network = Network::new(&other, &[])?;
+    let _other_network = Network::new(&other, &[], &NetworkOptions::default())?;
 
     let other_ns = NetNs::get(other.id())?;
     let output = other_ns.run(|_| {
@@ -60,3 +72,153 @@ fn test_next_door_namespaces_can_connect_to_internet() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn test_namespaced_network_ipv6_communication_works() -> anyhow::Result<()> {
+    let id = VmIdentifier::new("network-ipv6".into(), 3);
+    let options = NetworkOptionsBuilder::default().ipv6(true).build()?;
+    let network = Network::new(&id, &[], &options)?;
+
+    assert!(
+        network.microvm_ipv6().is_some(),
+        "A dual stack network should have an ipv6 address for the microvm"
+    );
+
+    let netns = NetNs::get(id.id())?;
+    let output = netns.run(|_| {
+        println!("Attempting to ping over ipv6 from netns {}", id.id());
+        ping("2001:4860:4860::8888")
+    })?;
+
+    assert!(
+        output.is_ok(),
+        "We should be able to ping 2001:4860:4860::8888 from inside the netns"
+    );
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_isolated_private_network_has_no_internet() -> anyhow::Result<()> {
+    let networks = PrivateNetworks::default();
+    let private_network = networks.create("isolated", PrivateNetworkOptions { internet: false })?;
+
+    let id = VmIdentifier::new("network-isolated".into(), 4);
+    let options = NetworkOptionsBuilder::default()
+        .private_network(private_network)
+        .build()?;
+    let network = Network::new(&id, &[], &options)?;
+
+    assert_eq!(
+        network
+            .private_network()
+            .map(|membership| membership.address()),
+        Some("10.201.0.2".to_string())
+    );
+
+    let netns = NetNs::get(id.id())?;
+    let output = netns.run(|_| {
+        println!("Attempting to ping from netns {}", id.id());
+        ping("8.8.8.8")
+    })?;
+
+    assert!(
+        output.is_err(),
+        "Sandboxes on an isolated private network should not reach the internet"
+    );
+    let error = networks.remove("isolated").unwrap_err();
+    assert!(
+        error.is::<ValidationErrors>(),
+        "Networks with members should not be removed"
+    );
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_offline_namespaces_cannot_reach_the_internet() -> anyhow::Result<()> {
+    let id = VmIdentifier::new("offline-1".into(), 1);
+    let offline = OfflineNetns::new(&id)?;
+    assert!(offline.path()?.exists());
+
+    let netns = NetNs::get(id.id())?;
+    let output = netns.run(|_| ping("8.8.8.8"))?;
+    assert!(
+        output.is_err(),
+        "Nothing should be reachable from an offline netns"
+    );
+
+    drop(offline);
+    assert!(NetNs::get(id.id()).is_err());
+
+    Ok(())
+}
+
+/// Runs `program` with `args`, failing when it exits unsuccessfully
+fn run(program: &str, args: &[&str]) -> anyhow::Result<()> {
+    let output = Command::new(program).args(args).output()?;
+    anyhow::ensure!(
+        output.status.success(),
+        "{program} {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn test_dns_policy_cannot_be_bypassed() -> anyhow::Result<()> {
+    let id = VmIdentifier::new("network-dns".into(), 5);
+    let interface = NetworkInterfaceBuilder::default()
+        .host_dev_name("tap0")
+        .iface_id("eth0")
+        .build()?;
+    let options = NetworkOptionsBuilder::default()
+        .dns_policy(DnsPolicy::default())
+        .build()?;
+    let _network = Network::new(&id, &[interface], &options)?;
+
+    // A guest in its own namespace stands in for the microvm, the tap device
+    // is swapped for a veth pair of the same name
+    let guest = NetNs::new("network-dns-guest")?;
+    NetNs::get(id.id())?.run(|_| {
+        run("ip", &["link", "del", "tap0"])?;
+        run(
+            "ip",
+            &[
+                "link", "add", "tap0", "type", "veth", "peer", "name", "guest0",
+            ],
+        )?;
+        run("ip", &["addr", "add", "172.16.0.1/30", "dev", "tap0"])?;
+        run("ip", &["link", "set", "tap0", "up"])?;
+        run(
+            "ip",
+            &["link", "set", "guest0", "netns", "network-dns-guest"],
+        )
+    })??;
+    let output = guest.run(|_| {
+        run("ip", &["addr", "add", "172.16.0.2/30", "dev", "guest0"])?;
+        run("ip", &["link", "set", "guest0", "up"])?;
+        run("ip", &["route", "add", "default", "via", "172.16.0.1"])?;
+
+        ping("8.8.8.8")?;
+        let dig = |server: &str| {
+            run(
+                "dig",
+                &[server, "+time=2", "+tries=1", "+notcp", "example.com"],
+            )
+        };
+        dig("@172.16.0.1")?;
+        anyhow::ensure!(
+            dig("@8.8.8.8").is_err(),
+            "Nameservers other than the forwarder should not be reachable"
+        );
+        Ok(())
+    });
+    guest.remove()?;
+
+    output?
+}
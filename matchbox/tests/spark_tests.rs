@@ -0,0 +1,633 @@
+use std::{sync::Arc, time::Duration};
+
+use matchbox::{
+    dependency::DependencyFactory,
+    sandbox::{
+        limits::LimitPolicy,
+        network::{dns::DnsPolicy, factory::InMemoryNetworkFactory, NetworkMode},
+        resources::SandboxResources,
+        InitializeSandbox,
+    },
+    server::{
+        config::{FirecrackerMode, ServerConfig, ServerConfigBuilder},
+        routes::sandbox::create::CreateSandboxRequest,
+    },
+};
+
+use crate::common::{
+    firecracker::{FakeFirecrackerFactory, FakeSandboxes},
+    spark::{FakeSpark, SparkScript},
+    RecordedUsage, TestServer,
+};
+
+mod common;
+
+/// A server whose sandboxes are backed by a fake VMM, an in memory network &
+/// a fake guest agent
+fn dependencies(sandboxes: &FakeSandboxes, spark: &FakeSpark) -> DependencyFactory {
+    let initializer: Box<dyn InitializeSandbox> = Box::new(sandboxes.initializer());
+    DependencyFactory::default()
+        .with_firecracker_provider(Arc::new(Box::new(sandboxes.firecracker.clone())))
+        .with_network_provider(Arc::new(Box::<InMemoryNetworkFactory>::default()))
+        .with_spark_client_provider(Arc::new(Box::new(spark.clone())))
+        .with_sandbox_initializer(Arc::new(initializer))
+        .with_dummy_drive_path(sandboxes.image("dummy.ext4"))
+        .with_kernel_image_path(sandboxes.image("kernel.bin"))
+}
+
+/// A server backed by fakes, its sandboxes talk to a guest agent following
+/// `script`
+async fn harness(script: SparkScript) -> (FakeSandboxes, FakeSpark, TestServer) {
+    harness_with_config(script, ServerConfig::default()).await
+}
+
+/// [`harness`] with a server configured by `config`
+async fn harness_with_config(
+    script: SparkScript,
+    config: ServerConfig,
+) -> (FakeSandboxes, FakeSpark, TestServer) {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let spark = FakeSpark::start(script).await;
+    let server = TestServer::with_config(dependencies(&sandboxes, &spark), config).await;
+    (sandboxes, spark, server)
+}
+
+#[tokio::test]
+async fn test_sandboxes_are_created_executed_and_deleted() {
+    let (_sandboxes, spark, server) =
+        harness(SparkScript::default().with_output("sh entrypoint", "hello")).await;
+
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+    assert_eq!(spark.mounts(), ["/dev/vdb"]);
+
+    let response = server.execute(&sandbox.id, "").await;
+    assert!(response.status().is_success());
+    let body: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["stdout"], "hello");
+    assert_eq!(body["exit_code"], 0);
+    assert_eq!(spark.executions(), ["sh entrypoint"]);
+
+    assert!(server.delete_vm(&sandbox.id).await.status().is_success());
+    assert_eq!(
+        server.execute(&sandbox.id, "").await.status(),
+        reqwest::StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_executions_are_streamed_until_they_exit() {
+    let (_sandboxes, _spark, server) = harness(
+        SparkScript::default()
+            .with_output("sh entrypoint", "building\r\n")
+            .with_error("sh entrypoint", "warning")
+            .with_exit_code("sh entrypoint", 3),
+    )
+    .await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+
+    let response = server.execute_stream(&sandbox.id, "").await;
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers()[reqwest::header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+    let body = response.text().await.unwrap();
+    let events = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(|data| serde_json::from_str::<serde_json::Value>(data).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [
+            serde_json::json!({ "stdout": "building\r\n" }),
+            serde_json::json!({ "stderr": "warning" }),
+            serde_json::json!({ "exit": { "code": 3 } }),
+        ]
+    );
+    assert!(body.contains("event: exit\n"));
+}
+
+#[tokio::test]
+async fn test_execute_options_are_passed_to_spark() {
+    let (_sandboxes, spark, server) = harness(SparkScript::default()).await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+
+    let options = serde_json::json!({
+        "env": { "LANG": "C.UTF-8" },
+        "cwd": "/srv",
+        "timeout_ms": 30000,
+        "uid": 1000,
+        "gid": 1000,
+        "stdin": "print(1)",
+    });
+    let response = server
+        .execute_stream(&sandbox.id, &options.to_string())
+        .await;
+    assert!(response.status().is_success());
+    response.text().await.unwrap();
+
+    let request = spark.execute_requests().pop().unwrap();
+    assert_eq!(request.env["LANG"], "C.UTF-8");
+    assert_eq!(request.cwd.as_deref(), Some("/srv"));
+    assert_eq!(request.timeout_ms, Some(30000));
+    assert_eq!((request.uid, request.gid), (Some(1000), Some(1000)));
+    assert_eq!(request.stdin, b"print(1)");
+
+    let response = server
+        .execute_stream(&sandbox.id, r#"{ "timeout_ms": "soon" }"#)
+        .await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_callers_choose_the_command_to_execute() {
+    let (_sandboxes, spark, server) = harness(
+        SparkScript::default()
+            .with_output("python3 -c exit(2)", "partial")
+            .with_error("python3 -c exit(2)", "Traceback")
+            .with_exit_code("python3 -c exit(2)", 2),
+    )
+    .await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+
+    let request = serde_json::json!({
+        "command": "python3",
+        "arguments": ["-c", "exit(2)"],
+        "env": { "PYTHONUNBUFFERED": "1" },
+    });
+    let response = server.execute(&sandbox.id, &request.to_string()).await;
+    assert!(response.status().is_success());
+    let body: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["stdout"], "partial");
+    assert_eq!(body["stderr"], "Traceback");
+    assert_eq!(body["exit_code"], 2);
+    assert_eq!(body["timed_out"], false);
+    assert!(body["duration_ms"].is_u64());
+    assert_eq!(spark.executions(), ["python3 -c exit(2)"]);
+    assert_eq!(spark.execute_requests()[0].env["PYTHONUNBUFFERED"], "1");
+
+    let request = serde_json::json!({ "arguments": ["-c", "exit(2)"] });
+    let response = server.execute(&sandbox.id, &request.to_string()).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_files_are_uploaded_resumed_and_downloaded() {
+    let (_sandboxes, spark, server) = harness(SparkScript::default()).await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+
+    let response = server
+        .upload(&sandbox.id, "path=/tmp/vdb/data.txt&partial=true", "hello ")
+        .await;
+    assert!(response.status().is_success());
+    let body: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["size"], 6);
+    assert_eq!(body["committed"], false);
+    assert_eq!(spark.file("/tmp/vdb/data.txt"), None);
+
+    let response = server
+        .stat_file(&sandbox.id, "path=/tmp/vdb/data.txt")
+        .await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["upload-offset"], "6");
+
+    let response = server
+        .upload(&sandbox.id, "path=/tmp/vdb/data.txt&offset=3", "lo ")
+        .await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let response = server
+        .upload(
+            &sandbox.id,
+            "path=/tmp/vdb/data.txt&offset=6&mode=600&uid=1000",
+            "world",
+        )
+        .await;
+    assert!(response.status().is_success());
+    let file = spark.file("/tmp/vdb/data.txt").unwrap();
+    assert_eq!(file.content, b"hello world");
+    assert_eq!(file.mode, 0o600);
+    assert_eq!(file.uid, 1000);
+    assert_eq!(spark.upload("/tmp/vdb/data.txt"), None);
+
+    let response = server
+        .download(&sandbox.id, "path=/tmp/vdb/data.txt&offset=6")
+        .await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["x-file-mode"], "600");
+    assert_eq!(response.headers()["content-length"], "5");
+    assert_eq!(response.text().await.unwrap(), "world");
+
+    let response = server.download(&sandbox.id, "path=tmp/vdb/data.txt").await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let response = server.download(&sandbox.id, "path=/tmp/vdb/missing").await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_dns_settings_of_the_request_reach_the_network() {
+    let (sandboxes, _spark, server) = harness(SparkScript::default()).await;
+
+    let request = CreateSandboxRequest {
+        nameservers: vec!["9.9.9.9".parse().unwrap()],
+        ..Default::default()
+    };
+    server.create_vm(request).await;
+    let request = CreateSandboxRequest {
+        dns_policy: Some(DnsPolicy {
+            allow: vec!["pypi.org".into()],
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    server.create_vm(request).await;
+
+    let boot_args = sandboxes
+        .firecracker
+        .vmms()
+        .iter()
+        .map(|vmm| vmm.config_file().unwrap()["boot-source"]["boot_args"].clone())
+        .collect::<Vec<_>>();
+    assert!(boot_args[0].as_str().unwrap().ends_with(" DNS::9.9.9.9"));
+    // Sandboxes with a policy resolve through the forwarder on their gateway
+    assert!(boot_args[1].as_str().unwrap().ends_with(" DNS::172.16.0.1"));
+}
+
+#[tokio::test]
+async fn test_spark_becoming_healthy_late_is_waited_for() {
+    let spark = FakeSpark::start(SparkScript {
+        unhealthy_checks: 2,
+        ..Default::default()
+    })
+    .await;
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new()).with_spark(spark.clone());
+    let mut sandbox = sandboxes.sandbox().await;
+
+    sandboxes
+        .initializer()
+        .initialize(&mut sandbox)
+        .await
+        .expect("spark should become healthy in time");
+    assert_eq!(spark.health_checks(), 3);
+}
+
+#[tokio::test]
+async fn test_spark_never_becoming_healthy_times_out() {
+    let spark = FakeSpark::start(SparkScript {
+        unhealthy_checks: usize::MAX,
+        ..Default::default()
+    })
+    .await;
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new()).with_spark(spark.clone());
+    let mut sandbox = sandboxes.sandbox().await;
+
+    let error = sandboxes
+        .initializer()
+        .with_spark_health_timeout(Duration::from_secs(1))
+        .initialize(&mut sandbox)
+        .await
+        .expect_err("spark should never become healthy");
+
+    assert!(error.to_string().contains("never became healthy"));
+    assert!(spark.mounts().is_empty());
+}
+
+#[tokio::test]
+async fn test_mount_failures_fail_the_initialization() {
+    let spark = FakeSpark::start(SparkScript {
+        failing_mounts: ["/dev/vdb".to_string()].into(),
+        ..Default::default()
+    })
+    .await;
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new()).with_spark(spark.clone());
+    let mut sandbox = sandboxes.sandbox().await;
+
+    let error = sandboxes
+        .initializer()
+        .initialize(&mut sandbox)
+        .await
+        .expect_err("the code drive should fail to mount");
+
+    assert!(format!("{error:?}").contains("failed to mount /dev/vdb"));
+}
+
+#[tokio::test]
+async fn test_invalid_sandboxes_are_rejected() {
+    let (sandboxes, _spark, server) = harness(SparkScript::default()).await;
+
+    let request = CreateSandboxRequest {
+        resources: Some(SandboxResources {
+            vcpus: 0,
+            memory_mib: 0,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let response = server.create(&request).await;
+
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["errors"][0]["path"], "resources.vcpus");
+    assert_eq!(body["errors"][1]["path"], "resources.memory_mib");
+
+    let request = CreateSandboxRequest {
+        network: Some(NetworkMode::None),
+        ipv6: true,
+        private_network: Some("backend".to_string()),
+        ..Default::default()
+    };
+    let response = server.create(&request).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["errors"][0]["path"], "ipv6");
+    assert_eq!(body["errors"][1]["path"], "private_network");
+    assert!(sandboxes.firecracker.vmms().is_empty());
+}
+
+#[tokio::test]
+async fn test_sandboxes_are_created_from_templates() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let spark = FakeSpark::start(SparkScript::default()).await;
+    let templates = tempfile::tempdir().unwrap();
+    let template = format!(
+        r#"
+        description = "Python with its packages"
+        boot_args = "console=ttyS0 quiet"
+
+        [resources]
+        vcpus = 2
+        memory_mib = 512
+
+        [[drives]]
+        drive_id = "packages"
+        source = "{}"
+        is_read_only = true
+        "#,
+        sandboxes.image("dummy.ext4").display()
+    );
+    std::fs::write(templates.path().join("python.toml"), template).unwrap();
+    let config = ServerConfigBuilder::default()
+        .templates(templates.path())
+        .build()
+        .unwrap();
+    let server = TestServer::with_config(dependencies(&sandboxes, &spark), config).await;
+
+    let body: serde_json::Value =
+        serde_json::from_str(&server.list_templates().await.text().await.unwrap()).unwrap();
+    assert_eq!(body["templates"][0]["name"], "python");
+    assert_eq!(
+        body["templates"][0]["drives"],
+        serde_json::json!(["rootfs", "vdb", "packages"])
+    );
+
+    let request = CreateSandboxRequest {
+        template: Some("python".to_string()),
+        ..Default::default()
+    };
+    server.create_vm(request).await;
+
+    let config = sandboxes.firecracker.vmms()[0].config_file().unwrap();
+    assert_eq!(config["machine-config"]["vcpu_count"], 2);
+    assert!(config["boot-source"]["boot_args"]
+        .as_str()
+        .unwrap()
+        .starts_with("console=ttyS0 quiet IP_ADDRESS::"));
+    assert_eq!(config["drives"][2]["path_on_host"], "/drives/packages.ext4");
+    assert_eq!(config["drives"][2]["is_read_only"], true);
+    assert_eq!(spark.mounts(), ["/dev/vdb", "/dev/vdc"]);
+
+    let request = CreateSandboxRequest {
+        template: Some("ruby".to_string()),
+        ..Default::default()
+    };
+    let response = server.create(&request).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_offline_sandboxes_need_a_kernel_with_vsock() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let spark = FakeSpark::start(SparkScript::default()).await;
+    let templates = tempfile::tempdir().unwrap();
+    let template = format!(
+        r#"
+        [image]
+        rootfs = "{}"
+        kernel = "{}"
+        "#,
+        sandboxes.image("rootfs.ext4").display(),
+        sandboxes.image("kernel-without-vsock.bin").display()
+    );
+    std::fs::write(templates.path().join("legacy.toml"), template).unwrap();
+    let config = ServerConfigBuilder::default()
+        .templates(templates.path())
+        .build()
+        .unwrap();
+    let server = TestServer::with_config(dependencies(&sandboxes, &spark), config).await;
+
+    let request = CreateSandboxRequest {
+        network: Some(NetworkMode::None),
+        ..Default::default()
+    };
+    server.create_vm(request).await;
+
+    let request = CreateSandboxRequest {
+        template: Some("legacy".to_string()),
+        network: Some(NetworkMode::None),
+        ..Default::default()
+    };
+    let response = server.create(&request).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["errors"][0]["path"], "network");
+
+    let request = CreateSandboxRequest {
+        template: Some("legacy".to_string()),
+        ..Default::default()
+    };
+    server.create_vm(request).await;
+}
+
+#[tokio::test]
+async fn test_drives_and_network_limits_are_updated_while_running() {
+    let (sandboxes, spark, server) = harness(SparkScript::default()).await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+    let code = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(code.path(), "new code").unwrap();
+    let location = serde_json::json!({
+        "location": { "type": "Local", "path": code.path() }
+    });
+
+    let response = server.patch(&sandbox.id, "drives/vdb", &location).await;
+    assert!(response.status().is_success());
+    let drive: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    let path_on_host = drive["path_on_host"].as_str().unwrap();
+    assert!(path_on_host.starts_with("/drives/vdb-"));
+    let vmm = &sandboxes.firecracker.vmms()[0];
+    assert_eq!(
+        std::fs::read_to_string(vmm.jailed_file(path_on_host)).unwrap(),
+        "new code"
+    );
+    assert!(!vmm.jailed_file("/drives/code-drive.ext4").exists());
+    assert_eq!(spark.unmounts(), ["/tmp/vdb"]);
+    assert_eq!(spark.mounts(), ["/dev/vdb", "/dev/vdb"]);
+
+    let response = server.patch(&sandbox.id, "drives/rootfs", &location).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let cloud_storage = serde_json::json!({
+        "location": { "type": "CloudStorage", "path": "gs://bucket/code.ext4" }
+    });
+    let response = server
+        .patch(&sandbox.id, "drives/vdb", &cloud_storage)
+        .await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let limits = serde_json::json!({ "tx": { "bandwidth": { "per_second": 1024 } } });
+    let response = server.patch(&sandbox.id, "network", &limits).await;
+    assert!(response.status().is_success());
+    let patch = vmm.calls().pop().unwrap();
+    assert_eq!(patch.path, "/network-interfaces/eth0");
+    let limiter = &patch.body.unwrap()["tx_rate_limiter"];
+    assert_eq!(limiter["bandwidth"]["size"], 1024);
+}
+
+#[tokio::test]
+async fn test_only_the_requested_limits_are_replaced() {
+    let policy =
+        serde_json::json!({ "max": { "network_tx": { "bandwidth": { "per_second": 2048 } } } });
+    let config = ServerConfigBuilder::default()
+        .limits(serde_json::from_value::<LimitPolicy>(policy).unwrap())
+        .build()
+        .unwrap();
+    let (sandboxes, _spark, server) = harness_with_config(SparkScript::default(), config).await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+    let vmm = &sandboxes.firecracker.vmms()[0];
+
+    let limits = serde_json::json!({ "network_tx": { "bandwidth": { "per_second": 4096 } } });
+    let response = server.patch(&sandbox.id, "limits", &limits).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let calls = vmm.calls().len();
+    let limits = serde_json::json!({ "drives": { "vdb": { "ops": { "per_second": 10 } } } });
+    let response = server.patch(&sandbox.id, "limits", &limits).await;
+    assert!(response.status().is_success());
+    let patches: Vec<_> = vmm.calls().split_off(calls);
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].path, "/drives/vdb");
+    let limiter = &patches[0].body.as_ref().unwrap()["rate_limiter"];
+    assert_eq!(limiter["ops"]["size"], 10);
+    assert_eq!(limiter["bandwidth"]["size"], 0);
+
+    let limits = serde_json::json!({ "rx": { "bandwidth": { "per_second": 1024 } } });
+    assert!(server
+        .patch(&sandbox.id, "network", &limits)
+        .await
+        .status()
+        .is_success());
+    let limits = serde_json::json!({ "rx": {} });
+    assert!(server
+        .patch(&sandbox.id, "network", &limits)
+        .await
+        .status()
+        .is_success());
+    let patch = vmm.calls().pop().unwrap();
+    assert_eq!(patch.path, "/network-interfaces/eth0");
+    let body = patch.body.unwrap();
+    assert_eq!(body["rx_rate_limiter"]["bandwidth"]["size"], 0);
+    assert!(body.get("tx_rate_limiter").is_none());
+}
+
+#[tokio::test]
+async fn test_untrusted_workloads_are_forbidden_without_the_jailer() {
+    let config = ServerConfigBuilder::default()
+        .firecracker(FirecrackerMode::Direct)
+        .build()
+        .unwrap();
+    let (_sandboxes, _spark, server) = harness_with_config(SparkScript::default(), config).await;
+
+    let response = server.create(&CreateSandboxRequest::default()).await;
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let request = CreateSandboxRequest {
+        trusted: true,
+        ..Default::default()
+    };
+    assert!(server.create(&request).await.status().is_success());
+}
+
+#[tokio::test]
+async fn test_deleted_sandboxes_record_their_usage() {
+    let sandboxes = FakeSandboxes::new(FakeFirecrackerFactory::new());
+    let spark = FakeSpark::start(SparkScript::default()).await;
+    let usage = RecordedUsage::default();
+    let dependencies =
+        dependencies(&sandboxes, &spark).with_usage_recorder(Arc::new(Box::new(usage.clone())));
+    let server = TestServer::with_dependencies(dependencies).await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+
+    let response = server.stats(&sandbox.id).await;
+    assert!(response.status().is_success());
+    let stats: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(stats["id"], sandbox.id);
+    assert!(usage.records().is_empty());
+
+    assert!(server.delete_vm(&sandbox.id).await.status().is_success());
+    let records = usage.records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].id, sandbox.id);
+    assert_eq!(
+        server.stats(&sandbox.id).await.status(),
+        reqwest::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        server.delete_vm(&sandbox.id).await.status(),
+        reqwest::StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_capture_errors_are_client_errors() {
+    let (_sandboxes, _spark, server) = harness(SparkScript::default()).await;
+    let sandbox = server.create_vm(CreateSandboxRequest::default()).await;
+
+    let options = serde_json::json!({ "duration_secs": 3600 });
+    let response = server.start_capture(&sandbox.id, &options).await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body["errors"][0]["path"], "duration_secs");
+
+    for path in ["unknown", "unknown/download"] {
+        assert_eq!(
+            server.capture(&sandbox.id, path).await.status(),
+            reqwest::StatusCode::NOT_FOUND
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_sandboxes_without_a_network_are_not_proxied() {
+    let (_sandboxes, _spark, server) = harness(SparkScript::default()).await;
+    let request = CreateSandboxRequest {
+        network: Some(NetworkMode::None),
+        ..Default::default()
+    };
+    let sandbox = server.create_vm(request).await;
+
+    let response = server.proxy(&sandbox.id, 8080, "index.html").await;
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(
+        server.proxy("unknown", 8080, "index.html").await.status(),
+        reqwest::StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_private_networks_are_not_found() {
+    let (_sandboxes, _spark, server) = harness(SparkScript::default()).await;
+
+    assert_eq!(
+        server.delete_network("backend").await.status(),
+        reqwest::StatusCode::NOT_FOUND
+    );
+}
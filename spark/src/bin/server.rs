@@ -1,25 +1,113 @@
-use std::process::Command;
+use std::io::SeekFrom;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::{Command, Stdio};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use sparklib::grpc::execute_stream_response::Event;
 use sparklib::grpc::guest_agent_server::{GuestAgent, GuestAgentServer};
+use sparklib::grpc::write_file_request::Message;
 use sparklib::grpc::{
-    ExecuteRequest, ExecuteResponse, HealthCheckRequest, HealthCheckResponse, MountRequest,
-    MountResponse,
+    exit_status, ExecuteRequest, ExecuteResponse, ExecuteStreamResponse, ExitStatus, FileInfo,
+    HealthCheckRequest, HealthCheckResponse, MountRequest, MountResponse, ReadFileRequest,
+    ReadFileResponse, StatFileRequest, StatFileResponse, UnmountRequest, UnmountResponse,
+    WriteFileRequest, WriteFileResponse,
 };
+use sparklib::SPARK_PORT;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::process::Child;
+use tokio::sync::mpsc::{self, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_vsock::{VsockAddr, VsockListener, VsockStream, VMADDR_CID_ANY};
+use tonic::transport::server::Connected;
 use tonic::transport::Server;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
 
 #[tokio::main]
 pub async fn main() -> anyhow::Result<()> {
-    let spark_server = SparkServer::default();
+    let tcp = Server::builder()
+        .add_service(GuestAgentServer::new(SparkServer::default()))
+        .serve(SocketAddr::from((Ipv4Addr::UNSPECIFIED, SPARK_PORT)));
 
-    Server::builder()
-        .add_service(GuestAgentServer::new(spark_server))
-        .serve("0.0.0.0:5001".parse()?)
-        .await?;
+    // Sandboxes without a network are only reachable over vsock. Images
+    // booted without a vsock device still get served over TCP.
+    let vsock = async {
+        let listener = match VsockListener::bind(VsockAddr::new(VMADDR_CID_ANY, SPARK_PORT.into()))
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                println!("not listening on vsock: {e}");
+                return std::future::pending().await;
+            }
+        };
+
+        Server::builder()
+            .add_service(GuestAgentServer::new(SparkServer::default()))
+            .serve_with_incoming(listener.incoming().map_ok(VsockConnection))
+            .await
+    };
+
+    tokio::try_join!(tcp, vsock)?;
 
     Ok(())
 }
 
+/// A vsock stream tonic can serve requests on
+struct VsockConnection(VsockStream);
+
+impl Connected for VsockConnection {
+    type ConnectInfo = ();
+
+    fn connect_info(&self) -> Self::ConnectInfo {}
+}
+
+impl AsyncRead for VsockConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for VsockConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// Directory commands are executed in, the code drive is mounted there
+const EXECUTE_DIRECTORY: &str = "/tmp/vdb";
+/// PATH of commands, which don't inherit spark-server's environment
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+/// Largest chunk of a command's output or a file streamed back at once
+const CHUNK_SIZE: usize = 8 * 1024;
+/// Messages buffered before the command's output or the file is read slower
+const STREAM_BUFFER: usize = 16;
+/// Permissions of uploaded files which don't ask for any
+const DEFAULT_FILE_MODE: u32 = 0o644;
+
+type ExecuteEvents = ReceiverStream<Result<ExecuteStreamResponse, Status>>;
+type FileChunks = ReceiverStream<Result<ReadFileResponse, Status>>;
+
 #[derive(Debug, Default)]
 pub struct SparkServer {}
 
@@ -38,31 +126,319 @@ impl SparkServer {
         Ok(())
     }
 
-    pub fn handle_execute_request(
-        &self,
-        request: &ExecuteRequest,
-    ) -> anyhow::Result<ExecuteResponse> {
-        std::env::set_current_dir("/tmp/vdb")?;
-        let mut cmd = Command::new(&request.command);
-        let output = cmd.args(&request.arguments).output()?;
+    pub fn handle_unmount_request(&self, request: &UnmountRequest) -> anyhow::Result<()> {
+        let mut cmd = Command::new("umount");
+        let output = cmd.arg(&request.path).output()?;
         if !output.status.success() {
             anyhow::bail!(
-                "execute {} {} failed: {}\n{}",
-                request.command,
-                request.arguments.join(" "),
+                "umount command failed: {}\n{}",
                 String::from_utf8_lossy(&output.stdout),
                 String::from_utf8_lossy(&output.stderr)
             );
         }
+        Ok(())
+    }
+
+    /// Runs the command to completion, how it exited is part of the response
+    pub async fn handle_execute_request(
+        &self,
+        request: &ExecuteRequest,
+    ) -> anyhow::Result<ExecuteResponse> {
+        let mut events = self.handle_execute_stream_request(request)?;
+        let (mut stdout, mut stderr, mut exit) = (Vec::new(), Vec::new(), None);
+        while let Some(event) = events.next().await {
+            match event?.event {
+                Some(Event::Stdout(chunk)) => stdout.extend(chunk),
+                Some(Event::Stderr(chunk)) => stderr.extend(chunk),
+                Some(Event::Exit(status)) => exit = Some(status),
+                None => {}
+            }
+        }
 
         Ok(ExecuteResponse {
-            output: String::from_utf8_lossy(&output.stdout).to_string(),
+            output: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            exit,
         })
     }
+
+    /// Spawns the command & streams its output as it is written, followed by
+    /// its exit status. The command is killed when the stream is dropped.
+    pub fn handle_execute_stream_request(
+        &self,
+        request: &ExecuteRequest,
+    ) -> anyhow::Result<ExecuteEvents> {
+        let mut child = spawn(request)?;
+        let pid = child.id();
+        let timeout_ms = request.timeout_ms;
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let stdout = forward_chunks(child.stdout.take().unwrap(), sender.clone(), |chunk| {
+            ExecuteStreamResponse {
+                event: Some(Event::Stdout(chunk)),
+            }
+        });
+        let stderr = forward_chunks(child.stderr.take().unwrap(), sender.clone(), |chunk| {
+            ExecuteStreamResponse {
+                event: Some(Event::Stderr(chunk)),
+            }
+        });
+        tokio::spawn(async move {
+            // The deadline covers the command itself, which may outlive its
+            // output after closing stdout & stderr
+            let run = async {
+                tokio::join!(stdout, stderr);
+                child.wait().await
+            };
+            tokio::pin!(run);
+            let exited = tokio::select! {
+                status = &mut run => Some(status),
+                _ = deadline(timeout_ms) => None,
+                _ = sender.closed() => {
+                    kill_process_group(pid);
+                    return;
+                }
+            };
+            let timed_out = exited.is_none();
+            let status = match exited {
+                Some(status) => status,
+                None => {
+                    // Output written before the kill is still sent
+                    kill_process_group(pid);
+                    run.await
+                }
+            };
+
+            let exit = match status {
+                Ok(status) => Ok(ExecuteStreamResponse {
+                    event: Some(Event::Exit(ExitStatus {
+                        status: match (timed_out, status.code()) {
+                            (true, _) => Some(exit_status::Status::TimedOut(true)),
+                            (false, Some(code)) => Some(exit_status::Status::Code(code)),
+                            (false, None) => status.signal().map(exit_status::Status::Signal),
+                        },
+                    })),
+                }),
+                Err(e) => Err(Status::internal(e.to_string())),
+            };
+            let _ = sender.send(exit).await;
+        });
+
+        Ok(ReceiverStream::new(receiver))
+    }
+
+    /// Writes an upload next to its file & moves it into place once it is
+    /// committed. Uploads which end early are kept to be resumed.
+    pub async fn handle_write_file_request(
+        &self,
+        mut requests: impl Stream<Item = Result<WriteFileRequest, Status>> + Unpin,
+    ) -> anyhow::Result<WriteFileResponse> {
+        let header = match requests.next().await.transpose()?.and_then(|r| r.message) {
+            Some(Message::Header(header)) => header,
+            _ => anyhow::bail!("uploads start with a header"),
+        };
+        let path = PathBuf::from(&header.path);
+        let upload = upload_path(&path)?;
+        tokio::fs::create_dir_all(upload.parent().unwrap()).await?;
+
+        let mut file = match header.offset {
+            0 => tokio::fs::File::create(&upload).await?,
+            offset => {
+                let file = tokio::fs::OpenOptions::new()
+                    .append(true)
+                    .open(&upload)
+                    .await?;
+                let size = file.metadata().await?.len();
+                if size != offset {
+                    anyhow::bail!(
+                        "the upload of {} has {size} bytes, not {offset}",
+                        path.display()
+                    );
+                }
+                file
+            }
+        };
+
+        let received = receive_upload(&mut file, &mut requests).await;
+        // Whatever was received is kept, even when the upload failed
+        file.flush().await?;
+        file.sync_all().await?;
+        let committed = received?;
+        let size = file.metadata().await?.len();
+
+        if committed {
+            let mode = header.mode.unwrap_or(DEFAULT_FILE_MODE);
+            tokio::fs::set_permissions(&upload, std::fs::Permissions::from_mode(mode)).await?;
+            if header.uid.is_some() || header.gid.is_some() {
+                std::os::unix::fs::chown(&upload, header.uid, header.gid)?;
+            }
+            tokio::fs::rename(&upload, &path).await?;
+        }
+
+        Ok(WriteFileResponse { size, committed })
+    }
+
+    /// Streams a file from an offset
+    pub async fn handle_read_file_request(
+        &self,
+        request: &ReadFileRequest,
+    ) -> anyhow::Result<FileChunks> {
+        let mut file = tokio::fs::File::open(&request.path).await?;
+        file.seek(SeekFrom::Start(request.offset)).await?;
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(forward_chunks(file, sender, |chunk| ReadFileResponse {
+            chunk,
+        }));
+
+        Ok(ReceiverStream::new(receiver))
+    }
+
+    pub async fn handle_stat_file_request(
+        &self,
+        request: &StatFileRequest,
+    ) -> anyhow::Result<StatFileResponse> {
+        let path = Path::new(&request.path);
+        let file = match tokio::fs::metadata(path).await {
+            Ok(metadata) => Some(FileInfo {
+                size: metadata.len(),
+                mode: metadata.mode() & 0o7777,
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let upload_size = match tokio::fs::metadata(upload_path(path)?).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(StatFileResponse { file, upload_size })
+    }
+}
+
+/// The request's command in its own process group, so every process it
+/// starts can be killed at once
+fn command(request: &ExecuteRequest) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new(&request.command);
+    cmd.args(&request.arguments)
+        .env_clear()
+        .env("PATH", DEFAULT_PATH)
+        .envs(&request.env)
+        .current_dir(request.cwd.as_deref().unwrap_or(EXECUTE_DIRECTORY))
+        .process_group(0)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    match request.stdin.is_empty() {
+        true => cmd.stdin(Stdio::null()),
+        false => cmd.stdin(Stdio::piped()),
+    };
+    if let Some(gid) = request.gid {
+        cmd.gid(gid);
+    }
+    if let Some(uid) = request.uid {
+        cmd.uid(uid);
+    }
+    cmd
+}
+
+/// Spawns the request's command & writes its stdin
+fn spawn(request: &ExecuteRequest) -> anyhow::Result<Child> {
+    let mut child = command(request).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = request.stdin.clone();
+        tokio::spawn(async move {
+            // Commands may exit without reading all of their input
+            let _ = stdin.write_all(&input).await;
+        });
+    }
+
+    Ok(child)
+}
+
+/// Resolves once the timeout passed, never without one
+async fn deadline(timeout_ms: Option<u64>) {
+    match timeout_ms {
+        Some(timeout_ms) => tokio::time::sleep(Duration::from_millis(timeout_ms)).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Kills a command & every process it started, the command leads their
+/// process group
+fn kill_process_group(pid: Option<u32>) {
+    if let Some(pid) = pid {
+        // SAFETY: kill only sends a signal. The command isn't reaped before
+        // the kill, so its pid still names the group it leads.
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+        }
+    }
+}
+
+/// Sends chunks of a command's output or a file until it is closed or nobody
+/// listens anymore
+async fn forward_chunks<T>(
+    mut output: impl AsyncRead + Unpin,
+    sender: Sender<Result<T, Status>>,
+    message: fn(Vec<u8>) -> T,
+) {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let chunk = match output.read(&mut buffer).await {
+            Ok(0) => return,
+            Ok(length) => Ok(message(buffer[..length].to_vec())),
+            Err(e) => Err(Status::internal(e.to_string())),
+        };
+        let failed = chunk.is_err();
+        if sender.send(chunk).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Partial uploads are kept next to their file, so they can be renamed into
+/// place
+fn upload_path(path: &Path) -> anyhow::Result<PathBuf> {
+    let (Some(directory), Some(name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("{} is not a file", path.display());
+    };
+    if !path.is_absolute() {
+        anyhow::bail!("{} is not an absolute path", path.display());
+    }
+
+    Ok(directory.join(format!(".{}.upload", name.to_string_lossy())))
+}
+
+/// Writes the chunks of an upload until it ends & returns whether it was
+/// committed
+async fn receive_upload(
+    file: &mut tokio::fs::File,
+    requests: &mut (impl Stream<Item = Result<WriteFileRequest, Status>> + Unpin),
+) -> anyhow::Result<bool> {
+    let mut committed = false;
+    while let Some(request) = requests.next().await {
+        if committed {
+            anyhow::bail!("nothing may follow the commit of an upload");
+        }
+        match request?.message {
+            Some(Message::Chunk(chunk)) => file.write_all(&chunk).await?,
+            Some(Message::Commit(commit)) => committed = commit,
+            _ => anyhow::bail!("uploads have a single header"),
+        }
+    }
+
+    Ok(committed)
 }
 
 #[tonic::async_trait]
 impl GuestAgent for SparkServer {
+    type ExecuteStreamStream = ExecuteEvents;
+    type ReadFileStream = FileChunks;
+
     async fn health_check(
         &self,
         _request: Request<HealthCheckRequest>,
@@ -79,12 +455,258 @@ impl GuestAgent for SparkServer {
         Ok(Response::new(MountResponse {}))
     }
 
+    async fn unmount(
+        &self,
+        request: Request<UnmountRequest>,
+    ) -> Result<Response<UnmountResponse>, Status> {
+        self.handle_unmount_request(request.get_ref())
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(UnmountResponse {}))
+    }
+
     async fn execute(
         &self,
         request: Request<ExecuteRequest>,
     ) -> Result<Response<ExecuteResponse>, Status> {
         self.handle_execute_request(request.get_ref())
+            .await
+            .map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new)
+    }
+
+    async fn execute_stream(
+        &self,
+        request: Request<ExecuteRequest>,
+    ) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        self.handle_execute_stream_request(request.get_ref())
+            .map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new)
+    }
+
+    async fn write_file(
+        &self,
+        request: Request<Streaming<WriteFileRequest>>,
+    ) -> Result<Response<WriteFileResponse>, Status> {
+        self.handle_write_file_request(request.into_inner())
+            .await
+            .map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new)
+    }
+
+    async fn read_file(
+        &self,
+        request: Request<ReadFileRequest>,
+    ) -> Result<Response<Self::ReadFileStream>, Status> {
+        self.handle_read_file_request(request.get_ref())
+            .await
             .map_err(|e| Status::internal(e.to_string()))
             .map(Response::new)
     }
+
+    async fn stat_file(
+        &self,
+        request: Request<StatFileRequest>,
+    ) -> Result<Response<StatFileResponse>, Status> {
+        self.handle_stat_file_request(request.get_ref())
+            .await
+            .map_err(|e| Status::internal(e.to_string()))
+            .map(Response::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    use futures_util::StreamExt;
+    use sparklib::grpc::write_file_request::Message;
+    use sparklib::grpc::{
+        execute_stream_response::Event, exit_status, ExecuteRequest, StatFileRequest,
+        WriteFileHeader, WriteFileRequest, WriteFileResponse,
+    };
+
+    use super::{upload_path, SparkServer};
+
+    fn header(path: &Path, offset: u64) -> WriteFileRequest {
+        WriteFileRequest {
+            message: Some(Message::Header(WriteFileHeader {
+                path: path.to_string_lossy().into(),
+                offset,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn chunk(chunk: &str) -> WriteFileRequest {
+        WriteFileRequest {
+            message: Some(Message::Chunk(chunk.as_bytes().to_vec())),
+        }
+    }
+
+    fn commit() -> WriteFileRequest {
+        WriteFileRequest {
+            message: Some(Message::Commit(true)),
+        }
+    }
+
+    async fn write(requests: Vec<WriteFileRequest>) -> anyhow::Result<WriteFileResponse> {
+        let requests = futures_util::stream::iter(requests.into_iter().map(Ok));
+        SparkServer::default()
+            .handle_write_file_request(requests)
+            .await
+    }
+
+    /// A file in a new directory & where its partial upload is kept
+    fn file(directory: &tempfile::TempDir) -> (PathBuf, PathBuf) {
+        let path = directory.path().join("code").join("main.py");
+        let upload = upload_path(&path).unwrap();
+        (path, upload)
+    }
+
+    #[tokio::test]
+    async fn uploads_are_moved_into_place_once_committed() {
+        let directory = tempfile::tempdir().unwrap();
+        let (path, upload) = file(&directory);
+
+        let response = write(vec![
+            header(&path, 0),
+            chunk("print("),
+            chunk("1)"),
+            commit(),
+        ])
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response,
+            WriteFileResponse {
+                size: 8,
+                committed: true
+            }
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "print(1)");
+        assert!(!upload.exists(), "the upload should have been renamed");
+        assert_eq!(std::fs::metadata(&path).unwrap().mode() & 0o7777, 0o644);
+    }
+
+    #[tokio::test]
+    async fn uploads_which_end_early_are_resumed() {
+        let directory = tempfile::tempdir().unwrap();
+        let (path, upload) = file(&directory);
+
+        let response = write(vec![header(&path, 0), chunk("print(")])
+            .await
+            .unwrap();
+        assert!(!response.committed);
+        assert!(!path.exists(), "an uncommitted upload isn't in place");
+        let stat = SparkServer::default()
+            .handle_stat_file_request(&StatFileRequest {
+                path: path.to_string_lossy().into(),
+            })
+            .await
+            .unwrap();
+        assert_eq!((stat.file, stat.upload_size), (None, 6));
+
+        let response = write(vec![header(&path, 6), chunk("1)"), commit()])
+            .await
+            .unwrap();
+        assert_eq!(response.size, 8);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "print(1)");
+        assert!(!upload.exists());
+    }
+
+    #[tokio::test]
+    async fn uploads_resumed_at_the_wrong_offset_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let (path, upload) = file(&directory);
+        write(vec![header(&path, 0), chunk("print(")])
+            .await
+            .unwrap();
+
+        let result = write(vec![header(&path, 4), chunk("t(1)"), commit()]).await;
+
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&upload).unwrap(), "print(");
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn uploads_get_the_requested_mode_and_owner() {
+        let directory = tempfile::tempdir().unwrap();
+        let (path, _) = file(&directory);
+        // SAFETY: getuid & getgid can't fail & have no side effects
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let mut request = header(&path, 0);
+        if let Some(Message::Header(header)) = &mut request.message {
+            header.mode = Some(0o750);
+            header.uid = Some(uid);
+            header.gid = Some(gid);
+        }
+
+        write(vec![request, chunk("#!/bin/sh"), commit()])
+            .await
+            .unwrap();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o750);
+        assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+    }
+
+    #[tokio::test]
+    async fn nothing_may_follow_the_commit() {
+        let directory = tempfile::tempdir().unwrap();
+        let (path, upload) = file(&directory);
+
+        let result = write(vec![
+            header(&path, 0),
+            chunk("print(1)"),
+            commit(),
+            chunk("!"),
+        ])
+        .await;
+
+        assert!(result.is_err());
+        assert!(!path.exists(), "a broken upload isn't moved into place");
+        assert_eq!(std::fs::read_to_string(&upload).unwrap(), "print(1)");
+    }
+
+    #[test]
+    fn uploads_are_kept_next_to_their_file() {
+        assert_eq!(
+            upload_path(Path::new("/code/main.py")).unwrap(),
+            Path::new("/code/.main.py.upload")
+        );
+        assert!(upload_path(Path::new("code/main.py")).is_err());
+        assert!(upload_path(Path::new("/")).is_err());
+    }
+
+    #[tokio::test]
+    async fn commands_closing_their_output_still_time_out() {
+        let request = ExecuteRequest {
+            command: "sh".into(),
+            arguments: vec!["-c".into(), "echo started; exec >&- 2>&-; sleep 60".into()],
+            cwd: Some("/tmp".into()),
+            timeout_ms: Some(200),
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let events = SparkServer::default()
+            .handle_execute_stream_request(&request)
+            .unwrap()
+            .map(|event| event.unwrap().event.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(events[0], Event::Stdout(b"started\n".to_vec()));
+        assert_eq!(
+            events.last().unwrap(),
+            &Event::Exit(sparklib::grpc::ExitStatus {
+                status: Some(exit_status::Status::TimedOut(true)),
+            })
+        );
+    }
 }
@@ -1,3 +1,6 @@
 pub mod grpc {
     tonic::include_proto!("spark");
 }
+
+/// Port spark-server listens on, both over TCP & vsock
+pub const SPARK_PORT: u16 = 5001;